@@ -0,0 +1,53 @@
+use num_derive::FromPrimitive;
+use num_traits::FromPrimitive;
+use solana_program::{
+    decode_error::DecodeError,
+    msg,
+    program_error::{PrintProgramError, ProgramError},
+};
+use thiserror::Error;
+
+// 该文件定义计数器合约的错误类型
+
+/// Errors that may be returned by the counter program.
+#[derive(Clone, Debug, Eq, Error, FromPrimitive, PartialEq)]
+pub enum CounterError {
+    /// 计数器加法溢出
+    #[error("Counter overflow")]
+    Overflow,
+    /// 计数器减法溢出（计数器不能小于0）
+    #[error("Counter underflow")]
+    Underflow,
+    /// 调用者不是计数器的管理员
+    #[error("Signer is not the counter authority")]
+    NotAuthority,
+    /// 计数器账户已经是最新版本
+    #[error("Counter account is already migrated")]
+    AlreadyMigrated,
+}
+
+impl From<CounterError> for ProgramError {
+    fn from(e: CounterError) -> Self {
+        ProgramError::Custom(e as u32)
+    }
+}
+
+impl<T> DecodeError<T> for CounterError {
+    fn type_of() -> &'static str {
+        "CounterError"
+    }
+}
+
+impl PrintProgramError for CounterError {
+    fn print<E>(&self)
+    where
+        E: 'static + std::error::Error + DecodeError<E> + PrintProgramError + FromPrimitive,
+    {
+        match self {
+            CounterError::Overflow => msg!("Error: Counter overflow"),
+            CounterError::Underflow => msg!("Error: Counter underflow"),
+            CounterError::NotAuthority => msg!("Error: Signer is not the counter authority"),
+            CounterError::AlreadyMigrated => msg!("Error: Counter account is already migrated"),
+        }
+    }
+}
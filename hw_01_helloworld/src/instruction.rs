@@ -0,0 +1,52 @@
+use borsh::{BorshDeserialize, BorshSerialize};
+
+// 该文件定义计数器合约有那些函数以及函数的参数（参数使用Borsh编码）
+
+/// Instructions supported by the counter program.
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
+pub enum CounterInstruction {
+    /// 计数器加上 by
+    ///
+    /// Accounts expected by this instruction:
+    ///
+    ///   0. `[writable]` The counter account.
+    ///   1. `[signer]` (Optional) The caller, recorded as `last_caller` on V2 accounts.
+    Increment { by: u32 },
+    /// 计数器减去 by
+    ///
+    /// Accounts expected by this instruction:
+    ///
+    ///   0. `[writable]` The counter account.
+    ///   1. `[signer]` (Optional) The caller, recorded as `last_caller` on V2 accounts.
+    Decrement { by: u32 },
+    /// 计数器置为0（只有管理员可以调用，V1账户没有管理员，需要先迁移）
+    ///
+    /// Accounts expected by this instruction:
+    ///
+    ///   0. `[writable]` The counter account.
+    ///   1. `[signer]` The counter authority.
+    Reset,
+    /// 将计数器设置成 value（只有管理员可以调用，V1账户没有管理员，需要先迁移）
+    ///
+    /// Accounts expected by this instruction:
+    ///
+    ///   0. `[writable]` The counter account.
+    ///   1. `[signer]` The counter authority.
+    Set { value: u32 },
+    /// 由合约创建用户的计数器账户（地址由 [b"counter", 用户地址] 生成，用户成为管理员）
+    ///
+    /// Accounts expected by this instruction:
+    ///
+    ///   0. `[writable]` The counter account, derived from `find_counter_address`.
+    ///   1. `[writable, signer]` The user, pays the rent-exempt lamports.
+    ///   2. `[]` System program.
+    InitializeCounter,
+    /// 将V1的计数器账户（4个字节的计数器）迁移成V2（扩容账户数据，新增的租金由支付者支付，支付者成为管理员）
+    ///
+    /// Accounts expected by this instruction:
+    ///
+    ///   0. `[writable]` The V1 counter account.
+    ///   1. `[writable, signer]` The payer of the rent difference, becomes the counter authority.
+    ///   2. `[]` System program.
+    Migrate,
+}
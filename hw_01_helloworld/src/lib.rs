@@ -3,19 +3,37 @@ use solana_program::{
     account_info::{next_account_info, AccountInfo},
     entrypoint,
     entrypoint::ProgramResult,
+    clock::Clock,
     msg,
+    program::{invoke, invoke_signed},
     program_error::ProgramError,
     pubkey::Pubkey,
+    rent::Rent,
+    system_instruction,
+    system_program,
+    sysvar::Sysvar,
 };
-/**
-这是一个简单的计数器合约（记录账户访问合约的次数）
+use crate::{error::CounterError, instruction::CounterInstruction};
+pub use crate::state::{GreetingAccount, GreetingAccountV2};
+
+pub mod error;
+pub mod instruction;
+pub mod state;
+
+/*
+这是一个简单的计数器合约（支持加、减、置0以及管理员直接设置计数）
 */
 
-/// 存储在账户中的计数器结构体
-#[derive(BorshSerialize, BorshDeserialize, Debug)]
-pub struct GreetingAccount {
-    /// 账户访问合约次数
-    pub counter: u32,
+/// 计数器账户PDA地址的种子前缀
+pub const COUNTER_SEED: &[u8] = b"counter";
+
+/**
+ * 获取用户的计数器账户地址（每个用户只有一个固定的计数器账户）
+ * @param user       用户地址
+ * @param program_id 合约程序ID
+ */
+pub fn find_counter_address(user: &Pubkey, program_id: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[COUNTER_SEED, user.as_ref()], program_id)
 }
 
 // 配置合约的入口函数（相当于main函数）
@@ -25,9 +43,23 @@ entrypoint!(process_instruction);
 /**
 * @param program_id        合约程序ID（合约地址）
 * @param accounts          发起者发起交易时所发送的所有账户信息（这个是在前端控制的）
-* @param _instruction_data 调用智能合约的参数
+* @param instruction_data  调用智能合约的参数（Borsh编码的CounterInstruction）
 */
-pub fn process_instruction(program_id: &Pubkey,accounts: &[AccountInfo],_instruction_data: &[u8],) -> ProgramResult {
+pub fn process_instruction(program_id: &Pubkey,accounts: &[AccountInfo],instruction_data: &[u8],) -> ProgramResult {
+    // 解析合约参数转换成实际调用函数和参数
+    let instruction = CounterInstruction::try_from_slice(instruction_data)
+        .map_err(|_| ProgramError::InvalidInstructionData)?;
+    match instruction {
+        CounterInstruction::InitializeCounter => {
+            msg!("调用智能合约InitializeCounter函数");
+            return process_initialize_counter(program_id, accounts);
+        }
+        CounterInstruction::Migrate => {
+            msg!("调用智能合约Migrate函数");
+            return process_migrate(program_id, accounts);
+        }
+        _ => {}
+    }
 
     // 获取合约账户的迭代器
     let accounts_iter = &mut accounts.iter();
@@ -41,15 +73,172 @@ pub fn process_instruction(program_id: &Pubkey,accounts: &[AccountInfo],_instruc
         return Err(ProgramError::IncorrectProgramId);
     }
 
-    // 合约程序存储在账户中的信息（就是GreetingAccount 存储在账户中的计数器结构体）
-    let mut greeting_account = GreetingAccount::try_from_slice(&account.data.borrow())?;
-    // 计数器加1
-    greeting_account.counter += 1;
-    // 再将修改后的数据存储到账户当中
-    greeting_account.serialize(&mut &mut account.data.borrow_mut()[..])?;
+    // 合约程序存储在账户中的信息（V1和V2的数据都可以解析成GreetingAccountV2）
+    let mut greeting_account = GreetingAccountV2::try_from_slice(&account.data.borrow())?;
+    // 调用者（Increment和Decrement可以不传，Reset和Set必须是管理员）
+    let caller_info = accounts_iter.next();
+    match instruction {
+        CounterInstruction::Increment { by } => {
+            msg!("调用智能合约Increment函数");
+            greeting_account.counter = greeting_account.counter.checked_add(by).ok_or(CounterError::Overflow)?;
+        }
+        CounterInstruction::Decrement { by } => {
+            msg!("调用智能合约Decrement函数");
+            greeting_account.counter = greeting_account.counter.checked_sub(by).ok_or(CounterError::Underflow)?;
+        }
+        CounterInstruction::Reset => {
+            msg!("调用智能合约Reset函数");
+            check_authority(&greeting_account, caller_info.ok_or(ProgramError::NotEnoughAccountKeys)?)?;
+            greeting_account.counter = 0;
+        }
+        CounterInstruction::Set { value } => {
+            msg!("调用智能合约Set函数");
+            check_authority(&greeting_account, caller_info.ok_or(ProgramError::NotEnoughAccountKeys)?)?;
+            greeting_account.counter = value;
+        }
+        _ => return Err(ProgramError::InvalidInstructionData),
+    }
+    // 再将修改后的数据存储到账户当中（还没有迁移的V1账户继续按V1格式存储）
+    if GreetingAccount::is_v1(&account.data.borrow()) {
+        GreetingAccount::from(&greeting_account).serialize(&mut &mut account.data.borrow_mut()[..])?;
+    } else {
+        greeting_account.last_updated_slot = Clock::get()?.slot;
+        greeting_account.last_caller = caller_info
+            .filter(|caller_info| caller_info.is_signer)
+            .map(|caller_info| *caller_info.key)
+            .unwrap_or_default();
+        greeting_account.serialize(&mut &mut account.data.borrow_mut()[..])?;
+    }
+
+    msg!("当前计数器的值为 {}!", greeting_account.counter);
+
+    Ok(())
+}
+
+// 创建用户的计数器账户（账户地址由合约通过 [b"counter", 用户地址] 生成，用户支付租金）
+fn process_initialize_counter(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+    let counter_info = next_account_info(accounts_iter)?;
+    let user_info = next_account_info(accounts_iter)?;
+    let system_program_info = next_account_info(accounts_iter)?;
+
+    if !user_info.is_signer {
+        msg!("用户没有签名，拒绝访问!");
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    if *system_program_info.key != system_program::id() {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    let (counter_key, bump) = find_counter_address(user_info.key, program_id);
+    if counter_key != *counter_info.key {
+        msg!("计数器账户地址与用户地址不匹配!");
+        return Err(ProgramError::InvalidSeeds);
+    }
+    if counter_info.owner == program_id {
+        msg!("计数器账户已经创建!");
+        return Err(ProgramError::AccountAlreadyInitialized);
+    }
+
+    // 通过系统合约创建计数器账户（存入免租金所需的最少余额，所有者是当前合约）
+    let lamports = Rent::get()?.minimum_balance(GreetingAccountV2::LEN);
+    let signer_seeds: &[&[u8]] = &[COUNTER_SEED, user_info.key.as_ref(), &[bump]];
+    if counter_info.lamports() == 0 {
+        invoke_signed(
+            &system_instruction::create_account(
+                user_info.key,
+                counter_info.key,
+                lamports,
+                GreetingAccountV2::LEN as u64,
+                program_id,
+            ),
+            &[user_info.clone(), counter_info.clone(), system_program_info.clone()],
+            &[signer_seeds],
+        )?;
+    } else {
+        // PDA地址可以被别人提前转入SOL（create_account会失败），这时补足免租金的余额后再分配空间并指定所属合约
+        let shortfall = lamports.saturating_sub(counter_info.lamports());
+        if shortfall > 0 {
+            invoke(
+                &system_instruction::transfer(user_info.key, counter_info.key, shortfall),
+                &[user_info.clone(), counter_info.clone(), system_program_info.clone()],
+            )?;
+        }
+        invoke_signed(
+            &system_instruction::allocate(counter_info.key, GreetingAccountV2::LEN as u64),
+            &[counter_info.clone(), system_program_info.clone()],
+            &[signer_seeds],
+        )?;
+        invoke_signed(
+            &system_instruction::assign(counter_info.key, program_id),
+            &[counter_info.clone(), system_program_info.clone()],
+            &[signer_seeds],
+        )?;
+    }
+
+    let greeting_account = GreetingAccountV2 {
+        counter: 0,
+        authority: *user_info.key,
+        bump,
+        last_updated_slot: Clock::get()?.slot,
+        last_caller: *user_info.key,
+    };
+    greeting_account.serialize(&mut &mut counter_info.data.borrow_mut()[..])?;
+    msg!("计数器账户 {} 创建成功!", counter_info.key);
+    Ok(())
+}
+
+// 将V1的计数器账户（最早部署的4个字节的计数器）迁移成V2（扩容账户数据，新增的租金由签名者支付，V1账户没有管理员，支付者成为管理员）
+fn process_migrate(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+    let counter_info = next_account_info(accounts_iter)?;
+    let payer_info = next_account_info(accounts_iter)?;
+    let system_program_info = next_account_info(accounts_iter)?;
+
+    if counter_info.owner != program_id {
+        msg!("该账户不是用来访问当前合约的，拒绝访问!");
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    if !payer_info.is_signer {
+        msg!("支付者没有签名，拒绝访问!");
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    if *system_program_info.key != system_program::id() {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    if !GreetingAccount::is_v1(&counter_info.data.borrow()) {
+        msg!("计数器账户已经是最新版本!");
+        return Err(CounterError::AlreadyMigrated.into());
+    }
+    let mut greeting_account = GreetingAccountV2::try_from_slice(&counter_info.data.borrow())?;
 
-    msg!("当前账户第 {} 次访问合约!", greeting_account.counter);
+    // 补足扩容后免租金所需的余额（由支付者转入）
+    let lamports = Rent::get()?.minimum_balance(GreetingAccountV2::LEN).saturating_sub(counter_info.lamports());
+    if lamports > 0 {
+        invoke(
+            &system_instruction::transfer(payer_info.key, counter_info.key, lamports),
+            &[payer_info.clone(), counter_info.clone(), system_program_info.clone()],
+        )?;
+    }
+    // 扩容账户数据并按V2格式重新存储
+    counter_info.realloc(GreetingAccountV2::LEN, false)?;
+    greeting_account.authority = *payer_info.key;
+    greeting_account.last_updated_slot = Clock::get()?.slot;
+    greeting_account.last_caller = *payer_info.key;
+    greeting_account.serialize(&mut &mut counter_info.data.borrow_mut()[..])?;
+    msg!("计数器账户 {} 已迁移到V2，管理员是 {}!", counter_info.key, payer_info.key);
+    Ok(())
+}
 
+// 校验调用者是否是计数器管理员（必须是管理员本人签名）
+fn check_authority(greeting_account: &GreetingAccountV2, authority_info: &AccountInfo) -> ProgramResult {
+    if !authority_info.is_signer {
+        msg!("管理员没有签名，拒绝访问!");
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    if greeting_account.authority != *authority_info.key {
+        msg!("调用者不是计数器管理员，拒绝访问!");
+        return Err(CounterError::NotAuthority.into());
+    }
     Ok(())
 }
 
@@ -58,7 +247,6 @@ pub fn process_instruction(program_id: &Pubkey,accounts: &[AccountInfo],_instruc
 mod test {
     use super::*;
     use solana_program::clock::Epoch;
-    use std::mem;
 
     #[test]
     fn test_sanity() {
@@ -66,7 +254,7 @@ mod test {
         let program_id = Pubkey::default();
         let key = Pubkey::default();
         let mut lamports = 0;
-        let mut data = vec![0; mem::size_of::<u32>()];
+        let mut data = vec![0; GreetingAccount::LEN];
         // 合约地址
         let owner = Pubkey::default();
         // 模拟账户
@@ -80,7 +268,8 @@ mod test {
             false,
             Epoch::default(),
         );
-        let instruction_data: Vec<u8> = Vec::new();
+        // 计数器加1的调用参数
+        let instruction_data = CounterInstruction::Increment { by: 1 }.try_to_vec().unwrap();
         // 将账户信息加入集合
         let accounts = vec![account];
         // 将合约存储在账户中的信息转换成GreetingAccount对象，并拿到计数
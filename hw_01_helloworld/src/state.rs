@@ -0,0 +1,133 @@
+use borsh::{BorshDeserialize, BorshSerialize};
+use solana_program::pubkey::Pubkey;
+use std::io::{Error, ErrorKind, Result, Write};
+
+// 该文件定义实际要存储数据的结构体
+
+/// V2及以后版本数据的第一个字节是版本号（V1数据没有版本号，只能通过数据长度区分）
+pub const GREETING_ACCOUNT_VERSION_V2: u8 = 2;
+
+/// 存储在账户中的计数器结构体（V1版本，就是最早部署的只有计数器的数据格式，没有版本号前缀）
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, Default, PartialEq)]
+pub struct GreetingAccount {
+    /// 计数器当前的值
+    pub counter: u32,
+}
+
+impl GreetingAccount {
+    /// 数据总长度（counter 4个字节）
+    pub const LEN: usize = 4;
+
+    /// 账户数据是不是V1版本（V1数据没有版本号，长度固定）
+    pub fn is_v1(data: &[u8]) -> bool {
+        data.len() == Self::LEN
+    }
+}
+
+/// 存储在账户中的计数器结构体（V2版本，增加了管理员、bump、最后修改的slot和最后调用者）
+///
+/// 使用 `try_from_slice` 解析时同时兼容V1和V2的数据（V1数据的新增字段为默认值，也就是还没有管理员）
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct GreetingAccountV2 {
+    /// 计数器当前的值
+    pub counter: u32,
+    /// 计数器管理员（只有管理员可以置0或者直接设置计数）
+    pub authority: Pubkey,
+    /// 计数器账户PDA地址的bump（由合约创建的计数器账户才有值）
+    pub bump: u8,
+    /// 最后一次修改计数器时的slot
+    pub last_updated_slot: u64,
+    /// 最后一次修改计数器的调用者（没有调用者签名时为默认地址）
+    pub last_caller: Pubkey,
+}
+
+impl GreetingAccountV2 {
+    /// 数据总长度（版本号 1个字节 + counter 4个字节 + authority 32个字节 + bump 1个字节 + last_updated_slot 8个字节 + last_caller 32个字节）
+    pub const LEN: usize = 1 + 4 + 32 + 1 + 8 + 32;
+}
+
+impl From<GreetingAccount> for GreetingAccountV2 {
+    fn from(v1: GreetingAccount) -> Self {
+        GreetingAccountV2 {
+            counter: v1.counter,
+            ..GreetingAccountV2::default()
+        }
+    }
+}
+
+impl From<&GreetingAccountV2> for GreetingAccount {
+    fn from(v2: &GreetingAccountV2) -> Self {
+        GreetingAccount { counter: v2.counter }
+    }
+}
+
+impl BorshSerialize for GreetingAccountV2 {
+    fn serialize<W: Write>(&self, writer: &mut W) -> Result<()> {
+        GREETING_ACCOUNT_VERSION_V2.serialize(writer)?;
+        self.counter.serialize(writer)?;
+        self.authority.serialize(writer)?;
+        self.bump.serialize(writer)?;
+        self.last_updated_slot.serialize(writer)?;
+        self.last_caller.serialize(writer)
+    }
+}
+
+impl BorshDeserialize for GreetingAccountV2 {
+    fn deserialize(buf: &mut &[u8]) -> Result<Self> {
+        // V1数据没有版本号，直接解析成V1再转换成V2
+        if GreetingAccount::is_v1(buf) {
+            return GreetingAccount::deserialize(buf).map(GreetingAccountV2::from);
+        }
+        let version = u8::deserialize(buf)?;
+        if version != GREETING_ACCOUNT_VERSION_V2 {
+            return Err(Error::new(ErrorKind::InvalidData, "Unknown GreetingAccount version"));
+        }
+        Ok(GreetingAccountV2 {
+            counter: u32::deserialize(buf)?,
+            authority: Pubkey::deserialize(buf)?,
+            bump: u8::deserialize(buf)?,
+            last_updated_slot: u64::deserialize(buf)?,
+            last_caller: Pubkey::deserialize(buf)?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_read_v1_as_v2() {
+        // V1数据就是Borsh编码的u32
+        let data = 9u32.try_to_vec().unwrap();
+        assert_eq!(data, GreetingAccount { counter: 9 }.try_to_vec().unwrap());
+        assert!(GreetingAccount::is_v1(&data));
+        // V1数据可以直接解析成V2（新增字段为默认值，还没有管理员）
+        assert_eq!(
+            GreetingAccountV2::try_from_slice(&data).unwrap(),
+            GreetingAccountV2 { counter: 9, ..GreetingAccountV2::default() }
+        );
+    }
+
+    #[test]
+    fn test_v2_round_trip() {
+        let account = GreetingAccountV2 {
+            counter: 9,
+            authority: Pubkey::new_unique(),
+            bump: 254,
+            last_updated_slot: 42,
+            last_caller: Pubkey::new_unique(),
+        };
+        let data = account.try_to_vec().unwrap();
+        assert_eq!(data.len(), GreetingAccountV2::LEN);
+        assert_eq!(data[0], GREETING_ACCOUNT_VERSION_V2);
+        assert_eq!(GreetingAccountV2::try_from_slice(&data).unwrap(), account);
+    }
+
+    #[test]
+    fn test_unknown_version() {
+        let mut data = GreetingAccountV2::default().try_to_vec().unwrap();
+        data[0] = 3;
+        assert!(GreetingAccountV2::try_from_slice(&data).is_err());
+    }
+}
@@ -1,29 +1,58 @@
-use borsh::BorshDeserialize;
-use helloworld::{process_instruction, GreetingAccount};
+use borsh::{BorshDeserialize, BorshSerialize};
+use hw_01_helloworld::{error::CounterError, find_counter_address, instruction::CounterInstruction, process_instruction, GreetingAccount, GreetingAccountV2};
 use solana_program_test::*;
 use solana_sdk::{
     account::Account,
-    instruction::{AccountMeta, Instruction},
+    hash::Hash,
+    instruction::{AccountMeta, Instruction, InstructionError},
     pubkey::Pubkey,
-    signature::Signer,
-    transaction::Transaction,
+    signature::{Keypair, Signer},
+    system_program,
+    transaction::{Transaction, TransactionError},
 };
-use std::mem;
 
-#[tokio::test]
-async fn test_helloworld() {
+mod native;
+
+// 原生模式运行计数器合约（支持合约 realloc 以及在CPI中创建账户，见 native 模块）
+fn process_native(_first_instruction_account: usize, input: &[u8], invoke_context: &mut InvokeContext) -> Result<(), InstructionError> {
+    native::process_instruction(process_instruction, input, invoke_context)
+}
+
+/**
+ * 模拟部署合约（加上 test-bpf 特性时加载编译好的BPF程序）
+ * @param program_id 合约程序ID
+ */
+fn new_program_test(program_id: Pubkey) -> ProgramTest {
+    let mut program_test = ProgramTest::default();
+    if cfg!(feature = "test-bpf") {
+        program_test.add_program("hw_01_helloworld", program_id, None);
+    } else {
+        program_test.add_builtin_program("hw_01_helloworld", program_id, process_native);
+    }
+    program_test
+}
+
+/**
+ * 模拟部署合约并给用户地址添加合约账户（账户里面已经存储了初始计数和管理员，V2格式）
+ * @param counter   计数器初始值
+ * @param authority 计数器管理员
+ */
+async fn start_with_counter(counter: u32, authority: Pubkey) -> (Pubkey, Pubkey, BanksClient, Keypair, Hash) {
+    start_with_account_data(GreetingAccountV2 { counter, authority, ..GreetingAccountV2::default() }.try_to_vec().unwrap()).await
+}
+
+/**
+ * 模拟部署合约并给用户地址添加合约账户
+ * @param data 账户里面存储的数据
+ */
+async fn start_with_account_data(data: Vec<u8>) -> (Pubkey, Pubkey, BanksClient, Keypair, Hash) {
     let program_id = Pubkey::new_unique();
     let greeted_pubkey = Pubkey::new_unique();
     println!("program_id={}",program_id);
     println!("greeted_pubkey={}",greeted_pubkey);
 
-    /**
-     * 模拟部署合约
-     * @param program_name        合约名称
-     * @param program_id          合约程序ID
-     * @param process_instruction 合约入口函数
-     */
-    let mut program_test = ProgramTest::new("helloworld",program_id,processor!(process_instruction),);
+    // 模拟部署合约
+    let mut program_test = new_program_test(program_id);
 
     /**
      * 给用户地址添加合约账户
@@ -32,55 +61,360 @@ async fn test_helloworld() {
      */
     program_test.add_account(greeted_pubkey,Account {
             lamports: 5,
-            data: vec![0_u8; mem::size_of::<u32>()],
+            data,
             owner: program_id,
             ..Account::default()
         },
     );
     // 启动合约
-    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+    let (banks_client, payer, recent_blockhash) = program_test.start().await;
+    (program_id, greeted_pubkey, banks_client, payer, recent_blockhash)
+}
+
+/**
+ * 调用计数器合约
+ * @param signers 交易签名者（第一个是手续费支付者）
+ */
+async fn send(banks_client: &mut BanksClient, signers: &[&Keypair], recent_blockhash: Hash, instruction: Instruction) -> Result<(), TransactionError> {
+    let mut transaction = Transaction::new_with_payer(&[instruction], Some(&signers[0].pubkey()));
+    // 交易签名
+    transaction.sign(&signers.to_vec(), recent_blockhash);
+    // 使用合约处理交易（就是访问智能合约）
+    banks_client.process_transaction(transaction).await.map_err(|e| e.unwrap())
+}
 
-    // 获取用户地址的合约账户
+// 获取用户地址的合约账户里面的数据（V1和V2的数据都可以解析成GreetingAccountV2）
+async fn get_greeting_account(banks_client: &mut BanksClient, greeted_pubkey: Pubkey) -> GreetingAccountV2 {
     let greeted_account = banks_client.get_account(greeted_pubkey).await.expect("get_account").expect("greeted_account not found");
+    GreetingAccountV2::try_from_slice(&greeted_account.data).unwrap()
+}
+
+// 获取用户地址的合约账户里面的计数
+async fn get_counter(banks_client: &mut BanksClient, greeted_pubkey: Pubkey) -> u32 {
+    get_greeting_account(banks_client, greeted_pubkey).await.counter
+}
+
+#[tokio::test]
+async fn test_increment_and_decrement() {
+    let authority = Keypair::new();
+    let (program_id, greeted_pubkey, mut banks_client, payer, recent_blockhash) = start_with_counter(0, authority.pubkey()).await;
     // 验证该账户是否没有访问过计数器合约
-    assert_eq!(GreetingAccount::try_from_slice(&greeted_account.data).unwrap().counter,0);
+    assert_eq!(get_counter(&mut banks_client, greeted_pubkey).await,0);
 
-    /*--------------------------------------第一次模拟交易-------------------------------------------------*/
-    // 模拟创建交易
-    let mut transaction = Transaction::new_with_payer(&[Instruction::new_with_bincode(
-            program_id, //要访问的智能合约ID（合约地址）
-            &[0], // ignored but makes the instruction unique in the slot
-            vec![AccountMeta::new(greeted_pubkey, false)],// 访问者地址（is_signer 表示访问者是否持有私钥，is_writable 表示程序是否可以修改账户信息）
-        )],
-        Some(&payer.pubkey()),
+    // 计数器加5（不需要管理员签名）
+    let instruction = Instruction::new_with_borsh(
+        program_id, //要访问的智能合约ID（合约地址）
+        &CounterInstruction::Increment { by: 5 },
+        vec![AccountMeta::new(greeted_pubkey, false)],// 访问者地址（is_signer 表示访问者是否持有私钥，is_writable 表示程序是否可以修改账户信息）
     );
-    // 交易签名
-    transaction.sign(&[&payer], recent_blockhash);
-    // 使用合约处理交易（就是访问智能合约）
-    let res = banks_client.process_transaction(transaction).await.unwrap();
-    println!("TransactionRes={:#?}",res);
+    send(&mut banks_client, &[&payer], recent_blockhash, instruction).await.unwrap();
+    assert_eq!(get_counter(&mut banks_client, greeted_pubkey).await,5);
+
+    // 计数器减2
+    let instruction = Instruction::new_with_borsh(
+        program_id,
+        &CounterInstruction::Decrement { by: 2 },
+        vec![AccountMeta::new(greeted_pubkey, false)],
+    );
+    send(&mut banks_client, &[&payer], recent_blockhash, instruction).await.unwrap();
+    assert_eq!(get_counter(&mut banks_client, greeted_pubkey).await,3);
+
+    // 计数器减4（计数器不能小于0）
+    let instruction = Instruction::new_with_borsh(
+        program_id,
+        &CounterInstruction::Decrement { by: 4 },
+        vec![AccountMeta::new(greeted_pubkey, false)],
+    );
+    assert_eq!(
+        send(&mut banks_client, &[&payer], recent_blockhash, instruction).await.unwrap_err(),
+        TransactionError::InstructionError(0, InstructionError::Custom(CounterError::Underflow as u32))
+    );
+    assert_eq!(get_counter(&mut banks_client, greeted_pubkey).await,3);
+}
+
+#[tokio::test]
+async fn test_increment_overflow() {
+    let authority = Keypair::new();
+    let (program_id, greeted_pubkey, mut banks_client, payer, recent_blockhash) = start_with_counter(u32::MAX - 1, authority.pubkey()).await;
+
+    // 计数器加2会超过u32的最大值
+    let instruction = Instruction::new_with_borsh(
+        program_id,
+        &CounterInstruction::Increment { by: 2 },
+        vec![AccountMeta::new(greeted_pubkey, false)],
+    );
+    assert_eq!(
+        send(&mut banks_client, &[&payer], recent_blockhash, instruction).await.unwrap_err(),
+        TransactionError::InstructionError(0, InstructionError::Custom(CounterError::Overflow as u32))
+    );
+    assert_eq!(get_counter(&mut banks_client, greeted_pubkey).await,u32::MAX - 1);
+
+    // 计数器加1刚好等于u32的最大值
+    let instruction = Instruction::new_with_borsh(
+        program_id,
+        &CounterInstruction::Increment { by: 1 },
+        vec![AccountMeta::new(greeted_pubkey, false)],
+    );
+    send(&mut banks_client, &[&payer], recent_blockhash, instruction).await.unwrap();
+    assert_eq!(get_counter(&mut banks_client, greeted_pubkey).await,u32::MAX);
+}
+
+#[tokio::test]
+async fn test_reset_and_set_by_authority() {
+    let authority = Keypair::new();
+    let (program_id, greeted_pubkey, mut banks_client, payer, recent_blockhash) = start_with_counter(7, authority.pubkey()).await;
+
+    // 管理员将计数器设置成100
+    let instruction = Instruction::new_with_borsh(
+        program_id,
+        &CounterInstruction::Set { value: 100 },
+        vec![AccountMeta::new(greeted_pubkey, false), AccountMeta::new_readonly(authority.pubkey(), true)],
+    );
+    send(&mut banks_client, &[&payer, &authority], recent_blockhash, instruction).await.unwrap();
+    assert_eq!(get_counter(&mut banks_client, greeted_pubkey).await,100);
+
+    // 管理员将计数器置0
+    let instruction = Instruction::new_with_borsh(
+        program_id,
+        &CounterInstruction::Reset,
+        vec![AccountMeta::new(greeted_pubkey, false), AccountMeta::new_readonly(authority.pubkey(), true)],
+    );
+    send(&mut banks_client, &[&payer, &authority], recent_blockhash, instruction).await.unwrap();
+    assert_eq!(get_counter(&mut banks_client, greeted_pubkey).await,0);
+}
+
+#[tokio::test]
+async fn test_reset_and_set_reject_non_authority() {
+    let authority = Keypair::new();
+    let (program_id, greeted_pubkey, mut banks_client, payer, recent_blockhash) = start_with_counter(7, authority.pubkey()).await;
+
+    // 不是管理员签名不能设置计数器
+    let instruction = Instruction::new_with_borsh(
+        program_id,
+        &CounterInstruction::Set { value: 100 },
+        vec![AccountMeta::new(greeted_pubkey, false), AccountMeta::new_readonly(payer.pubkey(), true)],
+    );
+    assert_eq!(
+        send(&mut banks_client, &[&payer], recent_blockhash, instruction).await.unwrap_err(),
+        TransactionError::InstructionError(0, InstructionError::Custom(CounterError::NotAuthority as u32))
+    );
+
+    // 管理员没有签名不能置0
+    let instruction = Instruction::new_with_borsh(
+        program_id,
+        &CounterInstruction::Reset,
+        vec![AccountMeta::new(greeted_pubkey, false), AccountMeta::new_readonly(authority.pubkey(), false)],
+    );
+    assert_eq!(
+        send(&mut banks_client, &[&payer], recent_blockhash, instruction).await.unwrap_err(),
+        TransactionError::InstructionError(0, InstructionError::MissingRequiredSignature)
+    );
+    assert_eq!(get_counter(&mut banks_client, greeted_pubkey).await,7);
+}
+
+#[tokio::test]
+async fn test_v1_counter_keeps_layout() {
+    // 最早部署的计数器账户只有4个字节的计数
+    let data = GreetingAccount { counter: 7 }.try_to_vec().unwrap();
+    let (program_id, greeted_pubkey, mut banks_client, payer, recent_blockhash) = start_with_account_data(data).await;
 
-    // 获取用户地址的合约账户
+    // V1账户可以继续加减计数，并且还是按V1格式存储
+    let instruction = Instruction::new_with_borsh(
+        program_id,
+        &CounterInstruction::Increment { by: 1 },
+        vec![AccountMeta::new(greeted_pubkey, false)],
+    );
+    send(&mut banks_client, &[&payer], recent_blockhash, instruction).await.unwrap();
     let greeted_account = banks_client.get_account(greeted_pubkey).await.expect("get_account").expect("greeted_account not found");
-    // 验证该账户是否已经访问过1次计数器合约（因为上面模拟交易访问过一次）
-    assert_eq!(GreetingAccount::try_from_slice(&greeted_account.data).unwrap().counter,1);
-
-    /*--------------------------------------第二次模拟交易-------------------------------------------------*/
-    // 再次模拟创建交易
-    let mut transaction = Transaction::new_with_payer(&[Instruction::new_with_bincode(
-            program_id, //要访问的智能合约ID（合约地址）
-            &[1], // ignored but makes the instruction unique in the slot
-            vec![AccountMeta::new(greeted_pubkey, false)], // 访问者地址（就是先通过这个地址从合约中拿到账户，再用账户去调用合约）。注意：账户信息在上面已经添加到合约当中
-        )],
-        Some(&payer.pubkey()),
+    assert_eq!(greeted_account.data, GreetingAccount { counter: 8 }.try_to_vec().unwrap());
+
+    // V1账户还没有管理员，不能置0或者直接设置计数（需要先迁移）
+    let instruction = Instruction::new_with_borsh(
+        program_id,
+        &CounterInstruction::Set { value: 100 },
+        vec![AccountMeta::new(greeted_pubkey, false), AccountMeta::new_readonly(payer.pubkey(), true)],
     );
-    // 交易签名
-    transaction.sign(&[&payer], recent_blockhash);
-    // 使用合约处理交易（就是访问智能合约）
-    banks_client.process_transaction(transaction).await.unwrap();
+    assert_eq!(
+        send(&mut banks_client, &[&payer], recent_blockhash, instruction).await.unwrap_err(),
+        TransactionError::InstructionError(0, InstructionError::Custom(CounterError::NotAuthority as u32))
+    );
+    assert_eq!(get_counter(&mut banks_client, greeted_pubkey).await,8);
+}
+
+#[tokio::test]
+async fn test_initialize_counter_rejects_wrong_address() {
+    let program_id = Pubkey::new_unique();
+    let program_test = new_program_test(program_id);
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+
+    // 计数器账户地址必须是 [b"counter", 用户地址] 生成的PDA地址
+    let other = Keypair::new();
+    let (counter_pubkey, _) = find_counter_address(&other.pubkey(), &program_id);
+    let instruction = Instruction::new_with_borsh(
+        program_id,
+        &CounterInstruction::InitializeCounter,
+        vec![
+            AccountMeta::new(counter_pubkey, false),
+            AccountMeta::new(payer.pubkey(), true),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+    );
+    assert_eq!(
+        send(&mut banks_client, &[&payer], recent_blockhash, instruction).await.unwrap_err(),
+        TransactionError::InstructionError(0, InstructionError::InvalidSeeds)
+    );
+}
+
+#[tokio::test]
+async fn test_initialize_counter() {
+    let program_id = Pubkey::new_unique();
+    let program_test = new_program_test(program_id);
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
 
-    // 获取用户地址的合约账户
+    // 计数器账户地址由用户地址生成，不需要预先创建账户
+    let (counter_pubkey, bump) = find_counter_address(&payer.pubkey(), &program_id);
+    let initialize = Instruction::new_with_borsh(
+        program_id,
+        &CounterInstruction::InitializeCounter,
+        vec![
+            AccountMeta::new(counter_pubkey, false),
+            AccountMeta::new(payer.pubkey(), true),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+    );
+    send(&mut banks_client, &[&payer], recent_blockhash, initialize.clone()).await.unwrap();
+
+    let counter_account = banks_client.get_account(counter_pubkey).await.expect("get_account").expect("counter_account not found");
+    assert_eq!(counter_account.owner, program_id);
+    // 新创建的计数器账户直接使用V2格式
+    assert_eq!(counter_account.data.len(), GreetingAccountV2::LEN);
+    let rent = banks_client.get_rent().await.unwrap();
+    assert!(rent.is_exempt(counter_account.lamports, GreetingAccountV2::LEN));
+    let greeting_account = GreetingAccountV2::try_from_slice(&counter_account.data).unwrap();
+    assert_eq!(greeting_account.counter, 0);
+    assert_eq!(greeting_account.authority, payer.pubkey());
+    assert_eq!(greeting_account.bump, bump);
+
+    // 创建好的计数器账户可以直接使用
+    let instruction = Instruction::new_with_borsh(
+        program_id,
+        &CounterInstruction::Increment { by: 3 },
+        vec![AccountMeta::new(counter_pubkey, false)],
+    );
+    send(&mut banks_client, &[&payer], recent_blockhash, instruction).await.unwrap();
+    assert_eq!(get_counter(&mut banks_client, counter_pubkey).await,3);
+
+    // 每个用户只能创建一个计数器账户
+    let recent_blockhash = banks_client.get_new_latest_blockhash(&recent_blockhash).await.unwrap();
+    assert_eq!(
+        send(&mut banks_client, &[&payer], recent_blockhash, initialize).await.unwrap_err(),
+        TransactionError::InstructionError(0, InstructionError::AccountAlreadyInitialized)
+    );
+}
+
+#[tokio::test]
+async fn test_initialize_prefunded_counter() {
+    let program_id = Pubkey::new_unique();
+    let user = Keypair::new();
+    let (counter_pubkey, bump) = find_counter_address(&user.pubkey(), &program_id);
+    let mut program_test = new_program_test(program_id);
+    program_test.add_account(user.pubkey(), Account { lamports: 1_000_000_000, ..Account::default() });
+    // 别人提前往计数器账户地址转入了1个lamport（create_account 会失败）
+    program_test.add_account(counter_pubkey, Account { lamports: 1, ..Account::default() });
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+
+    // 补足免租金的余额后仍然可以创建计数器账户
+    let instruction = Instruction::new_with_borsh(
+        program_id,
+        &CounterInstruction::InitializeCounter,
+        vec![
+            AccountMeta::new(counter_pubkey, false),
+            AccountMeta::new(user.pubkey(), true),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+    );
+    send(&mut banks_client, &[&payer, &user], recent_blockhash, instruction).await.unwrap();
+
+    let counter_account = banks_client.get_account(counter_pubkey).await.expect("get_account").expect("counter_account not found");
+    assert_eq!(counter_account.owner, program_id);
+    assert_eq!(counter_account.data.len(), GreetingAccountV2::LEN);
+    let rent = banks_client.get_rent().await.unwrap();
+    assert_eq!(counter_account.lamports, rent.minimum_balance(GreetingAccountV2::LEN));
+    let greeting_account = GreetingAccountV2::try_from_slice(&counter_account.data).unwrap();
+    assert_eq!(greeting_account.authority, user.pubkey());
+    assert_eq!(greeting_account.bump, bump);
+}
+
+#[tokio::test]
+async fn test_v2_records_last_caller() {
+    let authority = Keypair::new();
+    let data = GreetingAccountV2 { counter: 1, authority: authority.pubkey(), ..GreetingAccountV2::default() }.try_to_vec().unwrap();
+    let (program_id, greeted_pubkey, mut banks_client, payer, recent_blockhash) = start_with_account_data(data).await;
+
+    // V2账户会记录最后一次修改的调用者和slot
+    let instruction = Instruction::new_with_borsh(
+        program_id,
+        &CounterInstruction::Increment { by: 1 },
+        vec![AccountMeta::new(greeted_pubkey, false), AccountMeta::new_readonly(payer.pubkey(), true)],
+    );
+    send(&mut banks_client, &[&payer], recent_blockhash, instruction).await.unwrap();
+    let greeting_account = get_greeting_account(&mut banks_client, greeted_pubkey).await;
+    assert_eq!(greeting_account.counter, 2);
+    assert_eq!(greeting_account.last_caller, payer.pubkey());
+    assert_ne!(greeting_account.last_updated_slot, 0);
+
+    // V2账户不需要再迁移
+    let instruction = Instruction::new_with_borsh(
+        program_id,
+        &CounterInstruction::Migrate,
+        vec![
+            AccountMeta::new(greeted_pubkey, false),
+            AccountMeta::new(payer.pubkey(), true),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+    );
+    assert_eq!(
+        send(&mut banks_client, &[&payer], recent_blockhash, instruction).await.unwrap_err(),
+        TransactionError::InstructionError(0, InstructionError::Custom(CounterError::AlreadyMigrated as u32))
+    );
+}
+
+#[tokio::test]
+async fn test_migrate_v1_to_v2() {
+    // 最早部署的计数器账户只有4个字节的计数（没有版本号和管理员）
+    let data = GreetingAccount { counter: 7 }.try_to_vec().unwrap();
+    assert_eq!(data.len(), 4);
+    let (program_id, greeted_pubkey, mut banks_client, payer, recent_blockhash) = start_with_account_data(data).await;
+    let migrate = Instruction::new_with_borsh(
+        program_id,
+        &CounterInstruction::Migrate,
+        vec![
+            AccountMeta::new(greeted_pubkey, false),
+            AccountMeta::new(payer.pubkey(), true),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+    );
+
+    send(&mut banks_client, &[&payer], recent_blockhash, migrate).await.unwrap();
+
+    // 账户扩容到V2的长度，并且补足了免租金所需的余额
     let greeted_account = banks_client.get_account(greeted_pubkey).await.expect("get_account").expect("greeted_account not found");
-    // 验证该账户是否已经访问过2次计数器合约（因为上面模拟交易总共访问过2次）
-    assert_eq!(GreetingAccount::try_from_slice(&greeted_account.data).unwrap().counter,2);
-}
\ No newline at end of file
+    assert_eq!(greeted_account.data.len(), GreetingAccountV2::LEN);
+    let rent = banks_client.get_rent().await.unwrap();
+    assert!(rent.is_exempt(greeted_account.lamports, GreetingAccountV2::LEN));
+    // 计数保持不变，支付租金的签名者成为管理员
+    let greeting_account = GreetingAccountV2::try_from_slice(&greeted_account.data).unwrap();
+    assert_eq!(greeting_account.counter, 7);
+    assert_eq!(greeting_account.authority, payer.pubkey());
+    assert_eq!(greeting_account.last_caller, payer.pubkey());
+    assert_ne!(greeting_account.last_updated_slot, 0);
+
+    // 迁移以后管理员可以直接设置计数
+    let instruction = Instruction::new_with_borsh(
+        program_id,
+        &CounterInstruction::Set { value: 100 },
+        vec![AccountMeta::new(greeted_pubkey, false), AccountMeta::new_readonly(payer.pubkey(), true)],
+    );
+    send(&mut banks_client, &[&payer], recent_blockhash, instruction).await.unwrap();
+    assert_eq!(get_counter(&mut banks_client, greeted_pubkey).await,100);
+}
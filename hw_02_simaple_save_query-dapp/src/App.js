@@ -28,7 +28,8 @@ class Content extends React.Component {
     this.connection = new Connection(url);
     // 8B7QfHs8gFKmSLfTSiLoHCDntZ94BKoaEVe82sxBqjei
     this.programID = new PublicKey("8gSU73AzuwE2SgVyU62cTgBF2YPqoKLr7hnhxZf31Go7");
-    this.messageAccount = new Account();
+    // 消息的topic（每个用户的每个topic对应一个消息账户）
+    this.topic = "greeting";
     this.playerPrivKey = [82,225,254,52,201,68,118,133,116,22,32,184,203,37,211,93,152,39,183,144,80,221,96,180,32,218,118,10,16,137,239,56,223,133,86,36,186,139,88,155,30,10,104,80,106,84,55,105,47,30,248,38,224,88,195,126,248,29,120,173,150,207,110,168];
     this.playerAccount = new Account(this.playerPrivKey);
   }
@@ -55,16 +56,18 @@ class Content extends React.Component {
   }
 
   async onQuery() {
+      const messageAccountKey = await HelloWorld.findMessageAddress(this.playerAccount.publicKey, this.programID, this.topic);
       let trxi = HelloWorld.createQueryInstruction(
           this.playerAccount.publicKey,
-          this.messageAccount.publicKey,
+          messageAccountKey,
           this.programID,
+          this.topic,
       );
 
       const transaction = new Transaction();
       transaction.add(trxi);
 
-      let signers= [this.playerAccount, this.messageAccount];
+      let signers= [this.playerAccount];
       sendAndConfirmTransaction(this.connection, transaction, signers, {
           skipPreflight: false,
           commitment: 'recent',
@@ -78,16 +81,18 @@ class Content extends React.Component {
   }
 
   async onErase() {
+    const messageAccountKey = await HelloWorld.findMessageAddress(this.playerAccount.publicKey, this.programID, this.topic);
     let trxi = HelloWorld.createEraseInstruction(
         this.playerAccount.publicKey,
-        this.messageAccount.publicKey,
+        messageAccountKey,
         this.programID,
+        this.topic,
     );
 
     const transaction = new Transaction();
     transaction.add(trxi);
 
-    let signers= [this.playerAccount, this.messageAccount];
+    let signers= [this.playerAccount];
     sendAndConfirmTransaction(this.connection, transaction, signers, {
       skipPreflight: false,
       commitment: 'recent',
@@ -100,37 +105,30 @@ class Content extends React.Component {
   }
 
   async onHello() {
-      // 存储指定长度的数据所需要的价格（注意：这个钱由发起账户会自动打到数据账户）
-      let messageNeeded = await this.connection.getMinimumBalanceForRentExemption(Layout.messagSpace);
-      console.info("账户数据长度=",messageNeeded);
-      // 创建存储数据账户（因为要存储数据所以我们需要指定数据最大长度）
-      const trxi0 =  SystemProgram.createAccount({
-          fromPubkey: this.playerAccount.publicKey,
-          newAccountPubkey: this.messageAccount.publicKey,
-          lamports: messageNeeded,
-          space: Layout.messagSpace,
-          programId: this.programID,
-      });
-
+      const message = "hello world!ssdfsdfsdfsd";
+      // 按message实际的字节数计算账户数据长度（消息账户不存在时由合约按这个长度创建，费用由发起账户支付）
+      const space = Layout.messageSpace(Buffer.from(message, 'utf8').length);
+      let messageNeeded = await this.connection.getMinimumBalanceForRentExemption(space);
+      console.info("账户数据长度=",space,"免租金额=",messageNeeded);
+
+      // 消息账户地址由用户地址和topic生成
+      const messageAccountKey = await HelloWorld.findMessageAddress(this.playerAccount.publicKey, this.programID, this.topic);
       console.log("playerAccount:", this.playerAccount.publicKey.toBase58());
-      console.log("message:", this.messageAccount.publicKey.toBase58());
-
-      const pkey3 = await PublicKey.findProgramAddress([this.playerAccount.publicKey.toBuffer()],this.programID)
-      console.log("pkey3:",pkey3.toString())
+      console.log("message:", messageAccountKey.toBase58());
 
       // 创建调用智能合约参数也就是生成 input数据
       let trxi = HelloWorld.createHelloInstruction(
           this.playerAccount.publicKey,
-          this.messageAccount.publicKey,
+          messageAccountKey,
           this.programID,
-          "hello world!ssdfsdfsdfsd",
+          this.topic,
+          message,
       );
 
       const transaction = new Transaction();
-      transaction.add(trxi0);
       transaction.add(trxi);
 
-      let signers= [this.playerAccount, this.messageAccount];
+      let signers= [this.playerAccount];
       sendAndConfirmTransaction(this.connection, transaction, signers, {
           skipPreflight: false,
           commitment: 'recent',
@@ -13,31 +13,64 @@ import * as BufferLayout from '@solana/buffer-layout'
  * HelloWorld
  */
 export class HelloWorld {
-    static createHelloInstruction(
+    /**
+     * 获取用户某个topic的消息账户地址（每个用户的每个topic对应一个消息账户）
+     */
+    static async findMessageAddress(
         playerAccountKey,
-        messageAccountKey,
         programID,
-        message,
+        topic,
     ) {
+        const [messageAccountKey] = await PublicKey.findProgramAddress(
+            [Buffer.from("message", 'utf8'), playerAccountKey.toBuffer(), Buffer.from(topic, 'utf8')],
+            programID,
+        );
+        return messageAccountKey;
+    }
 
+    /**
+     * 生成函数标识 + topic（1个字节topic长度 + topic数据）的 input
+     */
+    static encodeTopic(i, topic) {
+        const topicBuffer = Buffer.from(topic, 'utf8');
         const dataLayout = BufferLayout.struct([
             BufferLayout.u8("i"),
-            BufferLayout.blob(message.length,"message"),
+            BufferLayout.u8("topicLength"),
+            BufferLayout.blob(topicBuffer.length,"topic"),
         ]);
 
         const data = Buffer.alloc(dataLayout.span);
         dataLayout.encode(
             {
-              i:0, // hello
-              message: Buffer.from(message, 'utf8'),
+                i,
+                topicLength: topicBuffer.length,
+                topic: topicBuffer,
             },
             data,
         );
+        return data;
+    }
+
+    static createHelloInstruction(
+        playerAccountKey,
+        messageAccountKey,
+        programID,
+        topic,
+        message,
+    ) {
+
+        const messageBuffer = Buffer.from(message, 'utf8');
+        const data = Buffer.concat([
+            HelloWorld.encodeTopic(0, topic), // hello
+            messageBuffer,
+        ]);
 
         let keys = [
             // is_signer 表示访问者是否持有私钥，is_writable 表示合约程序是否可以修改账户信息
             {pubkey: playerAccountKey, isSigner: true, isWritable: true},
-            {pubkey: messageAccountKey, isSigner: true, isWritable: true},
+            // 消息账户是合约生成的PDA地址（不存在时由合约创建）
+            {pubkey: messageAccountKey, isSigner: false, isWritable: true},
+            {pubkey: SystemProgram.programId, isSigner: false, isWritable: false},
         ];
 
         const  trxi = new TransactionInstruction({
@@ -53,24 +86,14 @@ export class HelloWorld {
         playerAccountKey,
         messageAccountKey,
         programID,
-        message,
+        topic,
     ) {
 
-        const dataLayout = BufferLayout.struct([
-            BufferLayout.u8("i"),
-        ]);
-
-        const data = Buffer.alloc(dataLayout.span);
-        dataLayout.encode(
-            {
-              i:1, // erase
-            },
-            data,
-        );
+        const data = HelloWorld.encodeTopic(1, topic); // erase
 
         let keys = [
             {pubkey: playerAccountKey, isSigner: true, isWritable: true},
-            {pubkey: messageAccountKey, isSigner: true, isWritable: true},
+            {pubkey: messageAccountKey, isSigner: false, isWritable: true},
         ];
 
         const  trxi = new TransactionInstruction({
@@ -85,24 +108,14 @@ export class HelloWorld {
         playerAccountKey,
         messageAccountKey,
         programID,
-        message,
+        topic,
     ) {
 
-        const dataLayout = BufferLayout.struct([
-            BufferLayout.u8("i"),
-        ]);
-
-        const data = Buffer.alloc(dataLayout.span);
-        dataLayout.encode(
-            {
-                i:2, // erase
-            },
-            data,
-        );
+        const data = HelloWorld.encodeTopic(2, topic); // query
 
         let keys = [
-            {pubkey: playerAccountKey, isSigner: true, isWritable: true},
-            {pubkey: messageAccountKey, isSigner: true, isWritable: true},
+            {pubkey: playerAccountKey, isSigner: true, isWritable: false},
+            {pubkey: messageAccountKey, isSigner: false, isWritable: false},
         ];
 
         const  trxi = new TransactionInstruction({
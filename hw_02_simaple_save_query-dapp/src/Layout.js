@@ -9,4 +9,16 @@ export function publicKey(property) {
     return BufferLayout.blob(32, property);
 }
 
-export const messagSpace = 32+1+256;
+// topic的最大长度（字节数）
+export const MAX_TOPIC_LEN = 32;
+
+// 最多保留多少条历史版本
+export const HISTORY_CAPACITY = 8;
+// 一条历史版本的长度（32个字节message哈希 + 8个字节slot + 32个字节修改者公钥）
+export const REVISION_LEN = 32+8+32;
+
+// 存储指定长度（字节数）的message所需要的账户数据长度
+// （32个字节公钥 + 1个字节topic长度 + 32个字节topic + 1个字节历史版本位置 + 1个字节历史版本数量 + 历史版本 + 4个字节message长度 + message数据）
+export function messageSpace(messageLength) {
+    return 32+1+MAX_TOPIC_LEN+1+1+REVISION_LEN*HISTORY_CAPACITY+4+messageLength;
+}
@@ -0,0 +1,260 @@
+use solana_program::instruction::Instruction;
+use solana_program::program_error::ProgramError;
+use solana_program::pubkey::Pubkey;
+use crate::instruction;
+use crate::state::{find_message_address, MessageRevision, MessageView};
+
+// 该文件是链下客户端调用合约的辅助函数
+
+// 运行时输出返回数据的日志前缀（格式为 "Program return: <合约ID> <base64编码的返回数据>"）
+const PROGRAM_RETURN_LOG_PREFIX: &str = "Program return: ";
+
+/**
+ * 创建调用Query函数的指令（消息账户地址由发起者和topic生成）
+ * @param program_id 合约ID
+ * @param client     发起者地址（需要签名，同时也是消息的所有者）
+ * @param topic      消息的topic
+ */
+pub fn query_instruction(program_id: &Pubkey,client: &Pubkey,topic: &str) -> Instruction {
+    let (message, _) = find_message_address(program_id, client, topic);
+    instruction::query(program_id, client, &message, topic)
+}
+
+/**
+ * 创建调用QueryHistory函数的指令（消息账户地址由发起者和topic生成）
+ * @param program_id 合约ID
+ * @param client     发起者地址（需要签名，同时也是消息的所有者）
+ * @param topic      消息的topic
+ */
+pub fn query_history_instruction(program_id: &Pubkey,client: &Pubkey,topic: &str) -> Instruction {
+    let (message, _) = find_message_address(program_id, client, topic);
+    instruction::query_history(program_id, client, &message, topic)
+}
+
+/**
+ * 从交易日志中获取合约的返回数据（取最后一条，因为后面的返回数据会覆盖前面的）
+ * @param program_id 合约ID
+ * @param logs       交易日志
+ */
+pub fn parse_return_data(program_id: &Pubkey,logs: &[String]) -> Option<Vec<u8>> {
+    let prefix = format!("{}{} ", PROGRAM_RETURN_LOG_PREFIX, program_id);
+    logs.iter()
+        .rev()
+        .find_map(|log| log.strip_prefix(&prefix))
+        .and_then(|data| base64::decode(data).ok())
+}
+
+/**
+ * 将Query函数的返回数据转换成 MessageView
+ * @param program_id 合约ID
+ * @param logs       交易日志
+ */
+pub fn decode_query_logs(program_id: &Pubkey,logs: &[String]) -> Result<MessageView, ProgramError> {
+    let return_data = parse_return_data(program_id, logs).ok_or(ProgramError::InvalidAccountData)?;
+    MessageView::unpack_from_slice(&return_data)
+}
+
+/**
+ * 将QueryHistory函数的返回数据转换成历史版本列表（第一个字节是历史版本的数量，后面是按时间顺序排列的历史版本）
+ * @param program_id 合约ID
+ * @param logs       交易日志
+ */
+pub fn decode_history_logs(program_id: &Pubkey,logs: &[String]) -> Result<Vec<MessageRevision>, ProgramError> {
+    let return_data = parse_return_data(program_id, logs).ok_or(ProgramError::InvalidAccountData)?;
+    let (&count, revisions_buf) = return_data.split_first().ok_or(ProgramError::InvalidAccountData)?;
+    if revisions_buf.len() != count as usize * MessageRevision::LEN {
+        return Err(ProgramError::InvalidAccountData);
+    }
+    Ok(revisions_buf.chunks_exact(MessageRevision::LEN).map(MessageRevision::unpack_from_slice).collect())
+}
+
+/**
+ * 模拟执行Query函数并解析返回数据（不会真正发送交易，也不需要支付手续费）
+ * 注意：当前版本的 BanksClient 不支持模拟交易，也拿不到返回数据，所以这里通过 RpcClient 模拟
+ * @param rpc_client RPC客户端
+ * @param program_id 合约ID
+ * @param client     发起者（需要签名，同时也是消息的所有者）
+ * @param topic      消息的topic
+ */
+#[cfg(feature = "client")]
+#[allow(clippy::result_large_err)]
+pub fn simulate_query(
+    rpc_client: &solana_client::rpc_client::RpcClient,
+    program_id: &Pubkey,
+    client: &solana_sdk::signature::Keypair,
+    topic: &str,
+) -> solana_client::client_error::Result<MessageView> {
+    use solana_client::client_error::ClientErrorKind;
+    use solana_sdk::signature::Signer;
+    use solana_sdk::transaction::Transaction;
+
+    let instruction = query_instruction(program_id, &client.pubkey(), topic);
+    let recent_blockhash = rpc_client.get_latest_blockhash()?;
+    let transaction = Transaction::new_signed_with_payer(&[instruction], Some(&client.pubkey()), &[client], recent_blockhash);
+    let result = rpc_client.simulate_transaction(&transaction)?.value;
+    if let Some(err) = result.err {
+        return Err(ClientErrorKind::TransactionError(err).into());
+    }
+    decode_query_logs(program_id, &result.logs.unwrap_or_default())
+        .map_err(|err| ClientErrorKind::Custom(format!("Invalid query return data: {}", err)).into())
+}
+
+/**
+ * 查询某个用户的所有消息（按消息账户数据里面的所有者过滤合约的所有账户）
+ * @param rpc_client RPC客户端
+ * @param program_id 合约ID
+ * @param owner      消息所有者地址
+ */
+#[cfg(feature = "client")]
+#[allow(clippy::result_large_err)]
+pub fn list_messages(
+    rpc_client: &solana_client::rpc_client::RpcClient,
+    program_id: &Pubkey,
+    owner: &Pubkey,
+) -> solana_client::client_error::Result<Vec<(Pubkey, crate::state::HelloWorldState)>> {
+    use solana_account_decoder::UiAccountEncoding;
+    use solana_client::rpc_config::{RpcAccountInfoConfig, RpcProgramAccountsConfig};
+    use solana_client::rpc_filter::{Memcmp, MemcmpEncodedBytes, RpcFilterType};
+    use crate::state::HelloWorldState;
+
+    let config = RpcProgramAccountsConfig {
+        filters: Some(vec![RpcFilterType::Memcmp(Memcmp {
+            offset: HelloWorldState::ACCOUNT_KEY_OFFSET,
+            bytes: MemcmpEncodedBytes::Base58(owner.to_string()),
+            encoding: None,
+        })]),
+        account_config: RpcAccountInfoConfig {
+            encoding: Some(UiAccountEncoding::Base64),
+            ..RpcAccountInfoConfig::default()
+        },
+        ..RpcProgramAccountsConfig::default()
+    };
+    let accounts = rpc_client.get_program_accounts_with_config(program_id, config)?;
+    // 跳过数据不合法的账户
+    Ok(accounts
+        .into_iter()
+        .filter_map(|(address, account)| {
+            HelloWorldState::unpack_from_slice(&account.data).ok().map(|state| (address, state))
+        })
+        .collect())
+}
+
+/**
+ * 查询某个用户所有消息的topic
+ * @param rpc_client RPC客户端
+ * @param program_id 合约ID
+ * @param owner      消息所有者地址
+ */
+#[cfg(feature = "client")]
+#[allow(clippy::result_large_err)]
+pub fn list_topics(
+    rpc_client: &solana_client::rpc_client::RpcClient,
+    program_id: &Pubkey,
+    owner: &Pubkey,
+) -> solana_client::client_error::Result<Vec<String>> {
+    list_messages(rpc_client, program_id, owner)
+        .map(|messages| messages.into_iter().map(|(_, state)| state.topic).collect())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn decode_query_return_data() {
+        let program_id = Pubkey::new_unique();
+        let view = MessageView {
+            account_key: Pubkey::new_unique(),
+            topic: String::from("greeting"),
+            message: String::from("hello world!"),
+        };
+        let mut data = vec![0u8; view.get_packed_len()];
+        view.pack_into_slice(&mut data).unwrap();
+        let logs = vec![
+            format!("Program {} invoke [1]", program_id),
+            String::from("Program log: 调用智能合约Query函数"),
+            format!("Program return: {} {}", Pubkey::new_unique(), base64::encode(b"other")),
+            format!("Program return: {} {}", program_id, base64::encode(&data)),
+            format!("Program {} success", program_id),
+        ];
+        assert_eq!(decode_query_logs(&program_id, &logs).unwrap(), view);
+
+        // 没有当前合约的返回数据
+        assert_eq!(parse_return_data(&program_id, &logs[..3]), None);
+    }
+
+    #[test]
+    fn decode_history_return_data() {
+        let program_id = Pubkey::new_unique();
+        let revision = MessageRevision {
+            message_hash: solana_program::hash::hash(b"hello"),
+            slot: 7,
+            editor: Pubkey::new_unique(),
+        };
+        let mut data = vec![1u8; 1 + MessageRevision::LEN];
+        revision.pack_into_slice(&mut data[1..]);
+        let logs = vec![format!("Program return: {} {}", program_id, base64::encode(&data))];
+        assert_eq!(decode_history_logs(&program_id, &logs).unwrap(), vec![revision]);
+
+        // 历史版本的数量和数据长度不一致
+        data[0] = 2;
+        let logs = vec![format!("Program return: {} {}", program_id, base64::encode(&data))];
+        assert_eq!(decode_history_logs(&program_id, &logs), Err(ProgramError::InvalidAccountData));
+    }
+
+    // 用模拟的RPC节点测试 simulate_query（模拟节点按请求类型返回预设的结果）
+    #[cfg(feature = "client")]
+    fn mock_simulate(logs: Vec<String>,err: Option<solana_sdk::transaction::TransactionError>) -> solana_client::rpc_client::RpcClient {
+        use std::collections::HashMap;
+        use solana_client::rpc_request::RpcRequest;
+        use solana_client::rpc_response::{Response, RpcResponseContext, RpcSimulateTransactionResult};
+
+        let mut mocks = HashMap::new();
+        mocks.insert(
+            RpcRequest::SimulateTransaction,
+            serde_json::to_value(Response {
+                context: RpcResponseContext { slot: 1, api_version: None },
+                value: RpcSimulateTransactionResult { err, logs: Some(logs), accounts: None, units_consumed: None },
+            })
+            .unwrap(),
+        );
+        solana_client::rpc_client::RpcClient::new_mock_with_mocks("succeeds".to_string(), mocks)
+    }
+
+    #[cfg(feature = "client")]
+    #[test]
+    fn simulate_query_decodes_return_data() {
+        use solana_client::client_error::ClientErrorKind;
+        use solana_sdk::instruction::InstructionError;
+        use solana_sdk::signature::{Keypair, Signer};
+        use solana_sdk::transaction::TransactionError;
+
+        let program_id = Pubkey::new_unique();
+        let client = Keypair::new();
+        let view = MessageView {
+            account_key: client.pubkey(),
+            topic: String::from("greeting"),
+            message: String::from("hello world!"),
+        };
+        let mut data = vec![0u8; view.get_packed_len()];
+        view.pack_into_slice(&mut data).unwrap();
+        let logs = vec![
+            format!("Program {} invoke [1]", program_id),
+            format!("Program return: {} {}", program_id, base64::encode(&data)),
+            format!("Program {} success", program_id),
+        ];
+        let rpc_client = mock_simulate(logs.clone(), None);
+        assert_eq!(simulate_query(&rpc_client, &program_id, &client, "greeting").unwrap(), view);
+
+        // 模拟执行失败时返回交易错误
+        let tx_err = TransactionError::InstructionError(0, InstructionError::Custom(1));
+        let rpc_client = mock_simulate(logs, Some(tx_err.clone()));
+        let err = simulate_query(&rpc_client, &program_id, &client, "greeting").unwrap_err();
+        assert!(matches!(err.kind(), ClientErrorKind::TransactionError(e) if *e == tx_err));
+
+        // 日志里面没有当前合约的返回数据
+        let rpc_client = mock_simulate(vec![format!("Program {} success", program_id)], None);
+        let err = simulate_query(&rpc_client, &program_id, &client, "greeting").unwrap_err();
+        assert!(matches!(err.kind(), ClientErrorKind::Custom(_)));
+    }
+}
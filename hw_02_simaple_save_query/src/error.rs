@@ -12,6 +12,24 @@ pub enum HelloWorldError {
     /// Invalid instruction
     #[error("Invalid instruction")]
     InvalidInstruction,
+    /// Message does not fit into the message account
+    #[error("Message too long")]
+    MessageTooLong,
+    /// Message is not valid UTF-8
+    #[error("Invalid UTF-8 message")]
+    InvalidUtf8,
+    /// Signer is not the owner of the message
+    #[error("Signer is not the message owner")]
+    NotOwner,
+    /// Message account is already used by another owner
+    #[error("Message account already initialized")]
+    AlreadyInitialized,
+    /// Message account is not owned by the program
+    #[error("Message account is not owned by the program")]
+    NotProgramOwned,
+    /// Topic is longer than 32 bytes
+    #[error("Invalid topic")]
+    InvalidTopic,
 }
 
 impl From<HelloWorldError> for ProgramError {
@@ -33,6 +51,12 @@ impl PrintProgramError for HelloWorldError {
     {
         match self {
             HelloWorldError::InvalidInstruction => msg!("Invalid instruction"),
+            HelloWorldError::MessageTooLong => msg!("Message too long"),
+            HelloWorldError::InvalidUtf8 => msg!("Invalid UTF-8 message"),
+            HelloWorldError::NotOwner => msg!("Signer is not the message owner"),
+            HelloWorldError::AlreadyInitialized => msg!("Message account already initialized"),
+            HelloWorldError::NotProgramOwned => msg!("Message account is not owned by the program"),
+            HelloWorldError::InvalidTopic => msg!("Invalid topic"),
         }
     }
 }
\ No newline at end of file
@@ -1,74 +1,275 @@
-use std::mem::size_of;
 use std::str::from_utf8;
+use borsh::{BorshDeserialize, BorshSerialize};
+use solana_program::instruction::{AccountMeta, Instruction};
 use solana_program::msg;
 use solana_program::program_error::ProgramError;
+use solana_program::pubkey::Pubkey;
+use solana_program::system_program;
 use crate::error::HelloWorldError;
+use crate::state::MAX_TOPIC_LEN;
 
 // 该文件是解析调用合约入口参数
 
-// 该枚举定义合约有那些函数以及函数的参数
+// Borsh编码的指令第一个字节是版本号（旧版本编码的第一个字节是函数标识0-2，版本号不能和函数标识重复）
+pub const INSTRUCTION_VERSION: u8 = 0x80;
+
+// 旧版本编码没有topic，旧版本的客户端读写的都是这个topic的消息
+pub const LEGACY_TOPIC: &str = "";
+
+// 该枚举定义合约有那些函数以及函数的参数（新版本使用Borsh编码，注意：不能调整函数的顺序，Borsh使用函数的顺序作为函数标识）
 #[repr(C)]
-#[derive(Debug)]
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, PartialEq)]
 pub enum HelloWorldInstruction {
-    // 函数Hello参数是message
+    // 函数Hello参数是消息的topic和message
+    // 账户：0. `[writable, signer]` 发起者（消息的所有者，同时支付创建消息账户的费用）
+    //      1. `[writable]` 消息账户（发起者和topic生成的PDA地址）
+    //      2. `[]` 系统合约
     Hello {
+        topic: String,
         message: String,
     },
-    // 函数Erase没有参数
-    Erase,
-    // 函数Query
-    Query,
+    // 函数Erase参数是要删除消息的topic
+    // 账户：0. `[writable, signer]` 发起者（消息的所有者，接收消息账户的余额）
+    //      1. `[writable]` 消息账户
+    Erase {
+        topic: String,
+    },
+    // 函数Query参数是要查询消息的topic
+    // 账户：0. `[signer]` 发起者（消息的所有者）
+    //      1. `[]` 消息账户
+    Query {
+        topic: String,
+    },
+    // 函数Resize参数是扩容（或缩容）后message可以存储的最大长度
+    // 账户：0. `[writable, signer]` 发起者（消息的所有者，支付或接收余额差额）
+    //      1. `[writable]` 消息账户
+    //      2. `[]` 系统合约
+    Resize {
+        message_len: u32,
+    },
+    // 函数QueryHistory参数是要查询历史版本的消息的topic
+    // 账户：0. `[signer]` 发起者（消息的所有者）
+    //      1. `[]` 消息账户
+    QueryHistory {
+        topic: String,
+    },
 }
 
 impl HelloWorldInstruction {
-    // 解析 input 参数
+    // 解析 input 参数（第一个字节是版本号时按Borsh解析，否则按旧版本编码解析）
     pub fn unpack(input: &[u8]) -> Result<Self, ProgramError> {
         use HelloWorldError::InvalidInstruction;
         msg!("正在解析 Input！");
+        let (&version, rest) = input.split_first().ok_or(InvalidInstruction)?;
+        if version != INSTRUCTION_VERSION {
+            return Self::unpack_legacy(input);
+        }
+        let instruction = Self::try_from_slice(rest).map_err(|_| InvalidInstruction)?;
+        // topic会作为生成PDA地址的种子，不能超过32个字节
+        match &instruction {
+            Self::Hello { topic, .. } | Self::Erase { topic } | Self::Query { topic } | Self::QueryHistory { topic }
+                if topic.len() > MAX_TOPIC_LEN => Err(HelloWorldError::InvalidTopic.into()),
+            _ => Ok(instruction),
+        }
+    }
+
+    // 解析旧版本编码的 input 参数（兼容还没有升级的客户端：第一个字节是函数标识，Hello后面是message，没有topic，使用默认topic）
+    pub fn unpack_legacy(input: &[u8]) -> Result<Self, ProgramError> {
+        use HelloWorldError::InvalidInstruction;
         let (&tag, rest) = input.split_first().ok_or(InvalidInstruction)?;
+        let topic = String::from(LEGACY_TOPIC);
         Ok(match tag {
             // 调用合约 Hello函数
             0 => {
-                let message= String::from(from_utf8(rest).unwrap());
+                let message= String::from(from_utf8(rest).map_err(|_| HelloWorldError::InvalidUtf8)?);
                 Self::Hello{
+                    topic,
                     message,
                 }
             },
             // 调用合约 Erase函数
-            1 => Self::Erase,
+            1 => Self::Erase { topic },
             // 调用Query函数
-            2 => Self::Query,
+            2 => Self::Query { topic },
             _ => return Err(HelloWorldError::InvalidInstruction.into()),
         })
     }
 
-    // 打包参数成 input
+    // 打包参数成 input（第一个字节是版本号，后面是Borsh编码的数据）
     pub fn pack(&self) -> Vec<u8> {
-        let mut buf : Vec<u8>;
-        let self_len= size_of::<Self>();
-        match self {
-            &Self::Hello {
-                ref message,
-            } => {
-                // 初始化缓冲区大小
-                buf = Vec::with_capacity(self_len+1);
-                // 前面第一个字节是函数标识
-                buf.push(0); // tag
-                buf.extend_from_slice(message.as_bytes());
-            }
-            Self::Erase => {
-                // 初始化缓冲区大小
-                buf = Vec::with_capacity(self_len);
-                // 前面第一个字节是函数标识
-                buf.push(1); //tag
-            },
-            Self::Query => {
-                // 初始化缓冲区大小
-                buf = Vec::with_capacity(self_len);
-                // 前面第一个字节是函数标识
-                buf.push(2);
-            }
-        };
+        let mut buf = vec![INSTRUCTION_VERSION];
+        // 写入Vec不会失败
+        self.serialize(&mut buf).unwrap();
         buf
     }
-}
\ No newline at end of file
+}
+
+/**
+ * 创建调用Hello函数的指令
+ * @param program_id      合约ID
+ * @param client          发起者地址（需要签名）
+ * @param message_account 消息账户地址（发起者和topic生成的PDA地址）
+ * @param topic           消息的topic
+ * @param message         要存储的消息
+ */
+pub fn hello(program_id: &Pubkey,client: &Pubkey,message_account: &Pubkey,topic: &str,message: &str) -> Instruction {
+    Instruction {
+        program_id: *program_id,
+        accounts: vec![
+            AccountMeta::new(*client, true),
+            AccountMeta::new(*message_account, false),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+        data: HelloWorldInstruction::Hello { topic: String::from(topic), message: String::from(message) }.pack(),
+    }
+}
+
+/**
+ * 创建调用Erase函数的指令
+ * @param program_id      合约ID
+ * @param client          发起者地址（需要签名）
+ * @param message_account 消息账户地址
+ * @param topic           消息的topic
+ */
+pub fn erase(program_id: &Pubkey,client: &Pubkey,message_account: &Pubkey,topic: &str) -> Instruction {
+    Instruction {
+        program_id: *program_id,
+        accounts: vec![
+            AccountMeta::new(*client, true),
+            AccountMeta::new(*message_account, false),
+        ],
+        data: HelloWorldInstruction::Erase { topic: String::from(topic) }.pack(),
+    }
+}
+
+/**
+ * 创建调用Query函数的指令
+ * @param program_id      合约ID
+ * @param client          发起者地址（需要签名）
+ * @param message_account 消息账户地址
+ * @param topic           消息的topic
+ */
+pub fn query(program_id: &Pubkey,client: &Pubkey,message_account: &Pubkey,topic: &str) -> Instruction {
+    Instruction {
+        program_id: *program_id,
+        accounts: vec![
+            AccountMeta::new_readonly(*client, true),
+            AccountMeta::new_readonly(*message_account, false),
+        ],
+        data: HelloWorldInstruction::Query { topic: String::from(topic) }.pack(),
+    }
+}
+
+/**
+ * 创建调用Resize函数的指令
+ * @param program_id      合约ID
+ * @param client          发起者地址（需要签名）
+ * @param message_account 消息账户地址
+ * @param message_len     扩容（或缩容）后message可以存储的最大长度
+ */
+pub fn resize(program_id: &Pubkey,client: &Pubkey,message_account: &Pubkey,message_len: u32) -> Instruction {
+    Instruction {
+        program_id: *program_id,
+        accounts: vec![
+            AccountMeta::new(*client, true),
+            AccountMeta::new(*message_account, false),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+        data: HelloWorldInstruction::Resize { message_len }.pack(),
+    }
+}
+
+/**
+ * 创建调用QueryHistory函数的指令
+ * @param program_id      合约ID
+ * @param client          发起者地址（需要签名）
+ * @param message_account 消息账户地址
+ * @param topic           消息的topic
+ */
+pub fn query_history(program_id: &Pubkey,client: &Pubkey,message_account: &Pubkey,topic: &str) -> Instruction {
+    Instruction {
+        program_id: *program_id,
+        accounts: vec![
+            AccountMeta::new_readonly(*client, true),
+            AccountMeta::new_readonly(*message_account, false),
+        ],
+        data: HelloWorldInstruction::QueryHistory { topic: String::from(topic) }.pack(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use proptest::prelude::*;
+
+    // 不超过32个字节的topic
+    fn topic() -> impl Strategy<Value = String> {
+        "[a-zA-Z0-9_-]{0,32}"
+    }
+
+    // 所有的函数以及参数
+    fn instruction() -> impl Strategy<Value = HelloWorldInstruction> {
+        prop_oneof![
+            (topic(), any::<String>()).prop_map(|(topic, message)| HelloWorldInstruction::Hello { topic, message }),
+            topic().prop_map(|topic| HelloWorldInstruction::Erase { topic }),
+            topic().prop_map(|topic| HelloWorldInstruction::Query { topic }),
+            any::<u32>().prop_map(|message_len| HelloWorldInstruction::Resize { message_len }),
+            topic().prop_map(|topic| HelloWorldInstruction::QueryHistory { topic }),
+        ]
+    }
+
+    proptest! {
+        #[test]
+        fn pack_unpack_round_trip(instruction in instruction()) {
+            let data = instruction.pack();
+            prop_assert_eq!(data[0], INSTRUCTION_VERSION);
+            prop_assert_eq!(HelloWorldInstruction::unpack(&data).unwrap(), instruction);
+        }
+
+        #[test]
+        fn unpack_legacy_hello(message in any::<String>()) {
+            // 旧版本的Hello就是函数标识0后面跟着message的UTF-8数据
+            let mut data = vec![0];
+            data.extend_from_slice(message.as_bytes());
+            prop_assert_eq!(
+                HelloWorldInstruction::unpack(&data).unwrap(),
+                HelloWorldInstruction::Hello { topic: String::from(LEGACY_TOPIC), message }
+            );
+        }
+
+        #[test]
+        fn unpack_arbitrary_input(data in proptest::collection::vec(any::<u8>(), 0..128)) {
+            // 任意数据都不会导致解析崩溃
+            let _ = HelloWorldInstruction::unpack(&data);
+        }
+    }
+
+    #[test]
+    fn unpack_invalid_input() {
+        assert_eq!(HelloWorldInstruction::unpack(&[]), Err(HelloWorldError::InvalidInstruction.into()));
+        // 只有版本号没有数据
+        assert_eq!(HelloWorldInstruction::unpack(&[INSTRUCTION_VERSION]), Err(HelloWorldError::InvalidInstruction.into()));
+        // topic超过32个字节
+        let instruction = HelloWorldInstruction::Erase { topic: "t".repeat(MAX_TOPIC_LEN + 1) };
+        assert_eq!(HelloWorldInstruction::unpack(&instruction.pack()), Err(HelloWorldError::InvalidTopic.into()));
+    }
+
+    #[test]
+    fn unpack_legacy_input() {
+        // 旧版本客户端发送的数据（第一个字节是函数标识，没有topic）
+        assert_eq!(
+            HelloWorldInstruction::unpack(b"\x00hello").unwrap(),
+            HelloWorldInstruction::Hello { topic: String::from(LEGACY_TOPIC), message: String::from("hello") }
+        );
+        assert_eq!(
+            HelloWorldInstruction::unpack(&[0]).unwrap(),
+            HelloWorldInstruction::Hello { topic: String::from(LEGACY_TOPIC), message: String::new() }
+        );
+        assert_eq!(HelloWorldInstruction::unpack(&[1]).unwrap(), HelloWorldInstruction::Erase { topic: String::from(LEGACY_TOPIC) });
+        assert_eq!(HelloWorldInstruction::unpack(&[2]).unwrap(), HelloWorldInstruction::Query { topic: String::from(LEGACY_TOPIC) });
+        // message必须是UTF-8
+        assert_eq!(HelloWorldInstruction::unpack(&[0, 0xff]), Err(HelloWorldError::InvalidUtf8.into()));
+        // 旧版本只有3个函数
+        assert_eq!(HelloWorldInstruction::unpack(&[3, 0, 0, 0, 0]), Err(HelloWorldError::InvalidInstruction.into()));
+    }
+}
@@ -1,11 +1,19 @@
 use solana_program::account_info::{AccountInfo, next_account_info};
+use solana_program::clock::Clock;
+use solana_program::hash::hash;
 use solana_program::entrypoint::ProgramResult;
 use solana_program::msg;
+use solana_program::program::{invoke, invoke_signed, set_return_data, MAX_RETURN_DATA};
 use solana_program::program_error::ProgramError;
-use solana_program::program_pack::Pack;
+use solana_program::program_pack::IsInitialized;
 use solana_program::pubkey::Pubkey;
+use solana_program::rent::Rent;
+use solana_program::system_instruction;
+use solana_program::system_program;
+use solana_program::sysvar::Sysvar;
+use crate::error::HelloWorldError;
 use crate::instruction::HelloWorldInstruction;
-use crate::state::HelloWorldState;
+use crate::state::{find_message_address, HelloWorldState, MessageRevision, MAX_TOPIC_LEN, MESSAGE_SEED};
 
 
 // 该文件是合约里面各个函数的逻辑实现
@@ -13,29 +21,114 @@ pub struct Processor{}
 
 impl Processor {
 
+    // 校验消息账户是不是当前合约的账户
+    fn check_program_owned(program_id: &Pubkey,message_info: &AccountInfo) -> ProgramResult {
+        if message_info.owner != program_id {
+            msg!("消息账户不属于当前合约");
+            return Err(HelloWorldError::NotProgramOwned.into());
+        }
+        Ok(())
+    }
+
+    // 校验发起者是不是消息的所有者
+    fn check_owner(state: &HelloWorldState,client_info: &AccountInfo) -> ProgramResult {
+        if state.account_key != *client_info.key {
+            msg!("发起者不是消息的所有者");
+            return Err(HelloWorldError::NotOwner.into());
+        }
+        Ok(())
+    }
+
+    // 校验消息账户地址是不是由发起者和topic生成的PDA地址，返回PDA地址的bump
+    fn check_message_address(program_id: &Pubkey,client_info: &AccountInfo,message_info: &AccountInfo,topic: &str) -> Result<u8, ProgramError> {
+        if topic.len() > MAX_TOPIC_LEN {
+            return Err(HelloWorldError::InvalidTopic.into());
+        }
+        let (message_address, bump) = find_message_address(program_id, client_info.key, topic);
+        if message_address != *message_info.key {
+            msg!("消息账户地址和topic不匹配");
+            return Err(ProgramError::InvalidSeeds);
+        }
+        Ok(bump)
+    }
 
-    // 存储数据
-    pub fn processor_hello(message: String,accounts: &[AccountInfo]) -> ProgramResult {
+    // 存储数据（消息账户不存在时会先创建消息账户）
+    pub fn processor_hello(program_id: &Pubkey,topic: String,message: String,accounts: &[AccountInfo]) -> ProgramResult {
         let account_info_iter = &mut accounts.iter();
-        // 发起者账户信息
+        // 发起者账户信息（同时也是创建消息账户的付款账户）
         let client_info = next_account_info(account_info_iter)?;
         // 发起者要存储的数据信息
         let message_info = next_account_info(account_info_iter)?;
-        //
+        // 系统合约
+        let system_program_info = next_account_info(account_info_iter)?;
         if !client_info.is_signer {
             return Err(ProgramError::MissingRequiredSignature);
         }
+        let bump = Self::check_message_address(program_id,client_info,message_info,&topic)?;
+        // 消息账户还不存在，按message的长度创建消息账户
+        if message_info.owner != program_id {
+            let space = HelloWorldState::packed_len(message.len());
+            let rent_lamports = Rent::get()?.minimum_balance(space);
+            let signer_seeds: &[&[u8]] = &[MESSAGE_SEED, client_info.key.as_ref(), topic.as_bytes(), &[bump]];
+            if message_info.lamports() == 0 {
+                invoke_signed(
+                    &system_instruction::create_account(
+                        client_info.key,
+                        message_info.key,
+                        rent_lamports,
+                        space as u64,
+                        program_id,
+                    ),
+                    &[client_info.clone(), message_info.clone(), system_program_info.clone()],
+                    &[signer_seeds],
+                )?;
+            } else {
+                // PDA地址可以被别人提前转入SOL（create_account会失败），这时补足免租金的余额后再分配空间并指定所属合约
+                let shortfall = rent_lamports.saturating_sub(message_info.lamports());
+                if shortfall > 0 {
+                    invoke(
+                        &system_instruction::transfer(client_info.key, message_info.key, shortfall),
+                        &[client_info.clone(), message_info.clone(), system_program_info.clone()],
+                    )?;
+                }
+                invoke_signed(
+                    &system_instruction::allocate(message_info.key, space as u64),
+                    &[message_info.clone(), system_program_info.clone()],
+                    &[signer_seeds],
+                )?;
+                invoke_signed(
+                    &system_instruction::assign(message_info.key, program_id),
+                    &[message_info.clone(), system_program_info.clone()],
+                    &[signer_seeds],
+                )?;
+            }
+        }
+        Self::check_program_owned(program_id,message_info)?;
         // 解包生成 HelloWorldState
-        let mut state = HelloWorldState::unpack_unchecked(&message_info.data.borrow())?;
+        let mut state = HelloWorldState::unpack_from_slice(&message_info.data.borrow())?;
+        // 不能覆盖别人的消息（消息的所有者在第一次存储时确定）
+        if state.is_initialized() && state.account_key != *client_info.key {
+            msg!("消息账户已经被其他用户使用");
+            return Err(HelloWorldError::AlreadyInitialized.into());
+        }
+        // 覆盖之前先保存旧message的哈希（第一次存储没有旧message）
+        if state.is_initialized() {
+            state.push_revision(MessageRevision {
+                message_hash: hash(state.message.as_bytes()),
+                slot: Clock::get()?.slot,
+                editor: *client_info.key,
+            });
+        }
         state.account_key = *client_info.key;
+        state.topic = topic;
         state.message = message;
-        // 打包存储
-        HelloWorldState::pack(state, &mut message_info.data.borrow_mut())?;
+        // 打包存储（message超过账户可存储的长度时需要先调用Resize扩容）
+        state.pack_into_slice(&mut message_info.data.borrow_mut())?;
         Ok(())
     }
 
     // 删除数据（说明：因为Solana上存储数据是要付费的，删除数据，我们只需要将存储数据账户上的余额全部转走，就等于删除了数据）
-    pub fn processor_erase(accounts: &[AccountInfo]) -> ProgramResult {
+    pub fn processor_erase(program_id: &Pubkey,topic: String,accounts: &[AccountInfo]) -> ProgramResult {
         let account_info_iter = &mut accounts.iter();
         let client_info = next_account_info(account_info_iter)?;
         let message_info = next_account_info(account_info_iter)?;
@@ -44,45 +137,130 @@ impl Processor {
         if !client_info.is_signer {
             return Err(ProgramError::MissingRequiredSignature);
         }
+        Self::check_message_address(program_id,client_info,message_info,&topic)?;
+        Self::check_program_owned(program_id,message_info)?;
+        // 只有消息的所有者才能删除消息
+        let state = HelloWorldState::unpack_from_slice(&message_info.data.borrow())?;
+        Self::check_owner(&state,client_info)?;
         // 获取用户账户余额（就是有多少SOL）
         let client_starting_lamports = client_info.lamports();
         // 将消息账户余额全部转到用户账户（逻辑就是：用户账户余额 = 用户账户余额本身余额 + 消息账户余额）
         **client_info.lamports.borrow_mut() = client_starting_lamports + message_info.lamports();
         // 将消息账户余额置为0（这样消息账户上的数据就会自动被删除）
         **message_info.lamports.borrow_mut() = 0;
+        // 清空数据并将账户还给系统合约（防止同一笔交易里面再次使用这个账户）
+        message_info.data.borrow_mut().fill(0);
+        message_info.assign(&system_program::id());
         Ok(())
     }
 
     // 查询函数
-    pub fn processor_query(accounts: &[AccountInfo]) -> ProgramResult {
+    pub fn processor_query(program_id: &Pubkey,topic: String,accounts: &[AccountInfo]) -> ProgramResult {
         let account_info_iter = &mut accounts.iter();
         let client_info = next_account_info(account_info_iter)?;
         let message_info = next_account_info(account_info_iter)?;
         if !client_info.is_signer {
             return Err(ProgramError::MissingRequiredSignature);
         }
-        let state = HelloWorldState::unpack_unchecked(&message_info.data.borrow())?;
+        Self::check_message_address(program_id,client_info,message_info,&topic)?;
+        Self::check_program_owned(program_id,message_info)?;
+        let state = HelloWorldState::unpack_from_slice(&message_info.data.borrow())?;
         msg!("查询到链上数据: {:#?}",&state);
+        // 将消息视图打包后作为返回数据返回给调用者（客户端不需要再解析日志，历史版本通过QueryHistory函数查询）
+        let view = state.view();
+        let mut return_data = vec![0u8; view.get_packed_len()];
+        view.pack_into_slice(&mut return_data)?;
+        if return_data.len() > MAX_RETURN_DATA {
+            msg!("消息太长，不能通过返回数据返回");
+            return Err(HelloWorldError::MessageTooLong.into());
+        }
+        set_return_data(&return_data);
         Ok(())
     }
 
 
 
+    // 查询历史版本（按时间顺序返回所有历史版本，第一个字节是历史版本的数量）
+    pub fn processor_query_history(program_id: &Pubkey,topic: String,accounts: &[AccountInfo]) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let client_info = next_account_info(account_info_iter)?;
+        let message_info = next_account_info(account_info_iter)?;
+        if !client_info.is_signer {
+            return Err(ProgramError::MissingRequiredSignature);
+        }
+        Self::check_message_address(program_id,client_info,message_info,&topic)?;
+        Self::check_program_owned(program_id,message_info)?;
+        let state = HelloWorldState::unpack_from_slice(&message_info.data.borrow())?;
+        let revisions = state.revisions();
+        let mut return_data = vec![0u8; 1 + revisions.len() * MessageRevision::LEN];
+        return_data[0] = revisions.len() as u8;
+        for (revision, revision_buf) in revisions.iter().zip(return_data[1..].chunks_exact_mut(MessageRevision::LEN)) {
+            msg!("历史版本: {:?}",revision);
+            revision.pack_into_slice(revision_buf);
+        }
+        set_return_data(&return_data);
+        Ok(())
+    }
+
+    // 修改消息账户的数据长度（扩容时由用户补足免租金所需的余额，缩容时多余的余额退还给用户）
+    pub fn processor_resize(program_id: &Pubkey,message_len: u32,accounts: &[AccountInfo]) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let client_info = next_account_info(account_info_iter)?;
+        let message_info = next_account_info(account_info_iter)?;
+        let system_program_info = next_account_info(account_info_iter)?;
+        if !client_info.is_signer {
+            return Err(ProgramError::MissingRequiredSignature);
+        }
+        Self::check_program_owned(program_id,message_info)?;
+        let state = HelloWorldState::unpack_from_slice(&message_info.data.borrow())?;
+        Self::check_owner(&state,client_info)?;
+        let new_len = HelloWorldState::packed_len(message_len as usize);
+        // 不能缩容到放不下当前的message
+        if state.get_packed_len() > new_len {
+            return Err(HelloWorldError::MessageTooLong.into());
+        }
+
+        let rent_lamports = Rent::get()?.minimum_balance(new_len);
+        let message_lamports = message_info.lamports();
+        if rent_lamports > message_lamports {
+            // 扩容：由用户补足免租金所需的余额
+            invoke(
+                &system_instruction::transfer(client_info.key, message_info.key, rent_lamports - message_lamports),
+                &[client_info.clone(), message_info.clone(), system_program_info.clone()],
+            )?;
+        } else {
+            // 缩容：将多余的余额退还给用户
+            **client_info.lamports.borrow_mut() = client_info.lamports() + (message_lamports - rent_lamports);
+            **message_info.lamports.borrow_mut() = rent_lamports;
+        }
+        message_info.realloc(new_len, true)?;
+        msg!("消息账户数据长度修改为: {}",new_len);
+        Ok(())
+    }
+
     pub fn processor(program_id: &Pubkey,accounts: &[AccountInfo],input: &[u8]) -> ProgramResult {
         // 解析合约参数转换成实际调用函数和参数
         let instruction = HelloWorldInstruction::unpack(input)?;
         match instruction {
-            HelloWorldInstruction::Hello {message} => {
+            HelloWorldInstruction::Hello {topic,message} => {
                 msg!("调用智能合约Hello函数");
-                Self::processor_hello(message,accounts)
+                Self::processor_hello(program_id,topic,message,accounts)
             },
-            HelloWorldInstruction::Erase => {
+            HelloWorldInstruction::Erase {topic} => {
                 msg!("调用智能合约Erase函数");
-                Self::processor_erase(accounts)
+                Self::processor_erase(program_id,topic,accounts)
             },
-            HelloWorldInstruction::Query => {
+            HelloWorldInstruction::Query {topic} => {
                 msg!("调用智能合约Query函数");
-                Self::processor_query(accounts)
+                Self::processor_query(program_id,topic,accounts)
+            },
+            HelloWorldInstruction::Resize {message_len} => {
+                msg!("调用智能合约Resize函数");
+                Self::processor_resize(program_id,message_len,accounts)
+            },
+            HelloWorldInstruction::QueryHistory {topic} => {
+                msg!("调用智能合约QueryHistory函数");
+                Self::processor_query_history(program_id,topic,accounts)
             },
             _ => Err(ProgramError::InvalidInstructionData),
         }
@@ -1,66 +1,430 @@
 
-
 // 该文件定义实际要存储数据的结构体
 
+use std::convert::TryInto;
 use std::str::from_utf8;
-use arrayref::{array_mut_ref,mut_array_refs, array_ref, array_refs};
+use solana_program::clock::Slot;
+use solana_program::hash::{Hash, HASH_BYTES};
 use solana_program::program_error::ProgramError;
-use solana_program::program_pack::{IsInitialized, Pack, Sealed};
+use solana_program::program_pack::IsInitialized;
 use solana_program::pubkey::Pubkey;
+use crate::error::HelloWorldError;
+
+// 消息账户PDA地址的种子前缀
+pub const MESSAGE_SEED: &[u8] = b"message";
+// topic的最大长度（topic会作为生成PDA地址的种子，种子最大32个字节）
+pub const MAX_TOPIC_LEN: usize = 32;
+
+/**
+ * 获取用户某个topic的消息账户地址（每个用户的每个topic对应一个消息账户）
+ * @param program_id 合约ID
+ * @param owner      消息所有者地址
+ * @param topic      消息的topic
+ */
+pub fn find_message_address(program_id: &Pubkey,owner: &Pubkey,topic: &str) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[MESSAGE_SEED, owner.as_ref(), topic.as_bytes()], program_id)
+}
+
+// 最多保留多少条历史版本（超过后覆盖最早的历史版本）
+pub const HISTORY_CAPACITY: usize = 8;
+
+// message的一个历史版本（只记录message的哈希，不保存原文）
+#[repr(C)]
+#[derive(Clone,Copy,Debug,Default,PartialEq)]
+pub struct MessageRevision {
+    // 被覆盖的message的哈希
+    pub message_hash: Hash,
+    // 覆盖时的slot
+    pub slot: Slot,
+    // 覆盖message的用户
+    pub editor: Pubkey,
+}
+
+impl MessageRevision {
+    // 数据长度（message_hash 32个字节 + slot 8个字节 + editor 32个字节）
+    pub const LEN: usize = HASH_BYTES + 8 + 32;
+
+    // 数据解包
+    pub fn unpack_from_slice(src: &[u8]) -> Self {
+        let (message_hash_buf, rest) = src.split_at(HASH_BYTES);
+        let (slot_buf, editor_buf) = rest.split_at(8);
+        MessageRevision {
+            message_hash: Hash::new(message_hash_buf),
+            slot: Slot::from_le_bytes(slot_buf.try_into().unwrap()),
+            editor: Pubkey::new(&editor_buf[..32]),
+        }
+    }
+
+    // 数据打包
+    pub fn pack_into_slice(&self, dst: &mut [u8]) {
+        let (message_hash_buf, rest) = dst.split_at_mut(HASH_BYTES);
+        let (slot_buf, editor_buf) = rest.split_at_mut(8);
+        message_hash_buf.copy_from_slice(self.message_hash.as_ref());
+        slot_buf.copy_from_slice(&self.slot.to_le_bytes());
+        editor_buf[..32].copy_from_slice(self.editor.as_ref());
+    }
+}
 
 #[repr(C)]
 #[derive(Clone,Debug,Default,PartialEq)]
 pub struct HelloWorldState {
     pub account_key: Pubkey,
+    pub topic: String,
+    // 下一条历史版本写入的位置（历史版本是一个环形缓冲区）
+    pub history_head: u8,
+    // 已经保存的历史版本数量（最多 HISTORY_CAPACITY 条）
+    pub history_len: u8,
+    pub history: [MessageRevision; HISTORY_CAPACITY],
     pub message: String,
 }
 
 
-impl Sealed for HelloWorldState {}
-
+// 消息的所有者为空表示消息账户还没有被使用
 impl IsInitialized for HelloWorldState {
     fn is_initialized(&self) -> bool {
-        return true;
+        self.account_key != Pubkey::default()
     }
 }
 
-// 实现数据打包解包（注意：这个实现我们其实可以不用写直接在结构体上面添加 BorshSerialize和BorshDeserialize 注解即可）
-impl Pack for HelloWorldState {
-    // 数据总长度
-    const LEN: usize = 289;
+// 实现数据打包解包（注意：message是变长的，所以账户数据长度由message的长度决定，不能使用固定长度的Pack）
+impl HelloWorldState {
+    // 固定部分的长度（0-32个字节是公钥，后面1个字节是topic的长度，32个字节用来存储topic的数据，
+    // 1个字节是下一条历史版本写入的位置，1个字节是历史版本的数量，然后是历史版本的数据，最后4个字节是message的长度）
+    pub const HEADER_LEN: usize = 32 + 1 + MAX_TOPIC_LEN + 1 + 1 + MessageRevision::LEN * HISTORY_CAPACITY + 4;
+    // 公钥在账户数据中的位置（客户端可以按这个位置过滤某个用户的所有消息账户）
+    pub const ACCOUNT_KEY_OFFSET: usize = 0;
+
+    // 存储指定长度的message所需要的账户数据长度（创建账户时按这个长度分配）
+    pub fn packed_len(message_len: usize) -> usize {
+        Self::HEADER_LEN + message_len
+    }
+
+    // 存储当前数据所需要的账户数据长度
+    pub fn get_packed_len(&self) -> usize {
+        Self::packed_len(self.message.len())
+    }
+
+    // 保存一条历史版本（历史版本满了以后覆盖最早的一条）
+    pub fn push_revision(&mut self, revision: MessageRevision) {
+        self.history[self.history_head as usize] = revision;
+        self.history_head = ((self.history_head as usize + 1) % HISTORY_CAPACITY) as u8;
+        if (self.history_len as usize) < HISTORY_CAPACITY {
+            self.history_len += 1;
+        }
+    }
+
+    // 按时间顺序（从早到晚）获取所有历史版本
+    pub fn revisions(&self) -> Vec<MessageRevision> {
+        let len = self.history_len as usize;
+        let start = (self.history_head as usize + HISTORY_CAPACITY - len) % HISTORY_CAPACITY;
+        (0..len).map(|i| self.history[(start + i) % HISTORY_CAPACITY]).collect()
+    }
 
     // 数据解包
-    fn unpack_from_slice(src: &[u8]) -> Result<Self, ProgramError> {
-        // 复制数组0-289个字节
-        let src = array_ref![src,0,289];
-        // 拆分字节数组0-32个字节是公钥，第33个字节是message的长度，后面的256个字节用来存储message的数据
-        let (account_key_buf, message_len_buf, message_buf) = array_refs![src, 32, 1, 256];
+    pub fn unpack_from_slice(src: &[u8]) -> Result<Self, ProgramError> {
+        if src.len() < Self::HEADER_LEN {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        // 拆分字节数组0-32个字节是公钥，后面1个字节是topic的长度，32个字节是topic，然后是历史版本，4个字节是message的长度，剩下的字节用来存储message的数据
+        let (account_key_buf, rest) = src.split_at(32);
+        let (topic_len_buf, rest) = rest.split_at(1);
+        let (topic_buf, rest) = rest.split_at(MAX_TOPIC_LEN);
+        let (history_head_buf, rest) = rest.split_at(1);
+        let (history_len_buf, rest) = rest.split_at(1);
+        let (history_buf, rest) = rest.split_at(MessageRevision::LEN * HISTORY_CAPACITY);
+        let (message_len_buf, message_buf) = rest.split_at(4);
         // 转换公钥
-        let account_key = Pubkey::new_from_array(*account_key_buf);
+        let account_key = Pubkey::new(account_key_buf);
+        // 转换topic数据
+        let topic_buf = topic_buf.get(..topic_len_buf[0] as usize).ok_or(ProgramError::InvalidAccountData)?;
+        let topic = String::from(from_utf8(topic_buf).map_err(|_| HelloWorldError::InvalidUtf8)?);
+        // 转换历史版本
+        let history_head = history_head_buf[0];
+        let history_len = history_len_buf[0];
+        if history_head as usize >= HISTORY_CAPACITY || history_len as usize > HISTORY_CAPACITY {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        let mut history = [MessageRevision::default(); HISTORY_CAPACITY];
+        for (revision, revision_buf) in history.iter_mut().zip(history_buf.chunks_exact(MessageRevision::LEN)) {
+            *revision = MessageRevision::unpack_from_slice(revision_buf);
+        }
         // 转换message数据长度
-        let message_len = message_len_buf[0] as u8;
+        let message_len = u32::from_le_bytes(message_len_buf.try_into().unwrap()) as usize;
         // 截取字节数组得到实际message数据
-        let (msg_buf,_) = message_buf.split_at(message_len.into());
+        let msg_buf = message_buf.get(..message_len).ok_or(ProgramError::InvalidAccountData)?;
         // 转换message数据
-        let message = String::from(from_utf8(msg_buf).unwrap());
+        let message = String::from(from_utf8(msg_buf).map_err(|_| HelloWorldError::InvalidUtf8)?);
         Ok(HelloWorldState {
             account_key,
+            topic,
+            history_head,
+            history_len,
+            history,
             message
         })
     }
 
-    // 数据打包存储
-    fn pack_into_slice(&self, dst: &mut [u8]) {
-        // 修改字节数组0-289位置的数据
-        let dst = array_mut_ref![dst,0,289];
-        // 修改字节数组0-32个字节是公钥，第33个字节是message的长度，后面的256个字节用来存储message的数据
-        let (account_key_buf,message_len_buf,message_buf) = mut_array_refs![dst,32,1,256];
+    // 数据打包存储（message超过账户可存储的长度时返回错误，需要先调用Resize扩容）
+    pub fn pack_into_slice(&self, dst: &mut [u8]) -> Result<(), ProgramError> {
+        if self.topic.len() > MAX_TOPIC_LEN {
+            return Err(HelloWorldError::InvalidTopic.into());
+        }
+        if self.get_packed_len() > dst.len() {
+            return Err(HelloWorldError::MessageTooLong.into());
+        }
+        let (account_key_buf, rest) = dst.split_at_mut(32);
+        let (topic_len_buf, rest) = rest.split_at_mut(1);
+        let (topic_buf, rest) = rest.split_at_mut(MAX_TOPIC_LEN);
+        let (history_head_buf, rest) = rest.split_at_mut(1);
+        let (history_len_buf, rest) = rest.split_at_mut(1);
+        let (history_buf, rest) = rest.split_at_mut(MessageRevision::LEN * HISTORY_CAPACITY);
+        let (message_len_buf, message_buf) = rest.split_at_mut(4);
         // 将公钥转换成字节数组再填充上去
         account_key_buf.copy_from_slice(self.account_key.as_ref());
+        // 填充topic数据（剩余的字节清零）
+        topic_len_buf[0] = self.topic.len() as u8;
+        topic_buf.fill(0);
+        topic_buf[..self.topic.len()].copy_from_slice(self.topic.as_bytes());
+        // 填充历史版本
+        history_head_buf[0] = self.history_head;
+        history_len_buf[0] = self.history_len;
+        for (revision, revision_buf) in self.history.iter().zip(history_buf.chunks_exact_mut(MessageRevision::LEN)) {
+            revision.pack_into_slice(revision_buf);
+        }
         // 填充message数据的长度
-        message_len_buf[0] = self.message.len() as u8;
-        // 将message转换成字节数组再填充上去
-        //message_buf.copy_from_slice(&self.message.as_bytes());
-        message_buf[..self.message.len()].copy_from_slice(&self.message.as_bytes());
+        message_len_buf.copy_from_slice(&(self.message.len() as u32).to_le_bytes());
+        // 将message转换成字节数组再填充上去，剩余的字节清零（防止残留旧的message数据）
+        let (msg_buf, unused_buf) = message_buf.split_at_mut(self.message.len());
+        msg_buf.copy_from_slice(self.message.as_bytes());
+        unused_buf.fill(0);
+        Ok(())
+    }
+
+    // 获取Query函数返回的消息视图（不包含历史版本）
+    pub fn view(&self) -> MessageView {
+        MessageView {
+            account_key: self.account_key,
+            topic: self.topic.clone(),
+            message: self.message.clone(),
+        }
+    }
+}
+
+// Query函数的返回数据（返回数据最多1024个字节，放不下历史版本，历史版本通过QueryHistory函数查询）
+#[derive(Clone,Debug,Default,PartialEq)]
+pub struct MessageView {
+    pub account_key: Pubkey,
+    pub topic: String,
+    pub message: String,
+}
+
+impl MessageView {
+    // 固定部分的长度（32个字节是公钥，1个字节是topic的长度，32个字节是topic，4个字节是message的长度）
+    pub const HEADER_LEN: usize = 32 + 1 + MAX_TOPIC_LEN + 4;
+
+    // 打包后的数据长度
+    pub fn get_packed_len(&self) -> usize {
+        Self::HEADER_LEN + self.message.len()
+    }
+
+    // 数据解包（数据长度必须和message的长度一致）
+    pub fn unpack_from_slice(src: &[u8]) -> Result<Self, ProgramError> {
+        if src.len() < Self::HEADER_LEN {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        let (account_key_buf, rest) = src.split_at(32);
+        let (topic_len_buf, rest) = rest.split_at(1);
+        let (topic_buf, rest) = rest.split_at(MAX_TOPIC_LEN);
+        let (message_len_buf, message_buf) = rest.split_at(4);
+        let topic_buf = topic_buf.get(..topic_len_buf[0] as usize).ok_or(ProgramError::InvalidAccountData)?;
+        let message_len = u32::from_le_bytes(message_len_buf.try_into().unwrap()) as usize;
+        if message_buf.len() != message_len {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        Ok(MessageView {
+            account_key: Pubkey::new(account_key_buf),
+            topic: String::from(from_utf8(topic_buf).map_err(|_| HelloWorldError::InvalidUtf8)?),
+            message: String::from(from_utf8(message_buf).map_err(|_| HelloWorldError::InvalidUtf8)?),
+        })
+    }
+
+    // 数据打包（dst的长度必须等于 get_packed_len）
+    pub fn pack_into_slice(&self, dst: &mut [u8]) -> Result<(), ProgramError> {
+        if self.topic.len() > MAX_TOPIC_LEN {
+            return Err(HelloWorldError::InvalidTopic.into());
+        }
+        if self.get_packed_len() != dst.len() {
+            return Err(HelloWorldError::MessageTooLong.into());
+        }
+        let (account_key_buf, rest) = dst.split_at_mut(32);
+        let (topic_len_buf, rest) = rest.split_at_mut(1);
+        let (topic_buf, rest) = rest.split_at_mut(MAX_TOPIC_LEN);
+        let (message_len_buf, message_buf) = rest.split_at_mut(4);
+        account_key_buf.copy_from_slice(self.account_key.as_ref());
+        topic_len_buf[0] = self.topic.len() as u8;
+        topic_buf.fill(0);
+        topic_buf[..self.topic.len()].copy_from_slice(self.topic.as_bytes());
+        message_len_buf.copy_from_slice(&(self.message.len() as u32).to_le_bytes());
+        message_buf.copy_from_slice(self.message.as_bytes());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // message长度在账户数据中的位置
+    const MESSAGE_LEN_OFFSET: usize = HelloWorldState::HEADER_LEN - 4;
+
+    fn revision(n: u8) -> MessageRevision {
+        MessageRevision {
+            message_hash: Hash::new_from_array([n; HASH_BYTES]),
+            slot: n as Slot,
+            editor: Pubkey::new_from_array([n; 32]),
+        }
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn pack_unpack_state() {
+        let mut state = HelloWorldState {
+            account_key: Pubkey::new_unique(),
+            topic: String::from("config"),
+            message: String::from("hello 世界"),
+            ..HelloWorldState::default()
+        };
+        state.push_revision(revision(1));
+        // 账户按message的实际长度创建
+        let mut data = vec![0u8; state.get_packed_len()];
+        state.pack_into_slice(&mut data).unwrap();
+        assert_eq!(&data[..32], state.account_key.as_ref());
+        assert_eq!(data[32], 6);
+        assert_eq!(&data[MESSAGE_LEN_OFFSET..MESSAGE_LEN_OFFSET + 4], &(state.message.len() as u32).to_le_bytes());
+        assert_eq!(HelloWorldState::unpack_from_slice(&data).unwrap(), state);
+
+        // 存储更短的message时剩余的字节会被清零
+        let shorter = HelloWorldState {
+            message: String::from("hi"),
+            ..state.clone()
+        };
+        shorter.pack_into_slice(&mut data).unwrap();
+        assert!(data[HelloWorldState::packed_len(2)..].iter().all(|b| *b == 0));
+        assert_eq!(HelloWorldState::unpack_from_slice(&data).unwrap(), shorter);
+    }
+
+    #[test]
+    fn pack_message_too_long() {
+        let mut state = HelloWorldState {
+            account_key: Pubkey::new_unique(),
+            topic: String::from("config"),
+            message: "a".repeat(300),
+            ..HelloWorldState::default()
+        };
+        let mut data = vec![0u8; HelloWorldState::packed_len(299)];
+        assert_eq!(
+            state.pack_into_slice(&mut data),
+            Err(HelloWorldError::MessageTooLong.into())
+        );
+        // topic不能超过32个字节
+        state.message = String::new();
+        state.topic = "t".repeat(MAX_TOPIC_LEN + 1);
+        assert_eq!(
+            state.pack_into_slice(&mut data),
+            Err(HelloWorldError::InvalidTopic.into())
+        );
+    }
+
+    #[test]
+    fn unpack_invalid_data() {
+        // 账户数据长度不够
+        assert_eq!(
+            HelloWorldState::unpack_from_slice(&[0u8; HelloWorldState::HEADER_LEN - 1]),
+            Err(ProgramError::InvalidAccountData)
+        );
+        // message的长度超过账户数据长度
+        let mut data = vec![0u8; HelloWorldState::packed_len(2)];
+        data[MESSAGE_LEN_OFFSET..MESSAGE_LEN_OFFSET + 4].copy_from_slice(&3u32.to_le_bytes());
+        assert_eq!(HelloWorldState::unpack_from_slice(&data), Err(ProgramError::InvalidAccountData));
+        // message不是合法的UTF-8
+        data[MESSAGE_LEN_OFFSET..MESSAGE_LEN_OFFSET + 4].copy_from_slice(&2u32.to_le_bytes());
+        data[HelloWorldState::HEADER_LEN..].copy_from_slice(&[0xff, 0xfe]);
+        assert_eq!(HelloWorldState::unpack_from_slice(&data), Err(HelloWorldError::InvalidUtf8.into()));
+        // 历史版本的位置超出范围
+        data[HelloWorldState::HEADER_LEN..].copy_from_slice(b"hi");
+        data[65] = HISTORY_CAPACITY as u8;
+        assert_eq!(HelloWorldState::unpack_from_slice(&data), Err(ProgramError::InvalidAccountData));
+    }
+
+    #[test]
+    fn history_ring_wraps() {
+        let mut state = HelloWorldState::default();
+        assert!(state.revisions().is_empty());
+        for n in 1..=3 {
+            state.push_revision(revision(n));
+        }
+        assert_eq!(state.revisions(), vec![revision(1), revision(2), revision(3)]);
+
+        // 超过容量以后覆盖最早的历史版本
+        let total = HISTORY_CAPACITY as u8 + 3;
+        for n in 4..=total {
+            state.push_revision(revision(n));
+        }
+        assert_eq!(state.history_len as usize, HISTORY_CAPACITY);
+        assert_eq!(state.history_head, 3);
+        let expected: Vec<MessageRevision> = (4..=total).map(revision).collect();
+        assert_eq!(state.revisions(), expected);
+    }
+
+    #[test]
+    fn pack_unpack_view() {
+        let mut state = HelloWorldState {
+            account_key: Pubkey::new_unique(),
+            topic: String::from("config"),
+            message: "a".repeat(900),
+            ..HelloWorldState::default()
+        };
+        for n in 1..=(HISTORY_CAPACITY as u8) {
+            state.push_revision(revision(n));
+        }
+        // 完整的数据超过了返回数据的最大长度，消息视图不包含历史版本
+        assert!(state.get_packed_len() > solana_program::program::MAX_RETURN_DATA);
+        let view = state.view();
+        assert!(view.get_packed_len() <= solana_program::program::MAX_RETURN_DATA);
+        let mut data = vec![0u8; view.get_packed_len()];
+        view.pack_into_slice(&mut data).unwrap();
+        assert_eq!(&data[..32], state.account_key.as_ref());
+        assert_eq!(data[32], 6);
+        assert_eq!(MessageView::unpack_from_slice(&data).unwrap(), view);
+
+        // 数据长度和message的长度不一致
+        assert_eq!(MessageView::unpack_from_slice(&data[..data.len() - 1]), Err(ProgramError::InvalidAccountData));
+    }
+
+    #[test]
+    fn history_layout_is_deterministic() {
+        let mut state = HelloWorldState {
+            account_key: Pubkey::new_from_array([9; 32]),
+            topic: String::from("t"),
+            message: String::from("m"),
+            ..HelloWorldState::default()
+        };
+        for n in 1..=(HISTORY_CAPACITY as u8 + 1) {
+            state.push_revision(revision(n));
+        }
+        let mut data = vec![0xffu8; state.get_packed_len()];
+        state.pack_into_slice(&mut data).unwrap();
+        // 同样的数据打包到已经有数据的账户里结果一样
+        let mut clean = vec![0u8; state.get_packed_len()];
+        state.pack_into_slice(&mut clean).unwrap();
+        assert_eq!(data, clean);
+        // 历史版本从第67个字节开始，按环形缓冲区的位置存储（第1条已经被第9条覆盖）
+        assert_eq!(data[65], 1);
+        assert_eq!(data[66] as usize, HISTORY_CAPACITY);
+        let mut expected = vec![0u8; MessageRevision::LEN];
+        revision(HISTORY_CAPACITY as u8 + 1).pack_into_slice(&mut expected);
+        assert_eq!(&data[67..67 + MessageRevision::LEN], &expected[..]);
+        revision(2).pack_into_slice(&mut expected);
+        assert_eq!(&data[67 + MessageRevision::LEN..67 + 2 * MessageRevision::LEN], &expected[..]);
+        assert_eq!(HelloWorldState::unpack_from_slice(&data).unwrap(), state);
+    }
+}
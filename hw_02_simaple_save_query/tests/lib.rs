@@ -0,0 +1,312 @@
+use hw_02_simaple_save_query::{
+    error::HelloWorldError,
+    instruction,
+    processor::Processor,
+    state::{find_message_address, HelloWorldState, MessageRevision, MessageView},
+};
+use solana_program::{hash::hash, instruction::AccountMeta};
+use solana_program_test::*;
+use solana_sdk::{
+    account::Account,
+    hash::Hash,
+    instruction::{Instruction, InstructionError},
+    pubkey::Pubkey,
+    rent::Rent,
+    signature::{Keypair, Signer},
+    transaction::{Transaction, TransactionError},
+};
+
+// 保存返回数据的账户可以存储的最大数据长度（4个字节的长度 + 返回数据）
+const RETURN_DATA_ACCOUNT_LEN: usize = 4 + solana_program::program::MAX_RETURN_DATA;
+
+/**
+ * 模拟部署合约并给消息所有者预置一个消息账户
+ * 注意：原生模式下的 solana-program-test 不支持在CPI中修改账户数据长度，所以这里直接预置消息账户，
+ * 通过 Hello 创建消息账户以及 Resize 的测试需要使用 cargo test-bpf 执行
+ * @param owner    消息所有者
+ * @param topic    消息的topic
+ * @param message  消息账户里面已经存储的消息（None表示不预置消息账户）
+ * @param capacity 消息账户可以存储的最大消息长度
+ */
+async fn start_with_message(owner: &Keypair, topic: &str, message: Option<&str>, capacity: usize) -> (Pubkey, Pubkey, BanksClient, Keypair, Hash) {
+    let (program_test, program_id, message_pubkey) = program_test_with_message(owner, topic, message, capacity);
+    let (banks_client, payer, recent_blockhash) = program_test.start().await;
+    (program_id, message_pubkey, banks_client, payer, recent_blockhash)
+}
+
+// 创建部署了合约和消息账户的 ProgramTest（参数同 start_with_message）
+fn program_test_with_message(owner: &Keypair, topic: &str, message: Option<&str>, capacity: usize) -> (ProgramTest, Pubkey, Pubkey) {
+    let program_id = Pubkey::new_unique();
+    let (message_pubkey, _) = find_message_address(&program_id, &owner.pubkey(), topic);
+
+    let mut program_test = ProgramTest::new("hw_02_simaple_save_query", program_id, processor!(Processor::processor));
+    // 给消息所有者转一些SOL（创建消息账户和扩容时需要）
+    program_test.add_account(owner.pubkey(), Account {
+        lamports: 1_000_000_000,
+        ..Account::default()
+    });
+    if let Some(message) = message {
+        let state = HelloWorldState {
+            account_key: owner.pubkey(),
+            topic: String::from(topic),
+            message: String::from(message),
+            ..HelloWorldState::default()
+        };
+        let mut data = vec![0u8; HelloWorldState::packed_len(capacity)];
+        state.pack_into_slice(&mut data).unwrap();
+        program_test.add_account(message_pubkey, Account {
+            lamports: Rent::default().minimum_balance(data.len()),
+            data,
+            owner: program_id,
+            ..Account::default()
+        });
+    }
+    (program_test, program_id, message_pubkey)
+}
+
+/**
+ * 转发指令的测试合约（BanksClient 拿不到返回数据，所以通过CPI调用被测合约，再把返回数据保存到第一个账户里）
+ * 注意：原生模式下通过 processor! 部署的合约在CPI之后会访问已经失效的指令上下文，所以这里直接实现内置合约
+ * 账户顺序：保存返回数据的账户，被测合约，被测指令的账户
+ */
+fn return_data_probe(_first_instruction_account: usize, input: &[u8], invoke_context: &mut InvokeContext) -> Result<(), InstructionError> {
+    let transaction_context = &invoke_context.transaction_context;
+    let instruction_context = transaction_context.get_current_instruction_context()?;
+    let program_id = *instruction_context.get_instruction_account_key(transaction_context, 1)?;
+    let accounts = (2..instruction_context.get_number_of_instruction_accounts())
+        .map(|index| {
+            let account = instruction_context.try_borrow_instruction_account(transaction_context, index)?;
+            Ok(AccountMeta { pubkey: *account.get_key(), is_signer: account.is_signer(), is_writable: account.is_writable() })
+        })
+        .collect::<Result<Vec<_>, InstructionError>>()?;
+    invoke_context.native_invoke(Instruction { program_id, accounts, data: input.to_vec() }, &[])?;
+
+    let transaction_context = &invoke_context.transaction_context;
+    let (_, return_data) = transaction_context.get_return_data();
+    let instruction_context = transaction_context.get_current_instruction_context()?;
+    let mut account = instruction_context.try_borrow_instruction_account(transaction_context, 0)?;
+    let data = account.get_data_mut();
+    data[..4].copy_from_slice(&(return_data.len() as u32).to_le_bytes());
+    data[4..4 + return_data.len()].copy_from_slice(return_data);
+    Ok(())
+}
+
+/**
+ * 部署转发指令的测试合约和保存返回数据的账户
+ * @return (测试合约ID, 保存返回数据的账户地址)
+ */
+fn add_return_data_probe(program_test: &mut ProgramTest) -> (Pubkey, Pubkey) {
+    let probe_id = Pubkey::new_unique();
+    let return_data_pubkey = Pubkey::new_unique();
+    program_test.add_builtin_program("return_data_probe", probe_id, return_data_probe);
+    program_test.add_account(return_data_pubkey, Account {
+        lamports: Rent::default().minimum_balance(RETURN_DATA_ACCOUNT_LEN),
+        data: vec![0u8; RETURN_DATA_ACCOUNT_LEN],
+        owner: probe_id,
+        ..Account::default()
+    });
+    (probe_id, return_data_pubkey)
+}
+
+/**
+ * 通过测试合约调用被测指令并获取被测合约的返回数据
+ * @param probe 测试合约ID和保存返回数据的账户地址
+ */
+async fn call_for_return_data(banks_client: &mut BanksClient, signers: &[&Keypair], recent_blockhash: Hash, probe: (Pubkey, Pubkey), instruction: Instruction) -> Vec<u8> {
+    let (probe_id, return_data_pubkey) = probe;
+    let mut accounts = vec![
+        AccountMeta::new(return_data_pubkey, false),
+        AccountMeta::new_readonly(instruction.program_id, false),
+    ];
+    accounts.extend(instruction.accounts);
+    let instruction = Instruction { program_id: probe_id, accounts, data: instruction.data };
+    send(banks_client, signers, recent_blockhash, instruction).await.unwrap();
+    let account = banks_client.get_account(return_data_pubkey).await.unwrap().unwrap();
+    let len = u32::from_le_bytes(account.data[..4].try_into().unwrap()) as usize;
+    account.data[4..4 + len].to_vec()
+}
+
+/**
+ * 调用合约
+ * @param signers 交易签名者（第一个是手续费支付者）
+ */
+async fn send(banks_client: &mut BanksClient, signers: &[&Keypair], recent_blockhash: Hash, instruction: Instruction) -> Result<(), TransactionError> {
+    let mut transaction = Transaction::new_with_payer(&[instruction], Some(&signers[0].pubkey()));
+    transaction.sign(&signers.to_vec(), recent_blockhash);
+    banks_client.process_transaction(transaction).await.map_err(|e| e.unwrap())
+}
+
+// 获取消息账户里面的数据
+async fn get_state(banks_client: &mut BanksClient, message_pubkey: Pubkey) -> HelloWorldState {
+    let account = banks_client.get_account(message_pubkey).await.expect("get_account").expect("message account not found");
+    HelloWorldState::unpack_from_slice(&account.data).unwrap()
+}
+
+#[tokio::test]
+async fn test_hello_updates_message_and_history() {
+    let owner = Keypair::new();
+    let (program_id, message_pubkey, mut banks_client, payer, recent_blockhash) = start_with_message(&owner, "greeting", Some("hello"), 32).await;
+
+    let instruction = instruction::hello(&program_id, &owner.pubkey(), &message_pubkey, "greeting", "hello world!");
+    send(&mut banks_client, &[&payer, &owner], recent_blockhash, instruction).await.unwrap();
+
+    let state = get_state(&mut banks_client, message_pubkey).await;
+    assert_eq!(state.message, "hello world!");
+    assert_eq!(state.topic, "greeting");
+    // 旧消息的哈希被保存到历史版本里
+    let revisions = state.revisions();
+    assert_eq!(revisions.len(), 1);
+    assert_eq!(revisions[0].message_hash, hash(b"hello"));
+    assert_eq!(revisions[0].editor, owner.pubkey());
+}
+
+#[tokio::test]
+async fn test_hello_message_too_long() {
+    let owner = Keypair::new();
+    let (program_id, message_pubkey, mut banks_client, payer, recent_blockhash) = start_with_message(&owner, "greeting", Some("hello"), 5).await;
+
+    // 超过消息账户可以存储的长度需要先调用Resize扩容
+    let instruction = instruction::hello(&program_id, &owner.pubkey(), &message_pubkey, "greeting", "hello world!");
+    assert_eq!(
+        send(&mut banks_client, &[&payer, &owner], recent_blockhash, instruction).await.unwrap_err(),
+        TransactionError::InstructionError(0, InstructionError::Custom(HelloWorldError::MessageTooLong as u32))
+    );
+    assert_eq!(get_state(&mut banks_client, message_pubkey).await.message, "hello");
+}
+
+#[tokio::test]
+async fn test_topic_must_match_message_address() {
+    let owner = Keypair::new();
+    let (program_id, message_pubkey, mut banks_client, payer, recent_blockhash) = start_with_message(&owner, "greeting", Some("hello"), 32).await;
+
+    // topic和消息账户地址不匹配
+    let instruction = instruction::hello(&program_id, &owner.pubkey(), &message_pubkey, "other", "hi");
+    assert_eq!(
+        send(&mut banks_client, &[&payer, &owner], recent_blockhash, instruction).await.unwrap_err(),
+        TransactionError::InstructionError(0, InstructionError::InvalidSeeds)
+    );
+
+    // 其他用户不能使用别人的消息账户
+    let other = Keypair::new();
+    let instruction = instruction::query(&program_id, &other.pubkey(), &message_pubkey, "greeting");
+    assert_eq!(
+        send(&mut banks_client, &[&payer, &other], recent_blockhash, instruction).await.unwrap_err(),
+        TransactionError::InstructionError(0, InstructionError::InvalidSeeds)
+    );
+}
+
+#[tokio::test]
+async fn test_query_and_query_history() {
+    let owner = Keypair::new();
+    let (mut program_test, program_id, message_pubkey) = program_test_with_message(&owner, "greeting", Some("hello"), 32);
+    let probe = add_return_data_probe(&mut program_test);
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+    // 覆盖一次消息，产生一条历史版本
+    let instruction = instruction::hello(&program_id, &owner.pubkey(), &message_pubkey, "greeting", "hello world!");
+    send(&mut banks_client, &[&payer, &owner], recent_blockhash, instruction).await.unwrap();
+
+    let instruction = instruction::query(&program_id, &owner.pubkey(), &message_pubkey, "greeting");
+    let return_data = call_for_return_data(&mut banks_client, &[&payer, &owner], recent_blockhash, probe, instruction).await;
+    let view = MessageView::unpack_from_slice(&return_data).unwrap();
+    assert_eq!(view.account_key, owner.pubkey());
+    assert_eq!(view.topic, "greeting");
+    assert_eq!(view.message, "hello world!");
+
+    // 第一个字节是历史版本的数量，后面是按时间顺序排列的历史版本
+    let instruction = instruction::query_history(&program_id, &owner.pubkey(), &message_pubkey, "greeting");
+    let return_data = call_for_return_data(&mut banks_client, &[&payer, &owner], recent_blockhash, probe, instruction).await;
+    assert_eq!(return_data.len(), 1 + MessageRevision::LEN);
+    assert_eq!(return_data[0], 1);
+    let revision = MessageRevision::unpack_from_slice(&return_data[1..]);
+    assert_eq!(revision.message_hash, hash(b"hello"));
+    assert_eq!(revision.editor, owner.pubkey());
+    assert_eq!(get_state(&mut banks_client, message_pubkey).await.revisions(), vec![revision]);
+}
+
+#[tokio::test]
+async fn test_query_long_message() {
+    let owner = Keypair::new();
+    let message = "a".repeat(900);
+    let (mut program_test, program_id, message_pubkey) = program_test_with_message(&owner, "greeting", Some(&message), 900);
+    let probe = add_return_data_probe(&mut program_test);
+    let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+
+    // 完整的消息账户数据超过了返回数据的最大长度，Query只返回消息视图
+    assert!(HelloWorldState::packed_len(900) > solana_program::program::MAX_RETURN_DATA);
+    let instruction = instruction::query(&program_id, &owner.pubkey(), &message_pubkey, "greeting");
+    let return_data = call_for_return_data(&mut banks_client, &[&payer, &owner], recent_blockhash, probe, instruction).await;
+    let view = MessageView::unpack_from_slice(&return_data).unwrap();
+    assert_eq!(view.account_key, owner.pubkey());
+    assert_eq!(view.topic, "greeting");
+    assert_eq!(view.message, message);
+}
+
+#[tokio::test]
+async fn test_erase() {
+    let owner = Keypair::new();
+    let (program_id, message_pubkey, mut banks_client, payer, recent_blockhash) = start_with_message(&owner, "greeting", Some("hello"), 32).await;
+    let message_lamports = banks_client.get_balance(message_pubkey).await.unwrap();
+    let owner_lamports = banks_client.get_balance(owner.pubkey()).await.unwrap();
+
+    let instruction = instruction::erase(&program_id, &owner.pubkey(), &message_pubkey, "greeting");
+    send(&mut banks_client, &[&payer, &owner], recent_blockhash, instruction).await.unwrap();
+
+    // 消息账户的余额全部退还给所有者，消息账户被删除
+    assert_eq!(banks_client.get_account(message_pubkey).await.unwrap(), None);
+    assert_eq!(banks_client.get_balance(owner.pubkey()).await.unwrap(), owner_lamports + message_lamports);
+}
+
+#[cfg(feature = "test-bpf")]
+#[tokio::test]
+async fn test_hello_creates_message_account() {
+    let owner = Keypair::new();
+    let (program_id, message_pubkey, mut banks_client, payer, recent_blockhash) = start_with_message(&owner, "greeting", None, 0).await;
+
+    let instruction = instruction::hello(&program_id, &owner.pubkey(), &message_pubkey, "greeting", "hello");
+    send(&mut banks_client, &[&payer, &owner], recent_blockhash, instruction).await.unwrap();
+
+    // 消息账户按消息的实际长度创建
+    let account = banks_client.get_account(message_pubkey).await.unwrap().unwrap();
+    assert_eq!(account.owner, program_id);
+    assert_eq!(account.data.len(), HelloWorldState::packed_len(5));
+    let state = get_state(&mut banks_client, message_pubkey).await;
+    assert_eq!(state.account_key, owner.pubkey());
+    assert_eq!(state.message, "hello");
+    assert!(state.revisions().is_empty());
+}
+
+#[cfg(feature = "test-bpf")]
+#[tokio::test]
+async fn test_resize_then_hello() {
+    let owner = Keypair::new();
+    let (program_id, message_pubkey, mut banks_client, payer, recent_blockhash) = start_with_message(&owner, "greeting", Some("hello"), 5).await;
+
+    let instruction = instruction::resize(&program_id, &owner.pubkey(), &message_pubkey, 12);
+    send(&mut banks_client, &[&payer, &owner], recent_blockhash, instruction).await.unwrap();
+    let instruction = instruction::hello(&program_id, &owner.pubkey(), &message_pubkey, "greeting", "hello world!");
+    send(&mut banks_client, &[&payer, &owner], recent_blockhash, instruction).await.unwrap();
+
+    let account = banks_client.get_account(message_pubkey).await.unwrap().unwrap();
+    assert_eq!(account.data.len(), HelloWorldState::packed_len(12));
+    assert_eq!(account.lamports, Rent::default().minimum_balance(account.data.len()));
+    assert_eq!(get_state(&mut banks_client, message_pubkey).await.message, "hello world!");
+}
+
+#[cfg(feature = "test-bpf")]
+#[tokio::test]
+async fn test_hello_with_prefunded_message_account() {
+    let owner = Keypair::new();
+    let (program_id, message_pubkey, mut banks_client, payer, recent_blockhash) = start_with_message(&owner, "greeting", None, 0).await;
+
+    // 提前往消息账户的PDA地址转入1 lamport，不能阻止消息所有者创建消息账户
+    let instruction = solana_sdk::system_instruction::transfer(&payer.pubkey(), &message_pubkey, 1);
+    send(&mut banks_client, &[&payer], recent_blockhash, instruction).await.unwrap();
+    let instruction = instruction::hello(&program_id, &owner.pubkey(), &message_pubkey, "greeting", "hello");
+    send(&mut banks_client, &[&payer, &owner], recent_blockhash, instruction).await.unwrap();
+
+    let account = banks_client.get_account(message_pubkey).await.unwrap().unwrap();
+    assert_eq!(account.owner, program_id);
+    assert_eq!(account.data.len(), HelloWorldState::packed_len(5));
+    assert_eq!(account.lamports, Rent::default().minimum_balance(account.data.len()));
+    assert_eq!(get_state(&mut banks_client, message_pubkey).await.message, "hello");
+}
@@ -1,9 +1,15 @@
 use anchor_lang::prelude::*;
-use anchor_lang::{AccountDeserialize, AnchorDeserialize};
-use anchor_spl::token::{self, TokenAccount, Transfer};
+use anchor_spl::token::{self, Token, TokenAccount, Transfer};
+
+declare_id!("Fg6PaFpoGXkYsidMpWTK6W2BeZ7FEfcYkg476zPFsLnS");
+
+// 托管账户PDA地址的种子前缀
+pub const AUCTION_SEED: &[u8] = b"auction";
+// 出价记录PDA地址的种子前缀（种子是 [b"bid", 拍卖账户地址, 出价序号]）
+pub const BID_RECORD_SEED: &[u8] = b"bid";
 
 #[program]
-mod auction {
+pub mod auction {
     use super::*;
 
     // 创建拍卖（就是给用户创建合约账户信息）
@@ -15,13 +21,15 @@ mod auction {
         // 卖家地址
         auction.seller = *ctx.accounts.seller.key;
         // 卖家账户
-        auction.item_holder = *ctx.accounts.item_holder.to_account_info().key;
+        auction.item_holder = ctx.accounts.item_holder.key();
         // 第三方账户，就是中间账户用来暂时存钱存NFT（类似于Solidity合约地址账户）
-        auction.currency_holder = *ctx.accounts.currency_holder.to_account_info().key;
+        auction.currency_holder = ctx.accounts.currency_holder.key();
         // 出价者账户
         auction.bidder = *ctx.accounts.seller.key;
         // 价格
         auction.price = start_price;
+        // 托管账户PDA地址的bump（种子是 [b"auction", 卖家地址, 拍卖账户地址]，同一个卖家可以同时进行多个拍卖）
+        auction.bump = *ctx.bumps.get("auction_authority").unwrap();
         Ok(())
     }
 
@@ -35,35 +43,43 @@ mod auction {
 
         // if refund_receiver exist, return money back to it
         if auction.refund_receiver != Pubkey::default() {
-            let (_, seed) =
-                Pubkey::find_program_address(&[&auction.seller.to_bytes()], &ctx.program_id);
-            let seeds = &[auction.seller.as_ref(), &[seed]];
+            let auction_key = auction.key();
+            let seeds = &[AUCTION_SEED, auction.seller.as_ref(), auction_key.as_ref(), &[auction.bump]];
             let signer = &[&seeds[..]];
             let cpi_accounts = Transfer {
-                from: ctx.accounts.currency_holder.to_account_info().clone(),
-                to: ctx.accounts.ori_refund_receiver.to_account_info().clone(),
-                authority: ctx.accounts.currency_holder_auth.clone(),
+                from: ctx.accounts.currency_holder.to_account_info(),
+                to: ctx.accounts.ori_refund_receiver.to_account_info(),
+                authority: ctx.accounts.currency_holder_auth.to_account_info(),
             };
-            let cpi_program = ctx.accounts.token_program.clone();
+            let cpi_program = ctx.accounts.token_program.to_account_info();
             let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
             token::transfer(cpi_ctx, auction.price)?;
         }
 
         // transfer bid pirce to custodial currency holder
         let cpi_accounts = Transfer {
-            from: ctx.accounts.from.to_account_info().clone(),
-            to: ctx.accounts.currency_holder.to_account_info().clone(),
-            authority: ctx.accounts.from_auth.clone(),
+            from: ctx.accounts.from.to_account_info(),
+            to: ctx.accounts.currency_holder.to_account_info(),
+            authority: ctx.accounts.from_auth.to_account_info(),
         };
-        let cpi_program = ctx.accounts.token_program.clone();
+        let cpi_program = ctx.accounts.token_program.to_account_info();
         let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
         token::transfer(cpi_ctx, price)?;
 
-        // update auction info
+        // 记录这次出价（出价记录由出价者付租金创建）
         let auction = &mut ctx.accounts.auction;
+        let bid_record = &mut ctx.accounts.bid_record;
+        bid_record.auction = auction.key();
+        bid_record.bidder = *ctx.accounts.bidder.key;
+        bid_record.price = price;
+        bid_record.timestamp = Clock::get()?.unix_timestamp;
+        bid_record.sequence = auction.bid_count;
+
+        // update auction info
         auction.bidder = *ctx.accounts.bidder.key;
-        auction.refund_receiver = *ctx.accounts.from.to_account_info().key;
+        auction.refund_receiver = ctx.accounts.from.key();
         auction.price = price;
+        auction.bid_count += 1;
 
         Ok(())
     }
@@ -71,29 +87,28 @@ mod auction {
     pub fn close_auction(ctx: Context<CloseAuction>) -> Result<()> {
         let auction = &mut ctx.accounts.auction;
 
-        let (_, seed) =
-            Pubkey::find_program_address(&[&auction.seller.to_bytes()], &ctx.program_id);
-        let seeds = &[auction.seller.as_ref(), &[seed]];
+        let auction_key = auction.key();
+        let seeds = &[AUCTION_SEED, auction.seller.as_ref(), auction_key.as_ref(), &[auction.bump]];
         let signer = &[&seeds[..]];
 
         // item ownership transfer
         let cpi_accounts = Transfer {
-            from: ctx.accounts.item_holder.to_account_info().clone(),
-            to: ctx.accounts.item_receiver.to_account_info().clone(),
-            authority: ctx.accounts.item_holder_auth.clone(),
+            from: ctx.accounts.item_holder.to_account_info(),
+            to: ctx.accounts.item_receiver.to_account_info(),
+            authority: ctx.accounts.item_holder_auth.to_account_info(),
         };
-        let cpi_program = ctx.accounts.token_program.clone();
+        let cpi_program = ctx.accounts.token_program.to_account_info();
         let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
         token::transfer(cpi_ctx, ctx.accounts.item_holder.amount)?;
 
         // currency ownership transfer
         if ctx.accounts.currency_holder.amount >= auction.price {
             let cpi_accounts = Transfer {
-                from: ctx.accounts.currency_holder.to_account_info().clone(),
-                to: ctx.accounts.currency_receiver.to_account_info().clone(),
-                authority: ctx.accounts.currency_holder_auth.clone(),
+                from: ctx.accounts.currency_holder.to_account_info(),
+                to: ctx.accounts.currency_receiver.to_account_info(),
+                authority: ctx.accounts.currency_holder_auth.to_account_info(),
             };
-            let cpi_program = ctx.accounts.token_program.clone();
+            let cpi_program = ctx.accounts.token_program.to_account_info();
             let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
             token::transfer(cpi_ctx, auction.price)?;
         }
@@ -105,92 +120,128 @@ mod auction {
 
 #[derive(Accounts)]
 pub struct CreateAuction<'info> {
-    #[account(init)]
-    auction: Account<'info, Auction>,
-    seller: AccountInfo<'info>,
-    #[account("&item_holder.owner == &Pubkey::find_program_address(&[&seller.key.to_bytes()], &program_id).0")]
-    item_holder: Account<'info, TokenAccount>,
-    #[account("&currency_holder.owner == &Pubkey::find_program_address(&[&seller.key.to_bytes()], &program_id).0")]
-    currency_holder: Account<'info, TokenAccount>,
-    rent: Sysvar<'info, Rent>,
+    #[account(init, payer = seller, space = 8 + Auction::LEN)]
+    pub auction: Account<'info, Auction>,
+    #[account(mut)]
+    pub seller: Signer<'info>,
+    /// CHECK: 托管账户的PDA地址（不存储数据，只用来校验托管账户的所有者）
+    #[account(seeds = [AUCTION_SEED, seller.key().as_ref(), auction.key().as_ref()], bump)]
+    pub auction_authority: UncheckedAccount<'info>,
+    #[account(constraint = item_holder.owner == auction_authority.key())]
+    pub item_holder: Account<'info, TokenAccount>,
+    #[account(constraint = currency_holder.owner == auction_authority.key())]
+    pub currency_holder: Account<'info, TokenAccount>,
+    pub system_program: Program<'info, System>,
 }
 
 #[derive(Accounts)]
 pub struct Bid<'info> {
-    #[account(mut, "auction.ongoing")]
-    auction: Account<'info, Auction>,
-    #[account(signer)]
-    bidder: AccountInfo<'info>,
+    #[account(mut, constraint = auction.ongoing)]
+    pub auction: Account<'info, Auction>,
+    #[account(mut)]
+    pub bidder: Signer<'info>,
     #[account(
-    mut,
-    "from.mint == currency_holder.mint",
-    "&from.owner == from_auth.key"
+        init,
+        payer = bidder,
+        space = 8 + BidRecord::LEN,
+        seeds = [BID_RECORD_SEED, auction.key().as_ref(), &auction.bid_count.to_le_bytes()],
+        bump
     )]
-    from: Account<'info, TokenAccount>,
-    #[account(signer)]
-    from_auth: AccountInfo<'info>,
+    pub bid_record: Account<'info, BidRecord>,
     #[account(
-    mut,
-    "currency_holder.to_account_info().key == &auction.currency_holder"
+        mut,
+        constraint = from.mint == currency_holder.mint,
+        constraint = from.owner == from_auth.key()
     )]
-    currency_holder: Account<'info, TokenAccount>,
-    #[account("&currency_holder.owner == currency_holder_auth.key")]
-    currency_holder_auth: AccountInfo<'info>,
-    #[account(mut, "ori_refund_receiver.key == &Pubkey::default() || ori_refund_receiver.key == &auction.refund_receiver")]
-    ori_refund_receiver: AccountInfo<'info>,
-    #[account("token_program.key == &token::ID")]
-    token_program: AccountInfo<'info>,
+    pub from: Account<'info, TokenAccount>,
+    pub from_auth: Signer<'info>,
+    #[account(mut, address = auction.currency_holder)]
+    pub currency_holder: Account<'info, TokenAccount>,
+    /// CHECK: 托管账户的PDA地址（退款时由合约签名）
+    #[account(seeds = [AUCTION_SEED, auction.seller.as_ref(), auction.key().as_ref()], bump = auction.bump)]
+    pub currency_holder_auth: UncheckedAccount<'info>,
+    /// CHECK: 上一个出价者的退款账户，转账时由Token合约校验
+    /// 还没有人出价时不退款，可以传任意可写的账户（默认地址就是系统合约的地址，交易里面总是只读的，不能满足 mut 约束）
+    #[account(
+        mut,
+        constraint = auction.refund_receiver == Pubkey::default() || ori_refund_receiver.key() == auction.refund_receiver
+    )]
+    pub ori_refund_receiver: UncheckedAccount<'info>,
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
 }
 
 #[derive(Accounts)]
 pub struct CloseAuction<'info> {
-    #[account(mut, "auction.ongoing")]
-    auction: Account<'info, Auction>,
-    #[account(signer)]
-    seller: AccountInfo<'info>,
-    #[account(
-    mut,
-    "item_holder.to_account_info().key == &auction.item_holder"
-    "&item_holder.owner == &Pubkey::find_program_address(&[&seller.key.to_bytes()], &program_id).0"
-    )]
-    item_holder: Account<'info, TokenAccount>,
-    item_holder_auth: AccountInfo<'info>,
-    #[account(mut, "item_receiver.owner == auction.bidder")]
-    item_receiver: Account<'info, TokenAccount>,
-    #[account(
-    mut,
-    "currency_holder.to_account_info().key == &auction.currency_holder"
-    "&currency_holder.owner == &Pubkey::find_program_address(&[&seller.key.to_bytes()], &program_id).0"
-    )]
-    currency_holder: Account<'info, TokenAccount>,
-    #[account("&currency_holder.owner == currency_holder_auth.key")]
-    currency_holder_auth: AccountInfo<'info>,
+    #[account(mut, has_one = seller, constraint = auction.ongoing)]
+    pub auction: Account<'info, Auction>,
+    pub seller: Signer<'info>,
+    #[account(mut, address = auction.item_holder)]
+    pub item_holder: Account<'info, TokenAccount>,
+    /// CHECK: 托管账户的PDA地址（转出拍品时由合约签名）
+    #[account(seeds = [AUCTION_SEED, seller.key().as_ref(), auction.key().as_ref()], bump = auction.bump)]
+    pub item_holder_auth: UncheckedAccount<'info>,
+    #[account(mut, constraint = item_receiver.owner == auction.bidder)]
+    pub item_receiver: Account<'info, TokenAccount>,
+    #[account(mut, address = auction.currency_holder)]
+    pub currency_holder: Account<'info, TokenAccount>,
+    /// CHECK: 托管账户的PDA地址（转出出价代币时由合约签名）
+    #[account(seeds = [AUCTION_SEED, seller.key().as_ref(), auction.key().as_ref()], bump = auction.bump)]
+    pub currency_holder_auth: UncheckedAccount<'info>,
     #[account(mut)]
-    currency_receiver: Account<'info, TokenAccount>,
-    #[account("token_program.key == &token::ID")]
-    token_program: AccountInfo<'info>,
+    pub currency_receiver: Account<'info, TokenAccount>,
+    pub token_program: Program<'info, Token>,
 }
 
 // 拍卖信息（注意：该信息存储在卖家的地址账户上）
 #[account]
 pub struct Auction {
     // 是否在拍卖中
-    ongoing: bool,
+    pub ongoing: bool,
     // 卖家地址
-    seller: Pubkey,
+    pub seller: Pubkey,
     // 卖家账户
-    item_holder: Pubkey,
+    pub item_holder: Pubkey,
     // 第三方账户，就是中间账户用来暂时存钱存NFT（类似于Solidity合约地址账户）
-    currency_holder: Pubkey,
+    pub currency_holder: Pubkey,
     // 出价者账户
-    bidder: Pubkey,
+    pub bidder: Pubkey,
     // 买家账户
-    refund_receiver: Pubkey,
+    pub refund_receiver: Pubkey,
     // 价格
-    price: u64,
+    pub price: u64,
+    // 托管账户PDA地址的bump
+    pub bump: u8,
+    // 出价次数（也是下一个出价记录的序号）
+    pub bid_count: u64,
+}
+
+impl Auction {
+    // 数据长度（不包括8个字节的账户类型标识）
+    pub const LEN: usize = 1 + 32 * 5 + 8 + 1 + 8;
+}
+
+// 出价记录（每次出价一个PDA账户，可以还原完整的出价顺序）
+#[account]
+pub struct BidRecord {
+    // 出价的拍卖
+    pub auction: Pubkey,
+    // 出价者地址
+    pub bidder: Pubkey,
+    // 出价
+    pub price: u64,
+    // 出价时间
+    pub timestamp: i64,
+    // 出价序号（从0开始）
+    pub sequence: u64,
+}
+
+impl BidRecord {
+    // 数据长度（不包括8个字节的账户类型标识）
+    pub const LEN: usize = 32 + 32 + 8 + 8 + 8;
 }
 
-#[error]
+#[error_code]
 pub enum AuctionErr {
     #[msg("your bid price is too low")]
     BidPirceTooLow,
@@ -0,0 +1,394 @@
+use anchor_lang::{
+    error::ErrorCode,
+    prelude::Pubkey,
+    solana_program::{program_option::COption, program_pack::Pack},
+    AccountDeserialize, InstructionData, ToAccountMetas,
+};
+use anchor_spl::token::spl_token::{
+    self,
+    state::{Account as TokenAccount, AccountState, Mint},
+};
+use hw_03_anchor_auction::{accounts, instruction, Auction, AUCTION_SEED, BID_RECORD_SEED, ID};
+use solana_program_test::*;
+use solana_sdk::{
+    account::Account,
+    instruction::{Instruction, InstructionError},
+    rent::Rent,
+    signature::{Keypair, Signer},
+    system_program,
+    transaction::{Transaction, TransactionError},
+};
+
+mod native;
+
+// 每个出价者初始拥有的出价代币数量
+const INITIAL_BALANCE: u64 = 1_000;
+
+/**
+ * 预置账户（账户数据是打包后的 data）
+ * @param address 账户地址
+ * @param owner   账户所属的合约
+ */
+fn add_packable_account<T: Pack>(program_test: &mut ProgramTest, address: Pubkey, data: T, owner: &Pubkey) {
+    let mut buf = vec![0u8; T::LEN];
+    T::pack(data, &mut buf).unwrap();
+    program_test.add_account(address, Account {
+        lamports: Rent::default().minimum_balance(T::LEN),
+        data: buf,
+        owner: *owner,
+        ..Account::default()
+    });
+}
+
+// 预置代币账户
+fn add_token_account(program_test: &mut ProgramTest, mint: &Pubkey, owner: &Pubkey, amount: u64) -> Pubkey {
+    let address = Pubkey::new_unique();
+    add_packable_account(program_test, address, TokenAccount {
+        mint: *mint,
+        owner: *owner,
+        amount,
+        state: AccountState::Initialized,
+        ..TokenAccount::default()
+    }, &spl_token::id());
+    address
+}
+
+// 预置代币
+fn add_mint(program_test: &mut ProgramTest, decimals: u8) -> Pubkey {
+    let address = Pubkey::new_unique();
+    add_packable_account(program_test, address, Mint {
+        mint_authority: COption::Some(Pubkey::new_unique()),
+        supply: u64::MAX,
+        decimals,
+        is_initialized: true,
+        freeze_authority: COption::None,
+    }, &spl_token::id());
+    address
+}
+
+// 原生模式运行合约（cargo test 不需要编译BPF程序，见 native 模块）
+fn process_native(_first_instruction_account: usize, input: &[u8], invoke_context: &mut InvokeContext) -> Result<(), InstructionError> {
+    native::process_instruction(hw_03_anchor_auction::entry, input, invoke_context)
+}
+
+// 原生模式运行Token合约（测试环境自带的Token合约是BPF程序）
+fn process_spl_token(_first_instruction_account: usize, input: &[u8], invoke_context: &mut InvokeContext) -> Result<(), InstructionError> {
+    native::process_instruction(spl_token::processor::Processor::process, input, invoke_context)
+}
+
+// 出价者以及出价者的代币账户
+struct Bidder {
+    keypair: Keypair,
+    item_account: Pubkey,
+    currency_account: Pubkey,
+}
+
+// 拍卖测试环境（拍品和出价代币的托管账户都属于拍卖的PDA地址，拍品已经放进托管账户）
+struct AuctionTest {
+    context: ProgramTestContext,
+    // 拍卖账户由合约创建（卖家付租金），创建时需要拍卖账户签名
+    auction: Keypair,
+    seller: Keypair,
+    pda: Pubkey,
+    bump: u8,
+    item_holder: Pubkey,
+    currency_holder: Pubkey,
+    seller_item_account: Pubkey,
+    seller_currency_account: Pubkey,
+    bidders: Vec<Bidder>,
+}
+
+impl AuctionTest {
+    /**
+     * 模拟部署合约并预置拍卖需要的账户
+     * @param bidder_count 出价者数量
+     */
+    async fn start(bidder_count: usize) -> Self {
+        let mut program_test = ProgramTest::default();
+        if cfg!(feature = "test-bpf") {
+            program_test.add_program("hw_03_anchor_auction", ID, None);
+        } else {
+            program_test.add_builtin_program("hw_03_anchor_auction", ID, process_native);
+            program_test.add_builtin_program("spl_token", spl_token::id(), process_spl_token);
+        }
+
+        let seller = Keypair::new();
+        // 卖家支付拍卖账户的租金
+        program_test.add_account(seller.pubkey(), Account { lamports: 1_000_000_000, ..Account::default() });
+        let auction = Keypair::new();
+        let (pda, bump) =
+            Pubkey::find_program_address(&[AUCTION_SEED, seller.pubkey().as_ref(), auction.pubkey().as_ref()], &ID);
+        let item = add_mint(&mut program_test, 0);
+        let currency = add_mint(&mut program_test, 6);
+        let item_holder = add_token_account(&mut program_test, &item, &pda, 1);
+        let currency_holder = add_token_account(&mut program_test, &currency, &pda, 0);
+        let seller_item_account = add_token_account(&mut program_test, &item, &seller.pubkey(), 0);
+        let seller_currency_account = add_token_account(&mut program_test, &currency, &seller.pubkey(), 0);
+        let bidders = (0..bidder_count)
+            .map(|_| {
+                let keypair = Keypair::new();
+                // 出价者支付出价记录账户的租金
+                program_test.add_account(keypair.pubkey(), Account { lamports: 1_000_000_000, ..Account::default() });
+                let item_account = add_token_account(&mut program_test, &item, &keypair.pubkey(), 0);
+                let currency_account = add_token_account(&mut program_test, &currency, &keypair.pubkey(), INITIAL_BALANCE);
+                Bidder { keypair, item_account, currency_account }
+            })
+            .collect();
+
+        let context = program_test.start_with_context().await;
+        AuctionTest {
+            context,
+            auction,
+            seller,
+            pda,
+            bump,
+            item_holder,
+            currency_holder,
+            seller_item_account,
+            seller_currency_account,
+            bidders,
+        }
+    }
+
+    /**
+     * 发送交易（手续费由测试环境的 payer 支付）
+     * @param signers 除了 payer 以外的签名者
+     */
+    async fn send(&mut self, signers: &[&Keypair], instruction: Instruction) -> Result<(), TransactionError> {
+        let mut all_signers = vec![&self.context.payer];
+        all_signers.extend_from_slice(signers);
+        let transaction = Transaction::new_signed_with_payer(
+            &[instruction],
+            Some(&self.context.payer.pubkey()),
+            &all_signers,
+            self.context.last_blockhash,
+        );
+        self.context.banks_client.process_transaction(transaction).await.map_err(|e| e.unwrap())
+    }
+
+    async fn get_auction(&mut self) -> Auction {
+        let account = self.context.banks_client.get_account(self.auction.pubkey()).await.unwrap().unwrap();
+        Auction::try_deserialize(&mut account.data.as_slice()).unwrap()
+    }
+
+    async fn token_balance(&mut self, address: Pubkey) -> u64 {
+        let account = self.context.banks_client.get_account(address).await.unwrap().unwrap();
+        TokenAccount::unpack(&account.data).unwrap().amount
+    }
+
+    // 第 sequence 次出价的出价记录地址
+    fn bid_record(&self, sequence: u64) -> Pubkey {
+        Pubkey::find_program_address(&[BID_RECORD_SEED, self.auction.pubkey().as_ref(), &sequence.to_le_bytes()], &ID).0
+    }
+
+    async fn create_auction(&mut self, start_price: u64) -> Result<(), TransactionError> {
+        let instruction = Instruction {
+            program_id: ID,
+            accounts: accounts::CreateAuction {
+                auction: self.auction.pubkey(),
+                seller: self.seller.pubkey(),
+                auction_authority: self.pda,
+                item_holder: self.item_holder,
+                currency_holder: self.currency_holder,
+                system_program: system_program::id(),
+            }
+            .to_account_metas(None),
+            data: instruction::CreateAuction { start_price }.data(),
+        };
+        let auction = Keypair::from_bytes(&self.auction.to_bytes()).unwrap();
+        let seller = Keypair::from_bytes(&self.seller.to_bytes()).unwrap();
+        self.send(&[&auction, &seller], instruction).await
+    }
+
+    /**
+     * 出价
+     * @param ori_refund_receiver 上一个出价者的退款账户（还没有人出价时传 None，不退款，使用出价者自己的代币账户）
+     */
+    async fn bid(&mut self, bidder: usize, price: u64, ori_refund_receiver: Option<Pubkey>) -> Result<(), TransactionError> {
+        let sequence = self.get_auction().await.bid_count;
+        let bid_record = self.bid_record(sequence);
+        let bidder = &self.bidders[bidder];
+        let ori_refund_receiver = ori_refund_receiver.unwrap_or(bidder.currency_account);
+        let instruction = Instruction {
+            program_id: ID,
+            accounts: accounts::Bid {
+                auction: self.auction.pubkey(),
+                bidder: bidder.keypair.pubkey(),
+                bid_record,
+                from: bidder.currency_account,
+                from_auth: bidder.keypair.pubkey(),
+                currency_holder: self.currency_holder,
+                currency_holder_auth: self.pda,
+                ori_refund_receiver,
+                token_program: spl_token::id(),
+                system_program: system_program::id(),
+            }
+            .to_account_metas(None),
+            data: instruction::Bid { price }.data(),
+        };
+        let keypair = Keypair::from_bytes(&bidder.keypair.to_bytes()).unwrap();
+        self.send(&[&keypair], instruction).await
+    }
+
+    // 卖家关闭拍卖
+    async fn close_auction(&mut self, item_receiver: Pubkey) -> Result<(), TransactionError> {
+        let seller = Keypair::from_bytes(&self.seller.to_bytes()).unwrap();
+        self.close_auction_as(&seller, item_receiver).await
+    }
+
+    /**
+     * 关闭拍卖
+     * @param seller        签名的卖家
+     * @param item_receiver 接收拍品的代币账户（必须属于当前的出价者，没有人出价时是卖家）
+     */
+    async fn close_auction_as(&mut self, seller: &Keypair, item_receiver: Pubkey) -> Result<(), TransactionError> {
+        let instruction = Instruction {
+            program_id: ID,
+            accounts: accounts::CloseAuction {
+                auction: self.auction.pubkey(),
+                seller: seller.pubkey(),
+                item_holder: self.item_holder,
+                item_holder_auth: self.pda,
+                item_receiver,
+                currency_holder: self.currency_holder,
+                currency_holder_auth: self.pda,
+                currency_receiver: self.seller_currency_account,
+                token_program: spl_token::id(),
+            }
+            .to_account_metas(None),
+            data: instruction::CloseAuction.data(),
+        };
+        self.send(&[seller], instruction).await
+    }
+}
+
+// 合约返回的自定义错误
+fn auction_error(error: hw_03_anchor_auction::AuctionErr) -> TransactionError {
+    TransactionError::InstructionError(0, InstructionError::Custom(anchor_lang::error::ERROR_CODE_OFFSET + error as u32))
+}
+
+#[tokio::test]
+async fn test_create_auction() {
+    let mut test = AuctionTest::start(0).await;
+    test.create_auction(100).await.unwrap();
+
+    // 卖家自己是第一个出价者，还没有退款账户
+    let seller = test.seller.pubkey();
+    let auction = test.get_auction().await;
+    assert!(auction.ongoing);
+    assert_eq!(auction.seller, seller);
+    assert_eq!(auction.item_holder, test.item_holder);
+    assert_eq!(auction.currency_holder, test.currency_holder);
+    assert_eq!(auction.bidder, seller);
+    assert_eq!(auction.refund_receiver, Pubkey::default());
+    assert_eq!(auction.price, 100);
+    assert_eq!(auction.bump, test.bump);
+    assert_eq!(auction.bid_count, 0);
+}
+
+#[tokio::test]
+async fn test_create_auction_requires_pda_holders() {
+    let mut test = AuctionTest::start(1).await;
+
+    // 托管账户必须属于拍卖的PDA地址
+    test.currency_holder = test.bidders[0].currency_account;
+    assert_eq!(
+        test.create_auction(100).await.unwrap_err(),
+        TransactionError::InstructionError(0, InstructionError::Custom(ErrorCode::ConstraintRaw as u32))
+    );
+}
+
+#[tokio::test]
+async fn test_bid_price_too_low() {
+    let mut test = AuctionTest::start(1).await;
+    test.create_auction(100).await.unwrap();
+
+    // 出价必须高于当前价格
+    assert_eq!(test.bid(0, 100, None).await.unwrap_err(), auction_error(hw_03_anchor_auction::AuctionErr::BidPirceTooLow));
+    let auction = test.get_auction().await;
+    assert_eq!(auction.price, 100);
+    assert_eq!(auction.bid_count, 0);
+}
+
+#[tokio::test]
+async fn test_close_auction_requires_seller() {
+    let mut test = AuctionTest::start(1).await;
+    test.create_auction(100).await.unwrap();
+
+    // 只有卖家可以关闭拍卖
+    let impostor = Keypair::from_bytes(&test.bidders[0].keypair.to_bytes()).unwrap();
+    let item_account = test.bidders[0].item_account;
+    assert_eq!(
+        test.close_auction_as(&impostor, item_account).await.unwrap_err(),
+        TransactionError::InstructionError(0, InstructionError::Custom(ErrorCode::ConstraintHasOne as u32))
+    );
+    assert!(test.get_auction().await.ongoing);
+}
+
+#[tokio::test]
+async fn test_first_bid_has_no_refund() {
+    let mut test = AuctionTest::start(1).await;
+    test.create_auction(100).await.unwrap();
+
+    // 第一个出价不需要退款（卖家没有托管起拍价）
+    test.bid(0, 120, None).await.unwrap();
+    let (bidder, currency_account) = (test.bidders[0].keypair.pubkey(), test.bidders[0].currency_account);
+    assert_eq!(test.token_balance(currency_account).await, INITIAL_BALANCE - 120);
+    assert_eq!(test.token_balance(test.currency_holder).await, 120);
+    let auction = test.get_auction().await;
+    assert_eq!(auction.bidder, bidder);
+    assert_eq!(auction.refund_receiver, currency_account);
+    assert_eq!(auction.price, 120);
+    assert_eq!(auction.bid_count, 1);
+}
+
+#[tokio::test]
+async fn test_higher_bid_refunds_previous_bidder() {
+    let mut test = AuctionTest::start(2).await;
+    test.create_auction(100).await.unwrap();
+    test.bid(0, 120, None).await.unwrap();
+
+    // 上一个出价者的出价退回到他的代币账户
+    let (first_account, second_account) = (test.bidders[0].currency_account, test.bidders[1].currency_account);
+    // 退款账户必须是上一个出价者的退款账户
+    assert_eq!(
+        test.bid(1, 150, Some(second_account)).await.unwrap_err(),
+        TransactionError::InstructionError(0, InstructionError::Custom(ErrorCode::ConstraintRaw as u32))
+    );
+    test.bid(1, 150, Some(first_account)).await.unwrap();
+    assert_eq!(test.token_balance(first_account).await, INITIAL_BALANCE);
+    assert_eq!(test.token_balance(second_account).await, INITIAL_BALANCE - 150);
+    assert_eq!(test.token_balance(test.currency_holder).await, 150);
+    let auction = test.get_auction().await;
+    assert_eq!(auction.refund_receiver, second_account);
+    assert_eq!(auction.bid_count, 2);
+}
+
+#[tokio::test]
+async fn test_close_auction_pays_seller() {
+    let mut test = AuctionTest::start(1).await;
+    test.create_auction(100).await.unwrap();
+    // 出价者出价 120（出价转进托管账户）
+    test.bid(0, 120, None).await.unwrap();
+
+    // 托管的钱够支付成交价：拍品给出价者，钱给卖家
+    let item_account = test.bidders[0].item_account;
+    test.close_auction(item_account).await.unwrap();
+    assert_eq!(test.token_balance(item_account).await, 1);
+    assert_eq!(test.token_balance(test.seller_currency_account).await, 120);
+    assert_eq!(test.token_balance(test.currency_holder).await, 0);
+    assert!(!test.get_auction().await.ongoing);
+}
+
+#[tokio::test]
+async fn test_close_auction_without_bids() {
+    let mut test = AuctionTest::start(0).await;
+    test.create_auction(100).await.unwrap();
+
+    // 没有人出价，托管的钱不够起拍价：拍品退还给卖家，不转账
+    let seller_item_account = test.seller_item_account;
+    test.close_auction(seller_item_account).await.unwrap();
+    assert_eq!(test.token_balance(seller_item_account).await, 1);
+    assert_eq!(test.token_balance(test.seller_currency_account).await, 0);
+    assert!(!test.get_auction().await.ongoing);
+}
@@ -1,6 +1,7 @@
-use crate::processor::Processor;
+use crate::{error::AuctionError, processor::Processor};
 use solana_program::{
-    account_info::AccountInfo, entrypoint, entrypoint::ProgramResult, pubkey::Pubkey,
+    account_info::AccountInfo, entrypoint, entrypoint::ProgramResult,
+    program_error::PrintProgramError, pubkey::Pubkey,
 };
 // 该文件是合约入口
 entrypoint!(process_instruction);
@@ -11,5 +12,9 @@ fn process_instruction(
     accounts: &[AccountInfo],
     instruction_data: &[u8],
 ) -> ProgramResult {
-    Processor::process(program_id, accounts, instruction_data)
+    if let Err(error) = Processor::process(program_id, accounts, instruction_data) {
+        error.print::<AuctionError>();
+        return Err(error);
+    }
+    Ok(())
 }
\ No newline at end of file
@@ -0,0 +1,110 @@
+use solana_program::{decode_error::DecodeError,
+                     program_error::ProgramError,
+                     msg,
+                     program_error::PrintProgramError};
+use thiserror::Error;
+use num_traits::FromPrimitive;
+use num_derive::FromPrimitive;
+
+/// Errors that may be returned by the auction program.
+#[derive(Clone, Debug, Eq, Error, FromPrimitive, PartialEq)]
+pub enum AuctionError {
+    /// Auction account is already initialized
+    #[error("Auction already initialized")]
+    AlreadyInitialized,
+    /// Auction is not accepting bids
+    #[error("Auction is not active")]
+    AuctionNotActive,
+    /// Auction status can not move to the requested status
+    #[error("Invalid auction status transition")]
+    InvalidStatusTransition,
+    /// Auction end time is not after the start time
+    #[error("Invalid auction time window")]
+    InvalidTimeWindow,
+    /// Bidding has not started yet
+    #[error("Auction not started")]
+    AuctionNotStarted,
+    /// Bidding is already over
+    #[error("Auction ended")]
+    AuctionEnded,
+    /// Auction can not be closed before the end time
+    #[error("Auction not ended")]
+    AuctionNotEnded,
+    /// Bid does not beat the current price by the minimum increment
+    #[error("Bid too low")]
+    BidTooLow,
+    /// Revealed reserve price does not match the commitment
+    #[error("Invalid reserve reveal")]
+    InvalidReserveReveal,
+    /// Reserve price must be revealed before the reveal deadline
+    #[error("Reserve not revealed")]
+    ReserveNotRevealed,
+    /// Auction can not be cancelled after someone placed a bid
+    #[error("Auction has bids")]
+    AuctionHasBids,
+    /// Instruction is not supported by this kind of auction
+    #[error("Wrong auction kind")]
+    WrongAuctionKind,
+    /// Dutch auction prices are inconsistent
+    #[error("Invalid price schedule")]
+    InvalidPriceSchedule,
+    /// Current price is above the buyer's limit
+    #[error("Price above limit")]
+    PriceAboveLimit,
+    /// Revealed bid does not match its commitment or was already revealed
+    #[error("Invalid bid reveal")]
+    InvalidBidReveal,
+    /// Revealed price is larger than the escrowed deposit
+    #[error("Deposit too low")]
+    DepositTooLow,
+    /// Item quantity is zero or a bid asks for more units than the auction sells
+    #[error("Invalid quantity")]
+    InvalidQuantity,
+    /// Royalty and platform fee exceed 100% or a fee has no recipient
+    #[error("Invalid fee split")]
+    InvalidFeeSplit,
+    /// Refund claim record holds nothing to withdraw
+    #[error("Nothing to claim")]
+    NothingToClaim,
+}
+
+impl From<AuctionError> for ProgramError {
+    fn from(e: AuctionError) -> Self {
+        ProgramError::Custom(e as u32)
+    }
+}
+impl<T> DecodeError<T> for AuctionError {
+    fn type_of() -> &'static str {
+        "AuctionError"
+    }
+}
+
+
+impl PrintProgramError for AuctionError {
+    fn print<E>(&self)
+        where
+            E: 'static + std::error::Error + DecodeError<E> + PrintProgramError + FromPrimitive,
+    {
+        match self {
+            AuctionError::AlreadyInitialized => msg!("Auction already initialized"),
+            AuctionError::AuctionNotActive => msg!("Auction is not active"),
+            AuctionError::InvalidStatusTransition => msg!("Invalid auction status transition"),
+            AuctionError::InvalidTimeWindow => msg!("Invalid auction time window"),
+            AuctionError::AuctionNotStarted => msg!("Auction not started"),
+            AuctionError::AuctionEnded => msg!("Auction ended"),
+            AuctionError::AuctionNotEnded => msg!("Auction not ended"),
+            AuctionError::BidTooLow => msg!("Bid too low"),
+            AuctionError::InvalidReserveReveal => msg!("Invalid reserve reveal"),
+            AuctionError::ReserveNotRevealed => msg!("Reserve not revealed"),
+            AuctionError::AuctionHasBids => msg!("Auction has bids"),
+            AuctionError::WrongAuctionKind => msg!("Wrong auction kind"),
+            AuctionError::InvalidPriceSchedule => msg!("Invalid price schedule"),
+            AuctionError::PriceAboveLimit => msg!("Price above limit"),
+            AuctionError::InvalidBidReveal => msg!("Invalid bid reveal"),
+            AuctionError::DepositTooLow => msg!("Deposit too low"),
+            AuctionError::InvalidQuantity => msg!("Invalid quantity"),
+            AuctionError::InvalidFeeSplit => msg!("Invalid fee split"),
+            AuctionError::NothingToClaim => msg!("Nothing to claim"),
+        }
+    }
+}
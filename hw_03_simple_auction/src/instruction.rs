@@ -1,12 +1,50 @@
-use solana_program::program_error::ProgramError;
+use crate::state::{AuctionKind, FeeSplit, IncrementKind};
+use solana_program::{hash::Hash, program_error::ProgramError};
+use std::convert::TryFrom;
 use std::mem::size_of;
 
 #[repr(C)]
 #[derive(Debug)]
 pub enum AuctionInstruction {
-    CreateAuction { start_price: u64 },
-    Bidding { price: u64, decimals: u8 },
-    CloseAuction,
+    // 创建拍卖（start_ts/end_ts 是出价开始和结束的时间戳，extension_secs 大于0时在结束前 extension_secs 秒内出价会把结束时间延长到出价后 extension_secs 秒）
+    // min_increment/increment_kind 是最低加价幅度，reserve_hash 是保留价的承诺值（见 state::reserve_commitment，默认值表示没有保留价）
+    // kind 是拍卖方式（荷兰式拍卖的 start_price 必须和 start_price 参数一致，卖家不需要托管起拍价），quantity 是托管的拍品数量（批量拍卖的 start_price 是最低单价）
+    // fees 是成交时分给创作者的版税和平台的手续费（默认值表示不分成）
+    // 出价代币是原生SOL（native_mint）时，money_holder 必须是 state::find_vault_address 的金库地址（不存在时由卖家付租金创建），收付款账户都是钱包地址
+    // 原生SOL的拍卖结算以后，金库里的钱全部领取完时关闭金库，租金退还给卖家（CloseAuction/Buy/WithdrawDeposit/ClaimRefund/ClaimBatchOrder 还要传入金库和卖家的钱包）
+    // 批量拍卖还要传入 state::find_order_book_address 的出价簿账户（由卖家付租金创建）
+    CreateAuction {
+        start_price: u64,
+        start_ts: i64,
+        end_ts: i64,
+        extension_secs: u32,
+        min_increment: u64,
+        increment_kind: IncrementKind,
+        reserve_hash: Hash,
+        kind: AuctionKind,
+        quantity: u64,
+        fees: FeeSplit,
+    },
+    // 出价（代币精度从出价代币的账户里读取，每次出价由出价者付租金创建一个出价记录，地址见 state::find_bid_record_address，还有自己的退款记录；被超过的出价记到原出价者的退款记录里，不直接退款）
+    Bidding { price: u64 },
+    // 关闭拍卖（reserve_reveal 是公开的保留价和随机盐，没有设置保留价时为None；没有达到保留价时最高出价记到出价者的退款记录里）
+    CloseAuction { reserve_reveal: Option<(u64, [u8; 32])> },
+    // 卖家取消拍卖（只能在没有人出价的时候取消，原生SOL的金库账户和批量拍卖的出价簿也会关闭，租金退还给卖家）
+    CancelAuction,
+    // 按照荷兰式拍卖的当前价格购买（max_price 是买家可以接受的最高价格）
+    Buy { max_price: u64 },
+    // 密封拍卖提交出价（commitment 是 state::bid_commitment 的结果，deposit 是托管的保证金，必须不低于之后公开的价格）
+    CommitBid { commitment: Hash, deposit: u64 },
+    // 密封拍卖公开出价
+    RevealBid { price: u64, salt: [u8; 32] },
+    // 密封拍卖结算以后取回保证金（获胜者取回保证金减去出价的部分）
+    WithdrawDeposit,
+    // 批量拍卖出价（price 是单价，quantity 是想要的数量，托管 price * quantity，出价记到出价簿账户里；出价被挤出去时托管的钱记到出价者的退款记录里）
+    BatchBid { price: u64, quantity: u64 },
+    // 领取出价被超过时记下的退款（退款记录地址见 state::find_refund_claim_address，出价者签名，可以转到自己的任意出价代币账户）
+    ClaimRefund,
+    // 批量拍卖关闭以后发送第 index 个出价的成交拍品和退款（关闭拍卖时已经记下，任何人都可以调用，只能转到出价时的拍品账户和退款账户）
+    ClaimBatchOrder { index: u8 },
 }
 
 // 该文件是解析调用合约入口参数
@@ -19,40 +57,184 @@ impl AuctionInstruction {
             .ok_or(ProgramError::InvalidInstructionData)?;
         Ok(match tag {
             0 => Self::CreateAuction {
-                start_price: {
-                    let mut fixed_data = [0u8; 8];
-                    fixed_data.copy_from_slice(&rest[..8]);
-                    u64::from_le_bytes(fixed_data)
+                start_price: Self::unpack_u64(rest, 0)?,
+                start_ts: Self::unpack_i64(rest, 8)?,
+                end_ts: Self::unpack_i64(rest, 16)?,
+                extension_secs: {
+                    let mut fixed_data = [0u8; 4];
+                    fixed_data.copy_from_slice(rest.get(24..28).ok_or(ProgramError::InvalidInstructionData)?);
+                    u32::from_le_bytes(fixed_data)
                 },
+                min_increment: Self::unpack_u64(rest, 28)?,
+                increment_kind: rest
+                    .get(36)
+                    .and_then(|&kind| IncrementKind::try_from(kind).ok())
+                    .ok_or(ProgramError::InvalidInstructionData)?,
+                reserve_hash: Hash::new(rest.get(37..69).ok_or(ProgramError::InvalidInstructionData)?),
+                kind: {
+                    let mut fixed_data = [0u8; AuctionKind::LEN];
+                    fixed_data.copy_from_slice(rest.get(69..94).ok_or(ProgramError::InvalidInstructionData)?);
+                    AuctionKind::unpack(&fixed_data).map_err(|_| ProgramError::InvalidInstructionData)?
+                },
+                quantity: Self::unpack_u64(rest, 94)?,
+                fees: {
+                    let mut fixed_data = [0u8; FeeSplit::LEN];
+                    fixed_data.copy_from_slice(rest.get(102..170).ok_or(ProgramError::InvalidInstructionData)?);
+                    FeeSplit::unpack(&fixed_data)
+                },
+            },
+            1 => Self::Bidding { price: Self::unpack_u64(rest, 0)? },
+            2 => Self::CloseAuction {
+                // 没有后续数据表示不公开保留价
+                reserve_reveal: if rest.is_empty() {
+                    None
+                } else {
+                    let mut salt = [0u8; 32];
+                    salt.copy_from_slice(rest.get(8..40).ok_or(ProgramError::InvalidInstructionData)?);
+                    Some((Self::unpack_u64(rest, 0)?, salt))
+                },
+            },
+            3 => Self::CancelAuction,
+            4 => Self::Buy { max_price: Self::unpack_u64(rest, 0)? },
+            5 => Self::CommitBid {
+                commitment: Hash::new(rest.get(..32).ok_or(ProgramError::InvalidInstructionData)?),
+                deposit: Self::unpack_u64(rest, 32)?,
             },
-            1 => Self::Bidding {
-                price: {
-                    let mut fixed_data = [0u8; 8];
-                    fixed_data.copy_from_slice(&rest[..8]);
-                    u64::from_le_bytes(fixed_data)
+            6 => Self::RevealBid {
+                price: Self::unpack_u64(rest, 0)?,
+                salt: {
+                    let mut salt = [0u8; 32];
+                    salt.copy_from_slice(rest.get(8..40).ok_or(ProgramError::InvalidInstructionData)?);
+                    salt
                 },
-                decimals: rest[8],
             },
-            2 => Self::CloseAuction,
+            7 => Self::WithdrawDeposit,
+            8 => Self::BatchBid {
+                price: Self::unpack_u64(rest, 0)?,
+                quantity: Self::unpack_u64(rest, 8)?,
+            },
+            9 => Self::ClaimRefund,
+            10 => Self::ClaimBatchOrder { index: *rest.first().ok_or(ProgramError::InvalidInstructionData)? },
             _ => return Err(ProgramError::InvalidInstructionData.into()),
         })
     }
 
+    // 从指定位置解析i64（数据长度不够时返回错误）
+    fn unpack_i64(input: &[u8], offset: usize) -> Result<i64, ProgramError> {
+        let mut fixed_data = [0u8; 8];
+        fixed_data.copy_from_slice(input.get(offset..offset + 8).ok_or(ProgramError::InvalidInstructionData)?);
+        Ok(i64::from_le_bytes(fixed_data))
+    }
+
+    // 从指定位置解析u64（数据长度不够时返回错误）
+    fn unpack_u64(input: &[u8], offset: usize) -> Result<u64, ProgramError> {
+        let mut fixed_data = [0u8; 8];
+        fixed_data.copy_from_slice(input.get(offset..offset + 8).ok_or(ProgramError::InvalidInstructionData)?);
+        Ok(u64::from_le_bytes(fixed_data))
+    }
+
     // 合约参数打包
     pub fn pack(&self) -> Vec<u8> {
         let mut buf = Vec::with_capacity(size_of::<Self>());
         match self {
-            &Self::CreateAuction { start_price } => {
+            &Self::CreateAuction {
+                start_price,
+                start_ts,
+                end_ts,
+                extension_secs,
+                min_increment,
+                increment_kind,
+                ref reserve_hash,
+                ref kind,
+                quantity,
+                ref fees,
+            } => {
                 buf.push(0);
                 buf.extend_from_slice(&start_price.to_le_bytes());
+                buf.extend_from_slice(&start_ts.to_le_bytes());
+                buf.extend_from_slice(&end_ts.to_le_bytes());
+                buf.extend_from_slice(&extension_secs.to_le_bytes());
+                buf.extend_from_slice(&min_increment.to_le_bytes());
+                buf.push(increment_kind.into());
+                buf.extend_from_slice(reserve_hash.as_ref());
+                let mut kind_data = [0u8; AuctionKind::LEN];
+                kind.pack(&mut kind_data);
+                buf.extend_from_slice(&kind_data);
+                buf.extend_from_slice(&quantity.to_le_bytes());
+                let mut fees_data = [0u8; FeeSplit::LEN];
+                fees.pack(&mut fees_data);
+                buf.extend_from_slice(&fees_data);
             }
-            &Self::Bidding { price, decimals } => {
+            &Self::Bidding { price } => {
                 buf.push(1);
                 buf.extend_from_slice(&price.to_le_bytes());
-                buf.extend_from_slice(&decimals.to_le_bytes());
             }
-            Self::CloseAuction => buf.push(2),
+            Self::CloseAuction { reserve_reveal } => {
+                buf.push(2);
+                if let Some((reserve_price, salt)) = reserve_reveal {
+                    buf.extend_from_slice(&reserve_price.to_le_bytes());
+                    buf.extend_from_slice(salt);
+                }
+            }
+            Self::CancelAuction => buf.push(3),
+            &Self::Buy { max_price } => {
+                buf.push(4);
+                buf.extend_from_slice(&max_price.to_le_bytes());
+            }
+            &Self::CommitBid { ref commitment, deposit } => {
+                buf.push(5);
+                buf.extend_from_slice(commitment.as_ref());
+                buf.extend_from_slice(&deposit.to_le_bytes());
+            }
+            &Self::RevealBid { price, ref salt } => {
+                buf.push(6);
+                buf.extend_from_slice(&price.to_le_bytes());
+                buf.extend_from_slice(salt);
+            }
+            Self::WithdrawDeposit => buf.push(7),
+            &Self::BatchBid { price, quantity } => {
+                buf.push(8);
+                buf.extend_from_slice(&price.to_le_bytes());
+                buf.extend_from_slice(&quantity.to_le_bytes());
+            }
+            Self::ClaimRefund => buf.push(9),
+            &Self::ClaimBatchOrder { index } => {
+                buf.push(10);
+                buf.push(index);
+            }
         };
         buf
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn unpack_short_input() {
+        let data = AuctionInstruction::CreateAuction {
+            start_price: 100,
+            start_ts: 1,
+            end_ts: 2,
+            extension_secs: 0,
+            min_increment: 0,
+            increment_kind: IncrementKind::default(),
+            reserve_hash: Hash::default(),
+            kind: AuctionKind::default(),
+            quantity: 1,
+            fees: FeeSplit::default(),
+        }
+        .pack();
+        assert!(matches!(
+            AuctionInstruction::unpack(&data),
+            Ok(AuctionInstruction::CreateAuction { start_price: 100, .. })
+        ));
+        // 参数不完整时返回错误，不能panic
+        for len in 0..data.len() {
+            assert_eq!(
+                AuctionInstruction::unpack(&data[..len]).unwrap_err(),
+                ProgramError::InvalidInstructionData
+            );
+        }
+    }
+}
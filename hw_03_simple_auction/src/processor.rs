@@ -1,36 +1,414 @@
-use crate::{instruction::AuctionInstruction, state::Auction};
+use crate::{
+    error::AuctionError,
+    instruction::AuctionInstruction,
+    state::{
+        bid_commitment, find_auction_authority, find_bid_record_address, find_order_book_address, find_refund_claim_address,
+        find_vault_address, reserve_commitment, Auction, AuctionKind, AuctionStatus, BatchOrder, BatchOrderBook, BidRecord,
+        FeeSplit, IncrementKind, RefundClaim, SealedBid, BID_RECORD_SEED, MAX_BATCH_BIDS, ORDER_BOOK_SEED,
+        REFUND_CLAIM_SEED, RESERVE_REVEAL_SECS, VAULT_SEED,
+    },
+};
 use solana_program::{
     account_info::{next_account_info, AccountInfo},
+    clock::Clock,
     entrypoint::ProgramResult,
+    hash::Hash,
     msg,
     program::{invoke, invoke_signed},
     program_error::ProgramError,
     program_pack::{IsInitialized, Pack},
     pubkey::Pubkey,
+    rent::Rent,
+    system_instruction, system_program,
+    sysvar::Sysvar,
 };
 
-use spl_token;
+use spl_token::{self, native_mint};
 
 pub struct Processor {}
 
 // 该文件是合约里面各个函数的逻辑实现
 impl Processor {
 
+    /**
+     * 从代币账户里读取精度（转账使用 transfer_checked，精度以链上的代币信息为准）
+     * @param mint_info     代币账户
+     * @param expected_mint 拍卖里记录的代币地址
+     */
+    fn mint_decimals(mint_info: &AccountInfo, expected_mint: &Pubkey) -> Result<u8, ProgramError> {
+        if mint_info.key != expected_mint {
+            msg!("mint mismatch");
+            return Err(ProgramError::InvalidAccountData);
+        }
+        // 原生SOL不经过Token合约转账
+        if *expected_mint == native_mint::id() {
+            return Ok(native_mint::DECIMALS);
+        }
+        if mint_info.owner != &spl_token::id() {
+            msg!("mint is not owned by the token program");
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        Ok(spl_token::state::Mint::unpack(&mint_info.data.borrow())?.decimals)
+    }
+
+    /**
+     * 从托管账户转出（托管账户属于拍卖的PDA地址，由合约签名）
+     * @param authority_seeds 托管账户PDA的签名种子
+     * @param source          托管账户
+     * @param destination     接收账户
+     */
+    #[allow(clippy::too_many_arguments)]
+    fn transfer_from_custody(
+        accounts: &[AccountInfo],
+        authority_seeds: &[&[u8]],
+        pda: &Pubkey,
+        source: &Pubkey,
+        mint: &Pubkey,
+        destination: &Pubkey,
+        amount: u64,
+        decimals: u8,
+    ) -> ProgramResult {
+        let transfer_instruction = spl_token::instruction::transfer_checked(
+            &spl_token::id(),
+            source,
+            mint,
+            destination,
+            pda,
+            &[pda],
+            amount,
+            decimals,
+        )?;
+        invoke_signed(&transfer_instruction, accounts, &[authority_seeds])
+    }
+
+    /**
+     * 付款（出价代币是原生SOL时使用系统合约转账，from 是签名的钱包；其他代币使用Token合约转账，authority 签名）
+     * @param currency 出价代币
+     * @param from     付款账户
+     * @param to       收款账户
+     */
+    fn pay(
+        accounts: &[AccountInfo],
+        currency: &Pubkey,
+        from: &Pubkey,
+        authority: &Pubkey,
+        to: &Pubkey,
+        amount: u64,
+        decimals: u8,
+    ) -> ProgramResult {
+        let pay_instruction = if *currency == native_mint::id() {
+            system_instruction::transfer(from, to, amount)
+        } else {
+            spl_token::instruction::transfer_checked(&spl_token::id(), from, currency, to, authority, &[authority], amount, decimals)?
+        };
+        invoke(&pay_instruction, accounts)
+    }
+
+    /**
+     * 从托管账户转出出价代币（原生SOL直接从合约的金库账户里扣除lamports，其他代币由托管账户的PDA签名转账）
+     * @param auction_key     拍卖账户地址
+     * @param authority_seeds 托管账户PDA的签名种子
+     * @param to              接收账户
+     */
+    #[allow(clippy::too_many_arguments)]
+    fn release_escrow(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        auction_key: &Pubkey,
+        auction: &Auction,
+        authority_seeds: &[&[u8]],
+        pda: &Pubkey,
+        to: &Pubkey,
+        amount: u64,
+        decimals: u8,
+    ) -> ProgramResult {
+        if auction.currency != native_mint::id() {
+            return Self::transfer_from_custody(accounts, authority_seeds, pda, &auction.money_holder, &auction.currency, to, amount, decimals);
+        }
+        // 只能从这个拍卖自己的金库里扣除lamports（金库属于本合约，没有PDA签名也可以直接修改余额）
+        if auction.money_holder != find_vault_address(program_id, auction_key).0 {
+            msg!("money holder must be the auction vault");
+            return Err(ProgramError::InvalidSeeds);
+        }
+        let find_account = |key: &Pubkey| accounts.iter().find(|account| account.key == key).ok_or(ProgramError::NotEnoughAccountKeys);
+        let vault = find_account(&auction.money_holder)?;
+        let receiver = find_account(to)?;
+        let vault_lamports = vault.lamports().checked_sub(amount).ok_or(ProgramError::InsufficientFunds)?;
+        **vault.lamports.borrow_mut() = vault_lamports;
+        **receiver.lamports.borrow_mut() = receiver.lamports().checked_add(amount).ok_or(ProgramError::InvalidAccountData)?;
+        Ok(())
+    }
+
+    /**
+     * 原生SOL的拍卖结算以后，金库里只剩下租金时（出价、退款和保证金都已经领取完）关闭金库，租金退还给卖家
+     * 这时需要在传入的账户里找到金库和卖家的钱包（按照地址查找）
+     * @param auction_key 拍卖账户地址
+     */
+    fn close_drained_vault(program_id: &Pubkey, accounts: &[AccountInfo], auction_key: &Pubkey, auction: &Auction) -> ProgramResult {
+        if auction.currency != native_mint::id() || auction.status != AuctionStatus::Settled {
+            return Ok(());
+        }
+        if auction.money_holder != find_vault_address(program_id, auction_key).0 {
+            msg!("money holder must be the auction vault");
+            return Err(ProgramError::InvalidSeeds);
+        }
+        let find_account = |key: &Pubkey| accounts.iter().find(|account| account.key == key).ok_or(ProgramError::NotEnoughAccountKeys);
+        let vault = find_account(&auction.money_holder)?;
+        if vault.lamports() > Rent::get()?.minimum_balance(0) {
+            return Ok(());
+        }
+        let seller = find_account(&auction.seller)?;
+        msg!("vault drained, closing it");
+        Self::close_account(vault, seller)
+    }
+
+    /**
+     * 检查拍卖账户属于本合约并解析拍卖数据（拍卖数据决定托管的钱和拍品转给谁，不能使用别人伪造的账户）
+     * @param auction_info 拍卖账户
+     */
+    fn unpack_auction(program_id: &Pubkey, auction_info: &AccountInfo) -> Result<Auction, ProgramError> {
+        Self::check_program_owned(program_id, auction_info)?;
+        Auction::unpack(&auction_info.data.borrow())
+    }
+
+    // 检查拍卖账户是不是本合约的账户
+    fn check_program_owned(program_id: &Pubkey, auction_info: &AccountInfo) -> ProgramResult {
+        if auction_info.owner != program_id {
+            msg!("auction account is not owned by the program");
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        Ok(())
+    }
+
+    /**
+     * 检查接收出价代币的账户（原生SOL是系统账户，也就是钱包地址；其他代币是这个代币的代币账户）
+     * @param currency 出价代币
+     * @param owner    账户的所有者（None 表示不检查）
+     */
+    fn check_currency_account(info: &AccountInfo, currency: &Pubkey, owner: Option<&Pubkey>) -> ProgramResult {
+        let (account_owner, matches_currency) = if *currency == native_mint::id() {
+            (*info.key, info.owner == &system_program::id())
+        } else {
+            let account = spl_token::state::Account::unpack(&info.data.borrow())?;
+            (account.owner, account.mint == *currency)
+        };
+        if !matches_currency || matches!(owner, Some(owner) if *owner != account_owner) {
+            msg!("currency account mismatch");
+            return Err(ProgramError::InvalidAccountData);
+        }
+        Ok(())
+    }
+
+    /**
+     * 按照分成把成交的钱转给卖家、创作者和平台（金额为0的部分不转账）
+     * @param fees            分成设置
+     * @param amount          成交的钱
+     * @param seller_receiver 卖家接收钱的代币账户
+     * @param transfer        转账（参数是接收账户和金额）
+     */
+    fn pay_out_sale(
+        fees: &FeeSplit,
+        amount: u64,
+        seller_receiver: &Pubkey,
+        mut transfer: impl FnMut(&Pubkey, u64) -> ProgramResult,
+    ) -> ProgramResult {
+        let (seller_amount, royalty, platform_fee) = fees.split(amount);
+        for (receiver, share) in [(seller_receiver, seller_amount), (&fees.creator, royalty), (&fees.platform, platform_fee)] {
+            if share > 0 {
+                transfer(receiver, share)?;
+            }
+        }
+        Ok(())
+    }
+
+    /**
+     * 创建属于本合约的PDA账户（任何人都可以提前往PDA地址转入lamports，这时 create_account 会失败，
+     * 所以已经有余额的账户改为补足免租金的余额以后分配空间并指定所属合约）
+     * @param payer        支付租金的账户（必须签名）
+     * @param target       要创建的PDA账户
+     * @param space        账户数据长度
+     * @param signer_seeds PDA的签名种子
+     */
+    fn create_program_account(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        payer: &Pubkey,
+        target: &AccountInfo,
+        space: usize,
+        signer_seeds: &[&[u8]],
+    ) -> ProgramResult {
+        let rent_lamports = Rent::get()?.minimum_balance(space);
+        if target.lamports() == 0 {
+            let create_instruction = system_instruction::create_account(payer, target.key, rent_lamports, space as u64, program_id);
+            return invoke_signed(&create_instruction, accounts, &[signer_seeds]);
+        }
+        let shortfall = rent_lamports.saturating_sub(target.lamports());
+        if shortfall > 0 {
+            invoke(&system_instruction::transfer(payer, target.key, shortfall), accounts)?;
+        }
+        invoke_signed(&system_instruction::allocate(target.key, space as u64), accounts, &[signer_seeds])?;
+        invoke_signed(&system_instruction::assign(target.key, program_id), accounts, &[signer_seeds])
+    }
+
+    /**
+     * 把退款记到钱包的退款记录里（不直接退款，由钱包自己领取；退款记录不存在时由 payer 支付租金创建）
+     * @param auction_key 拍卖账户地址
+     * @param claim_info  退款记录账户（地址见 state::find_refund_claim_address）
+     * @param wallet      可以领取退款的钱包
+     * @param payer       创建退款记录时支付租金的账户（None 表示退款记录必须已经存在）
+     * @param amount      退款金额（出价时传0只创建自己的退款记录）
+     */
+    #[allow(clippy::too_many_arguments)]
+    fn credit_refund_claim(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        auction_key: &Pubkey,
+        claim_info: &AccountInfo,
+        wallet: &Pubkey,
+        payer: Option<&Pubkey>,
+        amount: u64,
+    ) -> ProgramResult {
+        let (claim_address, claim_bump) = find_refund_claim_address(program_id, auction_key, wallet);
+        if *claim_info.key != claim_address {
+            msg!("refund claim address mismatch");
+            return Err(ProgramError::InvalidSeeds);
+        }
+        let mut refund_claim = if claim_info.owner == program_id {
+            RefundClaim::unpack(&claim_info.data.borrow())?
+        } else if let Some(payer) = payer {
+            let claim_seeds: &[&[u8]] = &[REFUND_CLAIM_SEED, auction_key.as_ref(), wallet.as_ref(), &[claim_bump]];
+            Self::create_program_account(program_id, accounts, payer, claim_info, RefundClaim::LEN, claim_seeds)?;
+            RefundClaim { auction: *auction_key, bidder: *wallet, amount: 0 }
+        } else {
+            msg!("refund claim not created");
+            return Err(ProgramError::UninitializedAccount);
+        };
+        refund_claim.amount = refund_claim.amount.checked_add(amount).ok_or(ProgramError::InvalidAccountData)?;
+        RefundClaim::pack(refund_claim, &mut claim_info.data.borrow_mut())
+    }
+
+    /**
+     * 在传入的账户里按照地址找到钱包的退款记录（只在需要退款时才用到的账户不固定位置）
+     * @param auction_key 拍卖账户地址
+     * @param wallet      可以领取退款的钱包
+     */
+    fn find_refund_claim<'a, 'b>(
+        program_id: &Pubkey,
+        accounts: &'a [AccountInfo<'b>],
+        auction_key: &Pubkey,
+        wallet: &Pubkey,
+    ) -> Result<&'a AccountInfo<'b>, ProgramError> {
+        let (claim_address, _) = find_refund_claim_address(program_id, auction_key, wallet);
+        accounts.iter().find(|account| *account.key == claim_address).ok_or(ProgramError::NotEnoughAccountKeys)
+    }
+
+    /**
+     * 在传入的账户里按照地址找到批量拍卖的出价簿（地址见 state::find_order_book_address）
+     * @param auction_key 拍卖账户地址
+     */
+    fn find_order_book<'a, 'b>(
+        program_id: &Pubkey,
+        accounts: &'a [AccountInfo<'b>],
+        auction_key: &Pubkey,
+    ) -> Result<&'a AccountInfo<'b>, ProgramError> {
+        let (order_book_address, _) = find_order_book_address(program_id, auction_key);
+        accounts.iter().find(|account| *account.key == order_book_address).ok_or(ProgramError::NotEnoughAccountKeys)
+    }
+
+    // 解析批量拍卖的出价簿（出价簿必须属于本合约）
+    fn unpack_order_book(program_id: &Pubkey, order_book_info: &AccountInfo) -> Result<BatchOrderBook, ProgramError> {
+        if order_book_info.owner != program_id {
+            msg!("order book is not owned by the program");
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        BatchOrderBook::unpack(&order_book_info.data.borrow())
+    }
+
+    /**
+     * 关闭属于本合约的账户（余额全部转给 receiver，清空数据并还给系统合约，防止同一笔交易里面再次使用这个账户）
+     * @param account  要关闭的账户
+     * @param receiver 接收余额的账户
+     */
+    fn close_account(account: &AccountInfo, receiver: &AccountInfo) -> ProgramResult {
+        **receiver.lamports.borrow_mut() = receiver
+            .lamports()
+            .checked_add(account.lamports())
+            .ok_or(ProgramError::InvalidAccountData)?;
+        **account.lamports.borrow_mut() = 0;
+        account.data.borrow_mut().fill(0);
+        account.assign(&system_program::id());
+        Ok(())
+    }
+
+    #[allow(clippy::too_many_arguments)]
     pub fn process_create_auction(
         program_id: Pubkey,
         accounts: &[AccountInfo],
         start_price: u64,
+        start_ts: i64,
+        end_ts: i64,
+        extension_secs: u32,
+        min_increment: u64,
+        increment_kind: IncrementKind,
+        reserve_hash: Hash,
+        kind: AuctionKind,
+        quantity: u64,
+        fees: FeeSplit,
     ) -> ProgramResult {
         let account_info_iter = &mut accounts.iter();
 
+        if quantity == 0 {
+            msg!("auction must sell at least one unit");
+            return Err(AuctionError::InvalidQuantity.into());
+        }
+
+        if start_ts >= end_ts {
+            msg!("auction end time must be after start time");
+            return Err(AuctionError::InvalidTimeWindow.into());
+        }
+        // 荷兰式拍卖的底价就是保留价，不能再设置保留价
+        if let AuctionKind::Dutch { start_price: dutch_start_price, floor_price, .. } = kind {
+            if dutch_start_price != start_price || floor_price > start_price || reserve_hash != Hash::default() {
+                msg!("invalid dutch auction price schedule");
+                return Err(AuctionError::InvalidPriceSchedule.into());
+            }
+        }
+        // 密封拍卖的公开阶段在出价结束以后，出价是隐藏的，不需要再设置保留价
+        if let AuctionKind::Sealed { reveal_end_ts } = kind {
+            if reveal_end_ts <= end_ts {
+                msg!("reveal phase must end after bidding ends");
+                return Err(AuctionError::InvalidTimeWindow.into());
+            }
+            if reserve_hash != Hash::default() {
+                msg!("sealed auctions can not have a reserve");
+                return Err(AuctionError::InvalidPriceSchedule.into());
+            }
+        }
+        if !fees.is_valid() {
+            msg!("invalid royalty or platform fee");
+            return Err(AuctionError::InvalidFeeSplit.into());
+        }
+        // 批量拍卖的 start_price 是最低单价，不能再设置保留价
+        if kind == AuctionKind::Batch && reserve_hash != Hash::default() {
+            msg!("batch auctions can not have a reserve");
+            return Err(AuctionError::InvalidPriceSchedule.into());
+        }
+
         let auction_info = next_account_info(account_info_iter)?;
+        Self::check_program_owned(&program_id, auction_info)?;
         let mut auction = Auction::unpack_from_slice(&auction_info.data.borrow())?;
+        if auction.is_initialized() {
+            msg!("auction already initialized");
+            return Err(AuctionError::AlreadyInitialized.into());
+        }
 
         let seller = next_account_info(account_info_iter)?;
 
-        let (pda, _) = Pubkey::find_program_address(&[&seller.key.to_bytes()], &program_id);
+        // 每个拍卖使用单独的托管地址（拍品和出价的托管账户都必须属于这个地址）
+        let (pda, bump) = find_auction_authority(&program_id, seller.key, auction_info.key);
 
         let item = next_account_info(account_info_iter)?;
+        let item_decimals = Self::mint_decimals(item, item.key)?;
         let item_holder_info = next_account_info(account_info_iter)?;
         let item_holder = spl_token::state::Account::unpack(&item_holder_info.data.borrow())?;
         if item_holder.mint != *item.key {
@@ -43,37 +421,75 @@ impl Processor {
         }
 
         let currency = next_account_info(account_info_iter)?;
+        let currency_decimals = Self::mint_decimals(currency, currency.key)?;
         let money_holder_info = next_account_info(account_info_iter)?;
-        let money_holder = spl_token::state::Account::unpack(&money_holder_info.data.borrow())?;
-        if money_holder.mint != *currency.key {
-            msg!("money holder mint mismatch");
-            return Err(ProgramError::InvalidAccountData);
-        }
-        if money_holder.owner != pda {
-            msg!("money holder owner mismatch");
-            return Err(ProgramError::InvalidAccountData);
+        if *currency.key == native_mint::id() {
+            // 原生SOL托管在合约自己的金库PDA账户里（属于本合约，合约可以直接扣除lamports，卖家支付租金）
+            let (vault, vault_bump) = find_vault_address(&program_id, auction_info.key);
+            if *money_holder_info.key != vault {
+                msg!("money holder must be the auction vault");
+                return Err(ProgramError::InvalidSeeds);
+            }
+            if money_holder_info.owner != &program_id {
+                let vault_seeds: &[&[u8]] = &[VAULT_SEED, auction_info.key.as_ref(), &[vault_bump]];
+                Self::create_program_account(&program_id, accounts, seller.key, money_holder_info, 0, vault_seeds)?;
+            }
+        } else {
+            let money_holder = spl_token::state::Account::unpack(&money_holder_info.data.borrow())?;
+            if money_holder.mint != *currency.key {
+                msg!("money holder mint mismatch");
+                return Err(ProgramError::InvalidAccountData);
+            }
+            if money_holder.owner != pda {
+                msg!("money holder owner mismatch");
+                return Err(ProgramError::InvalidAccountData);
+            }
         }
 
         let bidder = next_account_info(account_info_iter)?;
         let refund_address = next_account_info(account_info_iter)?;
 
-        let send_currency_instruction = spl_token::instruction::transfer(
-            &spl_token::id(),
-            &refund_address.key,
-            &money_holder_info.key,
-            &seller.key,
-            &[&seller.key],
-            start_price,
-        )?;
-        invoke(&send_currency_instruction, accounts)?;
+        // 批量拍卖的出价簿保存在单独的PDA账户里（由卖家付租金创建，其他拍卖不需要出价簿）
+        if kind == AuctionKind::Batch {
+            let order_book_info = Self::find_order_book(&program_id, accounts, auction_info.key)?;
+            if order_book_info.owner != &program_id {
+                let (_, order_book_bump) = find_order_book_address(&program_id, auction_info.key);
+                let order_book_seeds: &[&[u8]] = &[ORDER_BOOK_SEED, auction_info.key.as_ref(), &[order_book_bump]];
+                Self::create_program_account(&program_id, accounts, seller.key, order_book_info, BatchOrderBook::LEN, order_book_seeds)?;
+            }
+            let order_book = BatchOrderBook { auction: *auction_info.key, ..BatchOrderBook::default() };
+            BatchOrderBook::pack(order_book, &mut order_book_info.data.borrow_mut())?;
+        }
+
+        // 分成的接收账户必须可以接收出价代币（否则结算时转账失败，拍品和出价都会卡在托管账户里）
+        for (receiver, bps) in [(&fees.creator, fees.royalty_bps), (&fees.platform, fees.platform_fee_bps)] {
+            if bps == 0 {
+                continue;
+            }
+            let receiver_info = accounts
+                .iter()
+                .find(|account| account.key == receiver)
+                .ok_or(ProgramError::NotEnoughAccountKeys)?;
+            if Self::check_currency_account(receiver_info, currency.key, None).is_err() {
+                msg!("fee receiver must be able to receive the currency");
+                return Err(AuctionError::InvalidFeeSplit.into());
+            }
+        }
+
+        // 英式拍卖卖家先托管起拍价（第一个出价的人出价时退还给卖家）
+        if kind == AuctionKind::English {
+            Self::pay(accounts, currency.key, refund_address.key, seller.key, money_holder_info.key, start_price, currency_decimals)?;
+        }
 
-        let send_item_instruction = spl_token::instruction::transfer(
+        let send_item_instruction = spl_token::instruction::transfer_checked(
             &spl_token::id(),
-            &bidder.key,
-            &item_holder_info.key,
-            &seller.key,
-            &[&seller.key],
-            1,
+            bidder.key,
+            item.key,
+            item_holder_info.key,
+            seller.key,
+            &[seller.key],
+            quantity,
+            item_decimals,
         )?;
         invoke(&send_item_instruction, accounts)?;
 
@@ -85,6 +501,20 @@ impl Processor {
         auction.bidder = *bidder.key;
         auction.refund_address = *refund_address.key;
         auction.price = start_price;
+        auction.start_price = start_price;
+        auction.authority_bump = bump;
+        auction.start_ts = start_ts;
+        auction.end_ts = end_ts;
+        auction.extension_secs = extension_secs;
+        auction.min_increment = min_increment;
+        auction.increment_kind = increment_kind;
+        auction.reserve_hash = reserve_hash;
+        auction.kind = kind;
+        auction.quantity = quantity;
+        auction.fees = fees;
+        auction.top_bidder = *seller.key;
+        // 拍品和起拍价都已经托管，开始接受出价（实际出价时间由 start_ts/end_ts 控制）
+        auction.status = auction.status.transition(AuctionStatus::Active)?;
         Auction::pack(auction, &mut auction_info.data.borrow_mut())?;
         Ok(())
     }
@@ -93,12 +523,28 @@ impl Processor {
         program_id: Pubkey,
         accounts: &[AccountInfo],
         price: u64,
-        decimals: u8,
     ) -> ProgramResult {
         let account_info_iter = &mut accounts.iter();
 
         let auction_info = next_account_info(account_info_iter)?;
-        let mut auction = Auction::unpack(&auction_info.data.borrow())?;
+        let mut auction = Self::unpack_auction(&program_id, auction_info)?;
+        if auction.status != AuctionStatus::Active {
+            msg!("auction is not active");
+            return Err(AuctionError::AuctionNotActive.into());
+        }
+        if auction.kind != AuctionKind::English {
+            msg!("only english auctions accept bids");
+            return Err(AuctionError::WrongAuctionKind.into());
+        }
+        let now = Clock::get()?.unix_timestamp;
+        if now < auction.start_ts {
+            msg!("auction not started");
+            return Err(AuctionError::AuctionNotStarted.into());
+        }
+        if now >= auction.end_ts {
+            msg!("auction ended");
+            return Err(AuctionError::AuctionEnded.into());
+        }
 
         let bidder = next_account_info(account_info_iter)?;
 
@@ -114,130 +560,928 @@ impl Processor {
         }
 
         let refund_address_info = next_account_info(account_info_iter)?;
-        let refund_address = spl_token::state::Account::unpack(&refund_address_info.data.borrow())?;
-        if !refund_address.is_initialized() {
-            msg!("item receiver doesn't init");
-            return Err(ProgramError::InvalidAccountData);
+        Self::check_currency_account(refund_address_info, &auction.currency, None)?;
+
+        // 新的出价至少要比当前价格高出最低加价幅度
+        match auction.min_next_bid() {
+            Some(min_price) if price >= min_price => {}
+            _ => {
+                msg!("bid price too low");
+                return Err(AuctionError::BidTooLow.into());
+            }
         }
-        if refund_address.mint != auction.currency {
-            msg!("refund address mismatch");
+
+        let payer = next_account_info(account_info_iter)?;
+        let money_holder_info = next_account_info(account_info_iter)?;
+        if *money_holder_info.key != auction.money_holder {
+            msg!("money holder mismatch");
             return Err(ProgramError::InvalidAccountData);
         }
+        let currency = next_account_info(account_info_iter)?;
+        let decimals = Self::mint_decimals(currency, &auction.currency)?;
 
-        if price <= auction.price {
-            msg!("bid price too low");
-            return Err(ProgramError::InvalidAccountData);
+        // 出价记录的地址由拍卖和出价序号决定（出价者支付记录账户的租金）
+        let bid_record_info = next_account_info(account_info_iter)?;
+        let sequence = auction.bid_count;
+        let (bid_record_address, bid_record_bump) = find_bid_record_address(&program_id, auction_info.key, sequence);
+        if *bid_record_info.key != bid_record_address {
+            msg!("bid record address mismatch");
+            return Err(ProgramError::InvalidSeeds);
         }
 
-        let (pda, seed) = Pubkey::find_program_address(&[&auction.seller.to_bytes()], &program_id);
-        let refund_instruction = spl_token::instruction::transfer_checked(
-            &spl_token::id(),
-            &auction.money_holder,
-            &auction.currency,
-            &auction.refund_address,
-            &pda,
-            &[&pda],
+        // 被超过的出价不直接退回（退款账户被关闭或冻结时会导致无法继续出价），记到原出价者的退款记录里，由他自己领取
+        let refund_claim_info = next_account_info(account_info_iter)?;
+        // 出价者自己的退款记录（没有时由出价者付租金创建，之后被超过或者没有达到保留价时退款都记到这里）
+        let bidder_claim_info = next_account_info(account_info_iter)?;
+        Self::credit_refund_claim(&program_id, accounts, auction_info.key, bidder_claim_info, bidder.key, Some(bidder.key), 0)?;
+        // 卖家的退款记录在第一次出价时由出价者付租金创建
+        Self::credit_refund_claim(
+            &program_id,
+            accounts,
+            auction_info.key,
+            refund_claim_info,
+            &auction.top_bidder,
+            Some(bidder.key),
             auction.price,
-            decimals,
         )?;
 
-        invoke_signed(
-            &refund_instruction,
-            accounts,
-            &[&[&auction.seller.to_bytes(), &[seed]]],
-        )?;
+        Self::pay(accounts, &auction.currency, payer.key, bidder.key, &auction.money_holder, price, decimals)?;
 
-        let payer = next_account_info(account_info_iter)?;
-        let pay_for_bidding_instruction = spl_token::instruction::transfer_checked(
-            &spl_token::id(),
-            payer.key,
-            &auction.currency,
-            &auction.money_holder,
-            &bidder.key,
-            &[&bidder.key],
+        let sequence_bytes = sequence.to_le_bytes();
+        let bid_record_seeds: &[&[u8]] = &[BID_RECORD_SEED, auction_info.key.as_ref(), &sequence_bytes, &[bid_record_bump]];
+        Self::create_program_account(&program_id, accounts, bidder.key, bid_record_info, BidRecord::LEN, bid_record_seeds)?;
+        let bid_record = BidRecord {
+            auction: *auction_info.key,
+            bidder: *bidder.key,
             price,
-            decimals,
-        )?;
-        invoke_signed(
-            &pay_for_bidding_instruction,
-            accounts,
-            &[&[&auction.seller.to_bytes(), &[seed]]],
-        )?;
+            timestamp: now,
+            sequence,
+        };
+        BidRecord::pack(bid_record, &mut bid_record_info.data.borrow_mut())?;
 
         auction.bidder = *item_receiver_info.key;
         auction.refund_address = *refund_address_info.key;
         auction.price = price;
+        auction.bid_count = sequence + 1;
+        auction.top_bidder = *bidder.key;
+        // 防止最后时刻出价（结束前 extension_secs 秒内出价，结束时间延长到出价后 extension_secs 秒）
+        let extension_secs = auction.extension_secs as i64;
+        if extension_secs > 0 && auction.end_ts - now < extension_secs {
+            auction.end_ts = now + extension_secs;
+            msg!("auction extended to {}", auction.end_ts);
+        }
         Auction::pack(auction, &mut auction_info.data.borrow_mut())?;
         Ok(())
     }
 
-    pub fn process_close_auction(program_id: Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    pub fn process_close_auction(
+        program_id: Pubkey,
+        accounts: &[AccountInfo],
+        reserve_reveal: Option<(u64, [u8; 32])>,
+    ) -> ProgramResult {
         let account_info_iter = &mut accounts.iter();
 
         let auction_info = next_account_info(account_info_iter)?;
-        let auction = Auction::unpack(&auction_info.data.borrow())?;
+        let mut auction = Self::unpack_auction(&program_id, auction_info)?;
 
+        // 拍卖结束以后任何人都可以关闭拍卖（不需要卖家签名，钱只能转到卖家的账户）
         let seller = next_account_info(account_info_iter)?;
         if *seller.key != auction.seller {
             msg!("seller mismatch");
             return Err(ProgramError::InvalidAccountData.into());
         }
-        if !seller.is_signer {
-            msg!("seller need sign");
-            return Err(ProgramError::InvalidAccountData.into());
+        let now = Clock::get()?.unix_timestamp;
+        if now < auction.close_ts() {
+            msg!("auction not ended");
+            return Err(AuctionError::AuctionNotEnded.into());
+        }
+
+        // 关闭拍卖先结束出价再结算（已经结算或者取消的拍卖不能再关闭）
+        let mut status = auction.status;
+        if status == AuctionStatus::Active {
+            status = status.transition(AuctionStatus::Ended)?;
         }
+        auction.status = status.transition(AuctionStatus::Settled)?;
+
+        // 检查有没有达到保留价（卖家一直不公开保留价时，超过公开期限以后按照没有达到保留价处理）
+        let reserve_met = if !auction.has_reserve() {
+            true
+        } else if let Some((reserve_price, salt)) = reserve_reveal {
+            if reserve_commitment(reserve_price, &salt) != auction.reserve_hash {
+                msg!("reserve reveal mismatch");
+                return Err(AuctionError::InvalidReserveReveal.into());
+            }
+            auction.price >= reserve_price
+        } else if now < auction.end_ts.saturating_add(RESERVE_REVEAL_SECS) {
+            msg!("reserve not revealed");
+            return Err(AuctionError::ReserveNotRevealed.into());
+        } else {
+            false
+        };
 
         let moeny_receiver = next_account_info(account_info_iter)?;
+        Self::check_currency_account(moeny_receiver, &auction.currency, Some(&auction.seller))?;
+
+        // 没有达到保留价时拍品退还到卖家的这个账户
+        let item_return_info = next_account_info(account_info_iter)?;
+        let item_return = spl_token::state::Account::unpack(&item_return_info.data.borrow())?;
+        if item_return.owner != auction.seller || item_return.mint != auction.item {
+            msg!("item return account mismatch");
+            return Err(ProgramError::InvalidAccountData);
+        }
+
+        let currency = next_account_info(account_info_iter)?;
+        let currency_decimals = Self::mint_decimals(currency, &auction.currency)?;
+        let item = next_account_info(account_info_iter)?;
+        let item_decimals = Self::mint_decimals(item, &auction.item)?;
+
+        // 达到保留价：钱转给卖家，拍品转给出价最高的人；没有达到保留价：钱记到出价最高的人的退款记录里，拍品退还给卖家
+        let (money_to, item_to) = if reserve_met {
+            (moeny_receiver.key, &auction.bidder)
+        } else {
+            msg!("reserve not met");
+            (&auction.refund_address, item_return_info.key)
+        };
+
+        let bump = [auction.authority_bump];
+        let authority_seeds = auction.authority_seeds(auction_info.key, &bump);
+        let pda = Pubkey::create_program_address(&authority_seeds, &program_id)?;
+
+        // 批量拍卖按照统一的成交价格结算：只记下每个出价的成交数量和退款，由 ClaimBatchOrder 分别领取（一个出价者的账户有问题不影响其他人）
+        if auction.kind == AuctionKind::Batch {
+            let order_book_info = Self::find_order_book(&program_id, accounts, auction_info.key)?;
+            let mut order_book = Self::unpack_order_book(&program_id, order_book_info)?;
+            let (fills, clearing_price) = order_book.fills(auction.quantity);
+            let order_count = order_book.order_count as usize;
+            for (order, &fill) in order_book.bids[..order_count].iter_mut().zip(fills.iter()) {
+                // 托管的钱减去成交的部分退还给出价者（成交价不高于出价，不会溢出）
+                order.filled = fill;
+                order.refund = order.price * order.quantity - clearing_price * fill;
+            }
 
-        let (pda, seed) = Pubkey::find_program_address(&[&auction.seller.to_bytes()], &program_id);
-        let receive_money_instruction = spl_token::instruction::transfer(
+            // 成交的钱按照分成转给卖家、创作者和平台
+            let sold: u64 = fills.iter().sum();
+            Self::pay_out_sale(&auction.fees, clearing_price * sold, moeny_receiver.key, |receiver, amount| {
+                Self::release_escrow(
+                    &program_id,
+                    accounts,
+                    auction_info.key,
+                    &auction,
+                    &authority_seeds,
+                    &pda,
+                    receiver,
+                    amount,
+                    currency_decimals,
+                )
+            })?;
+            // 没有卖出去的拍品退还给卖家
+            if sold < auction.quantity {
+                Self::transfer_from_custody(
+                    accounts,
+                    &authority_seeds,
+                    &pda,
+                    &auction.item_holder,
+                    &auction.item,
+                    item_return_info.key,
+                    auction.quantity - sold,
+                    item_decimals,
+                )?;
+            }
+            msg!("sold {} units at {}", sold, clearing_price);
+            auction.price = clearing_price;
+            BatchOrderBook::pack(order_book, &mut order_book_info.data.borrow_mut())?;
+            Auction::pack(auction, &mut auction_info.data.borrow_mut())?;
+            return Self::close_drained_vault(&program_id, accounts, auction_info.key, &auction);
+        }
+
+        // 荷兰式拍卖没有托管的钱（没有人购买时只需要把拍品退还给卖家），没有达到保留价时全部退还，不分成
+        // 没有人出价时托管的是卖家自己的起拍价，全部退还给卖家，也不分成
+        let fees = if reserve_met && auction.has_bids() { auction.fees } else { FeeSplit::default() };
+        if reserve_met || !auction.has_bids() {
+            Self::pay_out_sale(&fees, auction.escrowed_amount(), money_to, |receiver, amount| {
+                Self::release_escrow(
+                    &program_id,
+                    accounts,
+                    auction_info.key,
+                    &auction,
+                    &authority_seeds,
+                    &pda,
+                    receiver,
+                    amount,
+                    currency_decimals,
+                )
+            })?;
+        } else {
+            // 出价者的退款记录在出价时已经创建（退款账户被关闭或冻结时不会导致无法关闭拍卖）
+            let claim_info = Self::find_refund_claim(&program_id, accounts, auction_info.key, &auction.top_bidder)?;
+            Self::credit_refund_claim(&program_id, accounts, auction_info.key, claim_info, &auction.top_bidder, None, auction.escrowed_amount())?;
+        }
+
+        let send_item_instruction = spl_token::instruction::transfer_checked(
             &spl_token::id(),
-            &auction.money_holder,
-            moeny_receiver.key,
+            &auction.item_holder,
+            &auction.item,
+            item_to,
             &pda,
             &[&pda],
-            auction.price,
+            auction.quantity,
+            item_decimals,
         )?;
-        invoke_signed(
-            &receive_money_instruction,
-            accounts,
-            &[&[&auction.seller.to_bytes(), &[seed]]],
+        invoke_signed(&send_item_instruction, accounts, &[&authority_seeds])?;
+
+        Auction::pack(auction, &mut auction_info.data.borrow_mut())?;
+        Self::close_drained_vault(&program_id, accounts, auction_info.key, &auction)
+    }
+
+    pub fn process_cancel_auction(program_id: Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+
+        let auction_info = next_account_info(account_info_iter)?;
+        let auction = Self::unpack_auction(&program_id, auction_info)?;
+
+        // 只有卖家可以取消拍卖
+        let seller = next_account_info(account_info_iter)?;
+        if *seller.key != auction.seller {
+            msg!("seller mismatch");
+            return Err(ProgramError::InvalidAccountData);
+        }
+        if !seller.is_signer {
+            msg!("seller need sign");
+            return Err(ProgramError::MissingRequiredSignature);
+        }
+
+        // 已经有人出价的拍卖不能取消（已经结算或者取消的拍卖也不能再取消）
+        auction.status.transition(AuctionStatus::Cancelled)?;
+        if auction.has_bids() {
+            msg!("auction has bids");
+            return Err(AuctionError::AuctionHasBids.into());
+        }
+        // 密封拍卖看不到有没有人出价，开始出价以后就不能取消
+        if let AuctionKind::Sealed { .. } = auction.kind {
+            if Clock::get()?.unix_timestamp >= auction.start_ts {
+                msg!("sealed auction may have bids");
+                return Err(AuctionError::AuctionHasBids.into());
+            }
+        }
+
+        // 拍品退还到卖家的这个账户
+        let item_return_info = next_account_info(account_info_iter)?;
+        let item_return = spl_token::state::Account::unpack(&item_return_info.data.borrow())?;
+        if item_return.owner != auction.seller || item_return.mint != auction.item {
+            msg!("item return account mismatch");
+            return Err(ProgramError::InvalidAccountData);
+        }
+
+        let currency = next_account_info(account_info_iter)?;
+        let currency_decimals = Self::mint_decimals(currency, &auction.currency)?;
+        let item = next_account_info(account_info_iter)?;
+        let item_decimals = Self::mint_decimals(item, &auction.item)?;
+
+        // 托管的起拍价退还到 refund_address（荷兰式拍卖没有托管的钱）
+        let bump = [auction.authority_bump];
+        let authority_seeds = auction.authority_seeds(auction_info.key, &bump);
+        let pda = Pubkey::create_program_address(&authority_seeds, &program_id)?;
+        let escrowed_amount = auction.escrowed_amount();
+        if escrowed_amount > 0 {
+            Self::release_escrow(
+                &program_id,
+                accounts,
+                auction_info.key,
+                &auction,
+                &authority_seeds,
+                &pda,
+                &auction.refund_address,
+                escrowed_amount,
+                currency_decimals,
+            )?;
+        }
+
+        let return_item_instruction = spl_token::instruction::transfer_checked(
+            &spl_token::id(),
+            &auction.item_holder,
+            &auction.item,
+            item_return_info.key,
+            &pda,
+            &[&pda],
+            auction.quantity,
+            item_decimals,
         )?;
+        invoke_signed(&return_item_instruction, accounts, &[&authority_seeds])?;
 
-        let send_item_instruction = spl_token::instruction::transfer(
+        // 原生SOL的金库账户也一起关闭，金库里剩下的租金退还给卖家
+        if auction.currency == native_mint::id() {
+            let vault = accounts
+                .iter()
+                .find(|account| account.key == &auction.money_holder)
+                .ok_or(ProgramError::NotEnoughAccountKeys)?;
+            Self::close_account(vault, seller)?;
+        }
+        // 批量拍卖的出价簿也一起关闭，租金退还给卖家
+        if auction.kind == AuctionKind::Batch {
+            let order_book_info = Self::find_order_book(&program_id, accounts, auction_info.key)?;
+            Self::unpack_order_book(&program_id, order_book_info)?;
+            Self::close_account(order_book_info, seller)?;
+        }
+
+        // 关闭拍卖账户，租金退还给卖家
+        Self::close_account(auction_info, seller)
+    }
+
+    pub fn process_buy(program_id: Pubkey, accounts: &[AccountInfo], max_price: u64) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+
+        let auction_info = next_account_info(account_info_iter)?;
+        let mut auction = Self::unpack_auction(&program_id, auction_info)?;
+        if auction.status != AuctionStatus::Active {
+            msg!("auction is not active");
+            return Err(AuctionError::AuctionNotActive.into());
+        }
+        let now = Clock::get()?.unix_timestamp;
+        // 只有荷兰式拍卖可以直接购买
+        let price = auction.dutch_price(now).ok_or(AuctionError::WrongAuctionKind)?;
+        if now < auction.start_ts {
+            msg!("auction not started");
+            return Err(AuctionError::AuctionNotStarted.into());
+        }
+        if now >= auction.end_ts {
+            msg!("auction ended");
+            return Err(AuctionError::AuctionEnded.into());
+        }
+        if price > max_price {
+            msg!("current price {} is above the limit {}", price, max_price);
+            return Err(AuctionError::PriceAboveLimit.into());
+        }
+
+        let buyer = next_account_info(account_info_iter)?;
+
+        let item_receiver_info = next_account_info(account_info_iter)?;
+        let item_receiver = spl_token::state::Account::unpack(&item_receiver_info.data.borrow())?;
+        if item_receiver.mint != auction.item {
+            msg!("item receiver mismatch");
+            return Err(ProgramError::InvalidAccountData);
+        }
+
+        let payer = next_account_info(account_info_iter)?;
+
+        // 钱直接转给卖家（不经过托管账户）
+        let money_receiver_info = next_account_info(account_info_iter)?;
+        Self::check_currency_account(money_receiver_info, &auction.currency, Some(&auction.seller))?;
+
+        let currency = next_account_info(account_info_iter)?;
+        let currency_decimals = Self::mint_decimals(currency, &auction.currency)?;
+        let item = next_account_info(account_info_iter)?;
+        let item_decimals = Self::mint_decimals(item, &auction.item)?;
+
+        // 成交的钱按照分成转给卖家、创作者和平台
+        Self::pay_out_sale(&auction.fees, price, money_receiver_info.key, |receiver, amount| {
+            Self::pay(accounts, &auction.currency, payer.key, buyer.key, receiver, amount, currency_decimals)
+        })?;
+
+        let bump = [auction.authority_bump];
+        let authority_seeds = auction.authority_seeds(auction_info.key, &bump);
+        let pda = Pubkey::create_program_address(&authority_seeds, &program_id)?;
+        let send_item_instruction = spl_token::instruction::transfer_checked(
             &spl_token::id(),
             &auction.item_holder,
-            &auction.bidder,
+            &auction.item,
+            item_receiver_info.key,
             &pda,
             &[&pda],
-            1,
+            auction.quantity,
+            item_decimals,
         )?;
-        invoke_signed(
-            &send_item_instruction,
+        invoke_signed(&send_item_instruction, accounts, &[&authority_seeds])?;
+
+        // 第一个购买的人直接成交
+        auction.bidder = *item_receiver_info.key;
+        auction.refund_address = *payer.key;
+        auction.price = price;
+        auction.status = auction.status.transition(AuctionStatus::Ended)?.transition(AuctionStatus::Settled)?;
+        Auction::pack(auction, &mut auction_info.data.borrow_mut())?;
+        // 荷兰式拍卖不使用金库，成交以后就可以关闭
+        Self::close_drained_vault(&program_id, accounts, auction_info.key, &auction)
+    }
+
+    pub fn process_batch_bid(program_id: Pubkey, accounts: &[AccountInfo], price: u64, quantity: u64) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+
+        let auction_info = next_account_info(account_info_iter)?;
+        let mut auction = Self::unpack_auction(&program_id, auction_info)?;
+        if auction.status != AuctionStatus::Active {
+            msg!("auction is not active");
+            return Err(AuctionError::AuctionNotActive.into());
+        }
+        if auction.kind != AuctionKind::Batch {
+            msg!("only batch auctions accept batch bids");
+            return Err(AuctionError::WrongAuctionKind.into());
+        }
+        let now = Clock::get()?.unix_timestamp;
+        if now < auction.start_ts {
+            msg!("auction not started");
+            return Err(AuctionError::AuctionNotStarted.into());
+        }
+        if now >= auction.end_ts {
+            msg!("auction ended");
+            return Err(AuctionError::AuctionEnded.into());
+        }
+        if quantity == 0 || quantity > auction.quantity {
+            msg!("bid quantity must be between 1 and {}", auction.quantity);
+            return Err(AuctionError::InvalidQuantity.into());
+        }
+        if price < auction.start_price {
+            msg!("bid price too low");
+            return Err(AuctionError::BidTooLow.into());
+        }
+        let order_escrow = price.checked_mul(quantity).ok_or(AuctionError::InvalidQuantity)?;
+
+        let bidder = next_account_info(account_info_iter)?;
+
+        let item_receiver_info = next_account_info(account_info_iter)?;
+        let item_receiver = spl_token::state::Account::unpack(&item_receiver_info.data.borrow())?;
+        if item_receiver.mint != auction.item {
+            msg!("item receiver mismatch");
+            return Err(ProgramError::InvalidAccountData);
+        }
+
+        let refund_address_info = next_account_info(account_info_iter)?;
+        Self::check_currency_account(refund_address_info, &auction.currency, None)?;
+
+        let payer = next_account_info(account_info_iter)?;
+        let money_holder_info = next_account_info(account_info_iter)?;
+        if *money_holder_info.key != auction.money_holder {
+            msg!("money holder mismatch");
+            return Err(ProgramError::InvalidAccountData);
+        }
+        let currency = next_account_info(account_info_iter)?;
+        let decimals = Self::mint_decimals(currency, &auction.currency)?;
+
+        // 出价者自己的退款记录（出价被挤出去时退款记到这里）
+        let bidder_claim_info = next_account_info(account_info_iter)?;
+        let order_book_info = Self::find_order_book(&program_id, accounts, auction_info.key)?;
+        let mut order_book = Self::unpack_order_book(&program_id, order_book_info)?;
+
+        // 出价簿满了：新的出价至少要比最低的出价高出最低加价幅度，最低的出价被挤出去
+        if order_book.order_count as usize == MAX_BATCH_BIDS {
+            let lowest = order_book.lowest_order().ok_or(ProgramError::InvalidAccountData)?;
+            let evicted = order_book.bids[lowest];
+            match auction.min_bid_over(evicted.price) {
+                Some(min_price) if price >= min_price => {}
+                _ => {
+                    msg!("order book full, bid must beat {}", evicted.price);
+                    return Err(AuctionError::BidTooLow.into());
+                }
+            }
+
+            // 被挤出去的钱记到出价者的退款记录里（退款账户被关闭或冻结时不会导致无法继续出价）
+            let claim_info = Self::find_refund_claim(&program_id, accounts, auction_info.key, &evicted.wallet)?;
+            Self::credit_refund_claim(
+                &program_id,
+                accounts,
+                auction_info.key,
+                claim_info,
+                &evicted.wallet,
+                Some(bidder.key),
+                evicted.price * evicted.quantity,
+            )?;
+            // 保持出价的先后顺序（单价相同时先出价的优先成交）
+            order_book.bids.copy_within(lowest + 1.., lowest);
+            order_book.order_count -= 1;
+        }
+
+        // 出价被接受以后才创建出价者自己的退款记录（由出价者付租金）
+        Self::credit_refund_claim(&program_id, accounts, auction_info.key, bidder_claim_info, bidder.key, Some(bidder.key), 0)?;
+        Self::pay(accounts, &auction.currency, payer.key, bidder.key, &auction.money_holder, order_escrow, decimals)?;
+
+        order_book.bids[order_book.order_count as usize] = BatchOrder {
+            bidder: *item_receiver_info.key,
+            refund_address: *refund_address_info.key,
+            price,
+            quantity,
+            wallet: *bidder.key,
+            ..BatchOrder::default()
+        };
+        order_book.order_count += 1;
+        auction.bid_count += 1;
+        // 防止最后时刻出价（和英式拍卖一样延长结束时间）
+        let extension_secs = auction.extension_secs as i64;
+        if extension_secs > 0 && auction.end_ts - now < extension_secs {
+            auction.end_ts = now + extension_secs;
+            msg!("auction extended to {}", auction.end_ts);
+        }
+        BatchOrderBook::pack(order_book, &mut order_book_info.data.borrow_mut())?;
+        Auction::pack(auction, &mut auction_info.data.borrow_mut())?;
+        Ok(())
+    }
+
+    /**
+     * 检查并解析密封出价账户
+     * @param program_id  合约ID
+     * @param bid_info    出价账户
+     * @param auction_key 出价所属的拍卖
+     * @param bidder      出价者（必须签名）
+     */
+    fn sealed_bid(program_id: &Pubkey, bid_info: &AccountInfo, auction_key: &Pubkey, bidder: &AccountInfo) -> Result<SealedBid, ProgramError> {
+        if !bidder.is_signer {
+            msg!("bidder need sign");
+            return Err(ProgramError::MissingRequiredSignature);
+        }
+        if bid_info.owner != program_id {
+            msg!("bid account is not owned by the program");
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        let bid = SealedBid::unpack(&bid_info.data.borrow())?;
+        if bid.auction != *auction_key || bid.bidder != *bidder.key {
+            msg!("bid account mismatch");
+            return Err(ProgramError::InvalidAccountData);
+        }
+        Ok(bid)
+    }
+
+    pub fn process_commit_bid(program_id: Pubkey, accounts: &[AccountInfo], commitment: Hash, deposit: u64) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+
+        let auction_info = next_account_info(account_info_iter)?;
+        let auction = Self::unpack_auction(&program_id, auction_info)?;
+        if auction.status != AuctionStatus::Active {
+            msg!("auction is not active");
+            return Err(AuctionError::AuctionNotActive.into());
+        }
+        if !matches!(auction.kind, AuctionKind::Sealed { .. }) {
+            msg!("only sealed auctions accept committed bids");
+            return Err(AuctionError::WrongAuctionKind.into());
+        }
+        let now = Clock::get()?.unix_timestamp;
+        if now < auction.start_ts {
+            msg!("auction not started");
+            return Err(AuctionError::AuctionNotStarted.into());
+        }
+        if now >= auction.end_ts {
+            msg!("auction ended");
+            return Err(AuctionError::AuctionEnded.into());
+        }
+
+        let bidder = next_account_info(account_info_iter)?;
+        if !bidder.is_signer {
+            msg!("bidder need sign");
+            return Err(ProgramError::MissingRequiredSignature);
+        }
+
+        // 出价账户由出价者预先创建（属于本合约），这里只初始化数据
+        let bid_info = next_account_info(account_info_iter)?;
+        if bid_info.owner != &program_id {
+            msg!("bid account is not owned by the program");
+            return Err(ProgramError::IncorrectProgramId);
+        }
+        if SealedBid::unpack_unchecked(&bid_info.data.borrow())?.is_initialized() {
+            msg!("bid account already initialized");
+            return Err(AuctionError::AlreadyInitialized.into());
+        }
+
+        let item_receiver_info = next_account_info(account_info_iter)?;
+        let item_receiver = spl_token::state::Account::unpack(&item_receiver_info.data.borrow())?;
+        if item_receiver.mint != auction.item {
+            msg!("item receiver mismatch");
+            return Err(ProgramError::InvalidAccountData);
+        }
+
+        let payer = next_account_info(account_info_iter)?;
+        let money_holder_info = next_account_info(account_info_iter)?;
+        if *money_holder_info.key != auction.money_holder {
+            msg!("money holder mismatch");
+            return Err(ProgramError::InvalidAccountData);
+        }
+        let currency = next_account_info(account_info_iter)?;
+        let decimals = Self::mint_decimals(currency, &auction.currency)?;
+
+        // 托管保证金（保证金金额是公开的，实际出价不能超过保证金）
+        Self::pay(accounts, &auction.currency, payer.key, bidder.key, &auction.money_holder, deposit, decimals)?;
+
+        let bid = SealedBid {
+            auction: *auction_info.key,
+            bidder: *bidder.key,
+            item_receiver: *item_receiver_info.key,
+            refund_address: *payer.key,
+            commitment,
+            deposit,
+            price: 0,
+            revealed: false,
+        };
+        SealedBid::pack(bid, &mut bid_info.data.borrow_mut())?;
+        Ok(())
+    }
+
+    pub fn process_reveal_bid(program_id: Pubkey, accounts: &[AccountInfo], price: u64, salt: [u8; 32]) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+
+        let auction_info = next_account_info(account_info_iter)?;
+        let mut auction = Self::unpack_auction(&program_id, auction_info)?;
+        if auction.status != AuctionStatus::Active {
+            msg!("auction is not active");
+            return Err(AuctionError::AuctionNotActive.into());
+        }
+        let reveal_end_ts = match auction.kind {
+            AuctionKind::Sealed { reveal_end_ts } => reveal_end_ts,
+            _ => {
+                msg!("only sealed auctions accept reveals");
+                return Err(AuctionError::WrongAuctionKind.into());
+            }
+        };
+        // 只能在出价结束以后、公开阶段结束之前公开
+        let now = Clock::get()?.unix_timestamp;
+        if now < auction.end_ts {
+            msg!("bidding not ended");
+            return Err(AuctionError::AuctionNotEnded.into());
+        }
+        if now >= reveal_end_ts {
+            msg!("reveal phase ended");
+            return Err(AuctionError::AuctionEnded.into());
+        }
+
+        let bidder = next_account_info(account_info_iter)?;
+        let bid_info = next_account_info(account_info_iter)?;
+        let mut bid = Self::sealed_bid(&program_id, bid_info, auction_info.key, bidder)?;
+        if bid.revealed || bid_commitment(price, &salt) != bid.commitment {
+            msg!("bid reveal mismatch");
+            return Err(AuctionError::InvalidBidReveal.into());
+        }
+        if price > bid.deposit {
+            msg!("bid price {} is above the deposit {}", price, bid.deposit);
+            return Err(AuctionError::DepositTooLow.into());
+        }
+        bid.revealed = true;
+        bid.price = price;
+        SealedBid::pack(bid, &mut bid_info.data.borrow_mut())?;
+
+        // 高于起拍价和当前最高出价的公开出价成为新的获胜者（出价相同时先公开的获胜）
+        if price > auction.price {
+            auction.price = price;
+            auction.bidder = bid.item_receiver;
+            auction.refund_address = *bid_info.key;
+            Auction::pack(auction, &mut auction_info.data.borrow_mut())?;
+        }
+        Ok(())
+    }
+
+    pub fn process_withdraw_deposit(program_id: Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+
+        let auction_info = next_account_info(account_info_iter)?;
+        let auction = Self::unpack_auction(&program_id, auction_info)?;
+        if auction.status != AuctionStatus::Settled {
+            msg!("auction not settled");
+            return Err(AuctionError::AuctionNotEnded.into());
+        }
+
+        let bidder = next_account_info(account_info_iter)?;
+        let bid_info = next_account_info(account_info_iter)?;
+        let bid = Self::sealed_bid(&program_id, bid_info, auction_info.key, bidder)?;
+
+        let refund_info = next_account_info(account_info_iter)?;
+        if *refund_info.key != bid.refund_address {
+            msg!("refund address mismatch");
+            return Err(ProgramError::InvalidAccountData);
+        }
+        let currency = next_account_info(account_info_iter)?;
+        let decimals = Self::mint_decimals(currency, &auction.currency)?;
+
+        // 获胜者的出价已经在结算时转给卖家
+        let is_winner = auction.has_bids() && auction.refund_address == *bid_info.key;
+        let amount = if is_winner {
+            bid.deposit.checked_sub(auction.price).ok_or(ProgramError::InvalidAccountData)?
+        } else {
+            bid.deposit
+        };
+        if amount > 0 {
+            let bump = [auction.authority_bump];
+            let authority_seeds = auction.authority_seeds(auction_info.key, &bump);
+            let pda = Pubkey::create_program_address(&authority_seeds, &program_id)?;
+            Self::release_escrow(
+                &program_id,
+                accounts,
+                auction_info.key,
+                &auction,
+                &authority_seeds,
+                &pda,
+                refund_info.key,
+                amount,
+                decimals,
+            )?;
+        }
+
+        // 关闭出价账户，租金退还给出价者
+        let bidder_starting_lamports = bidder.lamports();
+        **bidder.lamports.borrow_mut() = bidder_starting_lamports
+            .checked_add(bid_info.lamports())
+            .ok_or(ProgramError::InvalidAccountData)?;
+        **bid_info.lamports.borrow_mut() = 0;
+        bid_info.data.borrow_mut().fill(0);
+        bid_info.assign(&system_program::id());
+        Self::close_drained_vault(&program_id, accounts, auction_info.key, &auction)
+    }
+
+    pub fn process_claim_refund(program_id: Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+
+        let auction_info = next_account_info(account_info_iter)?;
+        let auction = Self::unpack_auction(&program_id, auction_info)?;
+
+        let bidder = next_account_info(account_info_iter)?;
+        if !bidder.is_signer {
+            msg!("bidder need sign");
+            return Err(ProgramError::MissingRequiredSignature);
+        }
+
+        let refund_claim_info = next_account_info(account_info_iter)?;
+        let (refund_claim_address, _) = find_refund_claim_address(&program_id, auction_info.key, bidder.key);
+        if *refund_claim_info.key != refund_claim_address {
+            msg!("refund claim address mismatch");
+            return Err(ProgramError::InvalidSeeds);
+        }
+        if refund_claim_info.owner != &program_id {
+            msg!("nothing to claim");
+            return Err(AuctionError::NothingToClaim.into());
+        }
+        let mut refund_claim = RefundClaim::unpack(&refund_claim_info.data.borrow())?;
+        if refund_claim.amount == 0 {
+            msg!("nothing to claim");
+            return Err(AuctionError::NothingToClaim.into());
+        }
+
+        // 出价者签名，可以领取到自己任意的出价代币账户
+        let refund_info = next_account_info(account_info_iter)?;
+        Self::check_currency_account(refund_info, &auction.currency, None)?;
+        let money_holder_info = next_account_info(account_info_iter)?;
+        if *money_holder_info.key != auction.money_holder {
+            msg!("money holder mismatch");
+            return Err(ProgramError::InvalidAccountData);
+        }
+        let currency = next_account_info(account_info_iter)?;
+        let decimals = Self::mint_decimals(currency, &auction.currency)?;
+
+        let bump = [auction.authority_bump];
+        let authority_seeds = auction.authority_seeds(auction_info.key, &bump);
+        let pda = Pubkey::create_program_address(&authority_seeds, &program_id)?;
+        Self::release_escrow(
+            &program_id,
             accounts,
-            &[&[&auction.seller.to_bytes(), &[seed]]],
+            auction_info.key,
+            &auction,
+            &authority_seeds,
+            &pda,
+            refund_info.key,
+            refund_claim.amount,
+            decimals,
         )?;
 
-        // TODO add close status to auction
+        refund_claim.amount = 0;
+        RefundClaim::pack(refund_claim, &mut refund_claim_info.data.borrow_mut())?;
+        Self::close_drained_vault(&program_id, accounts, auction_info.key, &auction)
+    }
 
-        Ok(())
+    pub fn process_claim_batch_order(program_id: Pubkey, accounts: &[AccountInfo], index: u8) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+
+        let auction_info = next_account_info(account_info_iter)?;
+        let auction = Self::unpack_auction(&program_id, auction_info)?;
+        if auction.kind != AuctionKind::Batch {
+            msg!("only batch auctions have orders to claim");
+            return Err(AuctionError::WrongAuctionKind.into());
+        }
+        // 关闭拍卖时才记下每个出价的成交数量和退款
+        if auction.status != AuctionStatus::Settled {
+            msg!("auction not settled");
+            return Err(AuctionError::AuctionNotEnded.into());
+        }
+        let order_book_info = Self::find_order_book(&program_id, accounts, auction_info.key)?;
+        let mut order_book = Self::unpack_order_book(&program_id, order_book_info)?;
+        let order = *order_book.orders().get(index as usize).ok_or(ProgramError::InvalidArgument)?;
+        if order.claimed || (order.filled == 0 && order.refund == 0) {
+            msg!("nothing to claim");
+            return Err(AuctionError::NothingToClaim.into());
+        }
+
+        // 任何人都可以调用，拍品和退款只能转到出价时记下的账户
+        let item_receiver_info = next_account_info(account_info_iter)?;
+        if *item_receiver_info.key != order.bidder {
+            msg!("item receiver mismatch");
+            return Err(ProgramError::InvalidAccountData);
+        }
+        let refund_info = next_account_info(account_info_iter)?;
+        if *refund_info.key != order.refund_address {
+            msg!("refund address mismatch");
+            return Err(ProgramError::InvalidAccountData);
+        }
+
+        let currency = next_account_info(account_info_iter)?;
+        let currency_decimals = Self::mint_decimals(currency, &auction.currency)?;
+        let item = next_account_info(account_info_iter)?;
+        let item_decimals = Self::mint_decimals(item, &auction.item)?;
+
+        let bump = [auction.authority_bump];
+        let authority_seeds = auction.authority_seeds(auction_info.key, &bump);
+        let pda = Pubkey::create_program_address(&authority_seeds, &program_id)?;
+        if order.filled > 0 {
+            Self::transfer_from_custody(
+                accounts,
+                &authority_seeds,
+                &pda,
+                &auction.item_holder,
+                &auction.item,
+                &order.bidder,
+                order.filled,
+                item_decimals,
+            )?;
+        }
+        if order.refund > 0 {
+            Self::release_escrow(
+                &program_id,
+                accounts,
+                auction_info.key,
+                &auction,
+                &authority_seeds,
+                &pda,
+                &order.refund_address,
+                order.refund,
+                currency_decimals,
+            )?;
+        }
+
+        order_book.bids[index as usize].claimed = true;
+        BatchOrderBook::pack(order_book, &mut order_book_info.data.borrow_mut())?;
+        Self::close_drained_vault(&program_id, accounts, auction_info.key, &auction)
     }
 
     pub fn process(program_id: &Pubkey, accounts: &[AccountInfo], input: &[u8]) -> ProgramResult {
         let instruction = AuctionInstruction::unpack(input)?;
 
         match instruction {
-            AuctionInstruction::CreateAuction { start_price } => {
+            AuctionInstruction::CreateAuction {
+                start_price,
+                start_ts,
+                end_ts,
+                extension_secs,
+                min_increment,
+                increment_kind,
+                reserve_hash,
+                kind,
+                quantity,
+                fees,
+            } => {
                 msg!("Instruction: CreateAuction");
-                Self::process_create_auction(*program_id, accounts, start_price)
+                Self::process_create_auction(
+                    *program_id,
+                    accounts,
+                    start_price,
+                    start_ts,
+                    end_ts,
+                    extension_secs,
+                    min_increment,
+                    increment_kind,
+                    reserve_hash,
+                    kind,
+                    quantity,
+                    fees,
+                )
             }
-            AuctionInstruction::Bidding { price, decimals } => {
+            AuctionInstruction::Bidding { price } => {
                 msg!("Instruction: Bidding");
-                Self::process_bidding(*program_id, accounts, price, decimals)
+                Self::process_bidding(*program_id, accounts, price)
             }
-            AuctionInstruction::CloseAuction => {
+            AuctionInstruction::CloseAuction { reserve_reveal } => {
                 msg!("Instruction: CloseAuction");
-                Self::process_close_auction(*program_id, accounts)
+                Self::process_close_auction(*program_id, accounts, reserve_reveal)
+            }
+            AuctionInstruction::CancelAuction => {
+                msg!("Instruction: CancelAuction");
+                Self::process_cancel_auction(*program_id, accounts)
+            }
+            AuctionInstruction::Buy { max_price } => {
+                msg!("Instruction: Buy");
+                Self::process_buy(*program_id, accounts, max_price)
+            }
+            AuctionInstruction::CommitBid { commitment, deposit } => {
+                msg!("Instruction: CommitBid");
+                Self::process_commit_bid(*program_id, accounts, commitment, deposit)
+            }
+            AuctionInstruction::RevealBid { price, salt } => {
+                msg!("Instruction: RevealBid");
+                Self::process_reveal_bid(*program_id, accounts, price, salt)
+            }
+            AuctionInstruction::WithdrawDeposit => {
+                msg!("Instruction: WithdrawDeposit");
+                Self::process_withdraw_deposit(*program_id, accounts)
+            }
+            AuctionInstruction::BatchBid { price, quantity } => {
+                msg!("Instruction: BatchBid");
+                Self::process_batch_bid(*program_id, accounts, price, quantity)
+            }
+            AuctionInstruction::ClaimRefund => {
+                msg!("Instruction: ClaimRefund");
+                Self::process_claim_refund(*program_id, accounts)
+            }
+            AuctionInstruction::ClaimBatchOrder { index } => {
+                msg!("Instruction: ClaimBatchOrder");
+                Self::process_claim_batch_order(*program_id, accounts, index)
             }
             _ => Err(ProgramError::InvalidInstructionData),
         }
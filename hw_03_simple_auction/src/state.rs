@@ -1,12 +1,275 @@
 use arrayref::{array_mut_ref, array_ref, array_refs, mut_array_refs};
+use num_enum::{IntoPrimitive, TryFromPrimitive};
 use solana_program::{
+    hash::{hashv, Hash},
     program_error::ProgramError,
     program_pack::{IsInitialized, Pack, Sealed},
     pubkey::Pubkey,
 };
+use std::convert::TryFrom;
+use crate::error::AuctionError;
 
 // 该文件定义实际要存储数据的结构体
 
+/// auction lifecycle status.
+/// 拍卖的状态（只能按 Created -> Active -> Ended -> Settled 的顺序流转，结算之前可以取消）
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, IntoPrimitive, TryFromPrimitive)]
+pub enum AuctionStatus {
+    Created,   // auction account initialized, not accepting bids yet
+    Active,    // item and start price escrowed, accepting bids
+    Ended,     // bidding is over, waiting for settlement
+    Settled,   // money sent to the seller and item sent to the winner (or both returned when the reserve is not met)
+    Cancelled, // auction cancelled by the seller
+}
+
+// 注意：不能使用 #[default]，num_enum 会把未知的值都转换成默认值
+#[allow(clippy::derivable_impls)]
+impl Default for AuctionStatus {
+    fn default() -> Self {
+        AuctionStatus::Created
+    }
+}
+
+impl AuctionStatus {
+    /// 状态流转（不允许的状态流转返回 InvalidStatusTransition）
+    pub fn transition(self, next: AuctionStatus) -> Result<AuctionStatus, AuctionError> {
+        use AuctionStatus::*;
+        match (self, next) {
+            (Created, Active)
+            | (Active, Ended)
+            | (Ended, Settled)
+            | (Created, Cancelled)
+            | (Active, Cancelled) => Ok(next),
+            _ => Err(AuctionError::InvalidStatusTransition),
+        }
+    }
+}
+
+/// 拍卖方式
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AuctionKind {
+    // 英式拍卖（价格递增，出价最高的人在结束以后获得拍品）
+    English,
+    // 荷兰式拍卖（价格从 start_price 开始每秒降低 decay_per_second，最低降到 floor_price，第一个购买的人直接成交）
+    Dutch { start_price: u64, floor_price: u64, decay_per_second: u64 },
+    // 密封拍卖（end_ts 之前提交出价的哈希并托管保证金，end_ts 到 reveal_end_ts 之间公开出价，公开的最高出价获胜）
+    Sealed { reveal_end_ts: i64 },
+    // 批量拍卖（卖家出售 quantity 个单位，每个出价包含单价和数量，结束以后按照统一的成交价格从高到低分配）
+    Batch,
+}
+
+#[allow(clippy::derivable_impls)]
+impl Default for AuctionKind {
+    fn default() -> Self {
+        AuctionKind::English
+    }
+}
+
+impl AuctionKind {
+    /// 打包后的长度（类型 1个字节 + start_price 8个字节 + floor_price 8个字节 + decay_per_second 8个字节，密封拍卖只使用前8个字节，批量拍卖只有类型）
+    pub const LEN: usize = 25;
+
+    pub fn unpack(src: &[u8; 25]) -> Result<Self, ProgramError> {
+        let (tag, start_price, floor_price, decay_per_second) = array_refs![src, 1, 8, 8, 8];
+        Ok(match tag[0] {
+            0 => AuctionKind::English,
+            1 => AuctionKind::Dutch {
+                start_price: u64::from_le_bytes(*start_price),
+                floor_price: u64::from_le_bytes(*floor_price),
+                decay_per_second: u64::from_le_bytes(*decay_per_second),
+            },
+            2 => AuctionKind::Sealed { reveal_end_ts: i64::from_le_bytes(*start_price) },
+            3 => AuctionKind::Batch,
+            _ => return Err(ProgramError::InvalidAccountData),
+        })
+    }
+
+    pub fn pack(&self, dst: &mut [u8; 25]) {
+        let (tag_dst, start_price_dst, floor_price_dst, decay_per_second_dst) = mut_array_refs![dst, 1, 8, 8, 8];
+        match *self {
+            AuctionKind::English => {
+                tag_dst[0] = 0;
+                *start_price_dst = [0; 8];
+                *floor_price_dst = [0; 8];
+                *decay_per_second_dst = [0; 8];
+            }
+            AuctionKind::Dutch { start_price, floor_price, decay_per_second } => {
+                tag_dst[0] = 1;
+                *start_price_dst = start_price.to_le_bytes();
+                *floor_price_dst = floor_price.to_le_bytes();
+                *decay_per_second_dst = decay_per_second.to_le_bytes();
+            }
+            AuctionKind::Sealed { reveal_end_ts } => {
+                tag_dst[0] = 2;
+                *start_price_dst = reveal_end_ts.to_le_bytes();
+                *floor_price_dst = [0; 8];
+                *decay_per_second_dst = [0; 8];
+            }
+            AuctionKind::Batch => {
+                tag_dst[0] = 3;
+                *start_price_dst = [0; 8];
+                *floor_price_dst = [0; 8];
+                *decay_per_second_dst = [0; 8];
+            }
+        }
+    }
+}
+
+/// 成交价的分成（版税给创作者，手续费给平台，剩下的给卖家，接收账户是出价代币的账户，比例为0时不分成）
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct FeeSplit {
+    pub creator: Pubkey,       // token account receiving the creator royalty
+    pub royalty_bps: u16,      // creator royalty in basis points (1/10000)
+    pub platform: Pubkey,      // token account receiving the marketplace fee
+    pub platform_fee_bps: u16, // marketplace fee in basis points (1/10000)
+}
+
+impl FeeSplit {
+    /// 打包后的长度
+    pub const LEN: usize = 68;
+
+    pub fn unpack(src: &[u8; 68]) -> Self {
+        let (creator, royalty_bps, platform, platform_fee_bps) = array_refs![src, 32, 2, 32, 2];
+        FeeSplit {
+            creator: Pubkey::new_from_array(*creator),
+            royalty_bps: u16::from_le_bytes(*royalty_bps),
+            platform: Pubkey::new_from_array(*platform),
+            platform_fee_bps: u16::from_le_bytes(*platform_fee_bps),
+        }
+    }
+
+    pub fn pack(&self, dst: &mut [u8; 68]) {
+        let (creator_dst, royalty_bps_dst, platform_dst, platform_fee_bps_dst) = mut_array_refs![dst, 32, 2, 32, 2];
+        creator_dst.copy_from_slice(self.creator.as_ref());
+        *royalty_bps_dst = self.royalty_bps.to_le_bytes();
+        platform_dst.copy_from_slice(self.platform.as_ref());
+        *platform_fee_bps_dst = self.platform_fee_bps.to_le_bytes();
+    }
+
+    /// 分成比例合计不能超过100%，设置了比例就必须有接收账户
+    pub fn is_valid(&self) -> bool {
+        self.royalty_bps as u32 + self.platform_fee_bps as u32 <= 10_000
+            && (self.royalty_bps == 0 || self.creator != Pubkey::default())
+            && (self.platform_fee_bps == 0 || self.platform != Pubkey::default())
+    }
+
+    /**
+     * 计算分成（版税和手续费向下取整，取整剩下的零头都给卖家，三部分加起来正好等于 amount）
+     * @param amount 成交的钱
+     * @return (卖家, 创作者, 平台)
+     */
+    pub fn split(&self, amount: u64) -> (u64, u64, u64) {
+        let share = |bps: u16| (amount as u128 * bps as u128 / 10_000) as u64;
+        let royalty = share(self.royalty_bps);
+        let platform_fee = share(self.platform_fee_bps);
+        (amount - royalty - platform_fee, royalty, platform_fee)
+    }
+}
+
+// 批量拍卖的出价簿容量（容量太大出价簿账户的租金太高）
+pub const MAX_BATCH_BIDS: usize = 8;
+
+/// 批量拍卖的一个出价（托管的钱是 price * quantity，关闭拍卖时记下成交数量和退款，由 ClaimBatchOrder 领取）
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct BatchOrder {
+    pub bidder: Pubkey,         // item receiver of this bid
+    pub refund_address: Pubkey, // where the unused part of the escrow returns
+    pub price: u64,             // price per unit
+    pub quantity: u64,          // units wanted
+    pub wallet: Pubkey,         // wallet that signed the bid, credited in its RefundClaim when the order is evicted
+    pub filled: u64,            // units won, recorded when the auction is closed
+    pub refund: u64,            // unused part of the escrow, recorded when the auction is closed
+    pub claimed: bool,          // whether the fill and refund were sent with ClaimBatchOrder
+}
+
+impl BatchOrder {
+    /// 打包后的长度
+    pub const LEN: usize = 129;
+
+    pub fn unpack(src: &[u8; 129]) -> Self {
+        let (bidder, refund_address, price, quantity, wallet, filled, refund, claimed) =
+            array_refs![src, 32, 32, 8, 8, 32, 8, 8, 1];
+        BatchOrder {
+            bidder: Pubkey::new_from_array(*bidder),
+            refund_address: Pubkey::new_from_array(*refund_address),
+            price: u64::from_le_bytes(*price),
+            quantity: u64::from_le_bytes(*quantity),
+            wallet: Pubkey::new_from_array(*wallet),
+            filled: u64::from_le_bytes(*filled),
+            refund: u64::from_le_bytes(*refund),
+            claimed: claimed[0] != 0,
+        }
+    }
+
+    pub fn pack(&self, dst: &mut [u8; 129]) {
+        let (bidder_dst, refund_address_dst, price_dst, quantity_dst, wallet_dst, filled_dst, refund_dst, claimed_dst) =
+            mut_array_refs![dst, 32, 32, 8, 8, 32, 8, 8, 1];
+        bidder_dst.copy_from_slice(self.bidder.as_ref());
+        refund_address_dst.copy_from_slice(self.refund_address.as_ref());
+        *price_dst = self.price.to_le_bytes();
+        *quantity_dst = self.quantity.to_le_bytes();
+        wallet_dst.copy_from_slice(self.wallet.as_ref());
+        *filled_dst = self.filled.to_le_bytes();
+        *refund_dst = self.refund.to_le_bytes();
+        claimed_dst[0] = self.claimed as u8;
+    }
+
+    /// 托管的钱（溢出时返回None）
+    pub fn escrow(&self) -> Option<u64> {
+        self.price.checked_mul(self.quantity)
+    }
+}
+
+// 拍卖托管账户PDA地址的种子前缀
+pub const AUCTION_SEED: &[u8] = b"auction";
+
+/**
+ * 获取拍卖托管账户的PDA地址（拍品和出价的托管账户都属于这个地址，每个拍卖对应一个地址，同一个卖家可以同时进行多个拍卖）
+ * @param program_id 合约ID
+ * @param seller     卖家地址
+ * @param auction    拍卖账户地址
+ */
+pub fn find_auction_authority(program_id: &Pubkey, seller: &Pubkey, auction: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[AUCTION_SEED, seller.as_ref(), auction.as_ref()], program_id)
+}
+
+/// 加价幅度的计算方式
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, IntoPrimitive, TryFromPrimitive)]
+pub enum IncrementKind {
+    Absolute,    // min_increment is an amount of currency base units
+    BasisPoints, // min_increment is a fraction of the current price in basis points (1/10000)
+}
+
+#[allow(clippy::derivable_impls)]
+impl Default for IncrementKind {
+    fn default() -> Self {
+        IncrementKind::Absolute
+    }
+}
+
+/// 卖家没有公开保留价时，拍卖结束以后再过这么长时间任何人都可以按照未达到保留价关闭拍卖（秒）
+pub const RESERVE_REVEAL_SECS: i64 = 24 * 60 * 60;
+
+/**
+ * 密封出价的承诺值（提交出价时只保存这个哈希，公开阶段再公开价格和随机盐）
+ * @param price 出价
+ * @param salt  随机盐
+ */
+pub fn bid_commitment(price: u64, salt: &[u8; 32]) -> Hash {
+    hashv(&[&price.to_le_bytes(), salt])
+}
+
+/**
+ * 保留价的承诺值（创建拍卖时只保存这个哈希，关闭拍卖时再公开保留价）
+ * @param reserve_price 保留价
+ * @param salt          随机盐（防止通过穷举价格猜出保留价）
+ */
+pub fn reserve_commitment(reserve_price: u64, salt: &[u8; 32]) -> Hash {
+    hashv(&[&reserve_price.to_le_bytes(), salt])
+}
+
 /// auction data.
 #[repr(C)]
 #[derive(Clone, Copy, Debug, Default, PartialEq)]
@@ -17,8 +280,99 @@ pub struct Auction {
     pub currency: Pubkey,       // token mint about using which type of token to bid
     pub money_holder: Pubkey,   // a custodian account of the bidding money (controlled by pda)
     pub bidder: Pubkey,         // the highest price bidder
-    pub refund_address: Pubkey, // if someone bid a higher price, the previous price will return back to this address
+    pub refund_address: Pubkey, // if someone bid a higher price, the previous price will return back to this address (sealed: the winning bid record)
     pub price: u64,             // price for the item now
+    pub status: AuctionStatus,  // lifecycle status of this auction
+    pub start_ts: i64,          // unix timestamp when bidding opens
+    pub end_ts: i64,            // unix timestamp when bidding closes (may be extended by late bids)
+    pub extension_secs: u32,    // a bid in the last extension_secs seconds moves end_ts to now + extension_secs (0 disables)
+    pub min_increment: u64,     // a new bid must beat price by at least this much (see increment_kind)
+    pub increment_kind: IncrementKind, // whether min_increment is absolute or in basis points
+    pub reserve_hash: Hash,     // commitment of the hidden reserve price (default hash means no reserve)
+    pub start_price: u64,       // price escrowed by the seller when the auction was created
+    pub authority_bump: u8,     // bump of the custody pda (see find_auction_authority)
+    pub kind: AuctionKind,      // english (ascending bids), dutch (descending price, first buy wins), sealed (commit/reveal) or batch (uniform clearing price)
+    pub quantity: u64,          // units of the item escrowed by the seller (sold as one lot unless the auction is a batch auction)
+    pub fees: FeeSplit,         // creator royalty and marketplace fee taken from the sale price
+    pub bid_count: u64,         // number of accepted Bidding (also the sequence number of the next BidRecord) or BatchBid calls
+    pub top_bidder: Pubkey,     // wallet behind the current highest bid (the seller before the first bid), credited with the refund when outbid
+}
+
+impl Auction {
+    /// 下一次出价的最低价格（至少比当前价格高1，溢出时返回None）
+    pub fn min_next_bid(&self) -> Option<u64> {
+        self.min_bid_over(self.price)
+    }
+
+    /**
+     * 要超过某个价格至少需要出的价格（至少比这个价格高1，溢出时返回None）
+     * @param price 要超过的价格
+     */
+    pub fn min_bid_over(&self, price: u64) -> Option<u64> {
+        let increment = match self.increment_kind {
+            IncrementKind::Absolute => self.min_increment,
+            // 按照这个价格的万分比计算，向上取整
+            IncrementKind::BasisPoints => {
+                let increment = (price as u128 * self.min_increment as u128).div_ceil(10_000);
+                u64::try_from(increment).ok()?
+            }
+        };
+        price.checked_add(increment.max(1))
+    }
+
+    /// 有没有人出过价（出价必须高于起拍价，所以价格高于起拍价就说明有人出过价，批量拍卖看出价次数）
+    pub fn has_bids(&self) -> bool {
+        match self.kind {
+            AuctionKind::Batch => self.bid_count > 0,
+            _ => self.price > self.start_price,
+        }
+    }
+
+    /**
+     * 托管账户PDA的签名种子（使用保存的bump，不需要重新计算）
+     * @param auction_key 拍卖账户地址
+     * @param bump        [authority_bump]
+     */
+    pub fn authority_seeds<'a>(&'a self, auction_key: &'a Pubkey, bump: &'a [u8; 1]) -> [&'a [u8]; 4] {
+        [AUCTION_SEED, self.seller.as_ref(), auction_key.as_ref(), bump]
+    }
+
+    /// 有没有设置保留价
+    pub fn has_reserve(&self) -> bool {
+        self.reserve_hash != Hash::default()
+    }
+
+    /// 结算时从 money_holder 转出的钱（英式拍卖是当前最高出价，荷兰式拍卖成交时直接付款给卖家，不托管，密封拍卖是获胜者的出价，批量拍卖托管的钱见 BatchOrderBook::escrowed_amount）
+    pub fn escrowed_amount(&self) -> u64 {
+        match self.kind {
+            AuctionKind::English => self.price,
+            AuctionKind::Sealed { .. } if self.has_bids() => self.price,
+            AuctionKind::Dutch { .. } | AuctionKind::Sealed { .. } | AuctionKind::Batch => 0,
+        }
+    }
+
+    /// 可以关闭拍卖的时间（密封拍卖要等公开阶段结束）
+    pub fn close_ts(&self) -> i64 {
+        match self.kind {
+            AuctionKind::Sealed { reveal_end_ts } => reveal_end_ts,
+            _ => self.end_ts,
+        }
+    }
+
+    /**
+     * 荷兰式拍卖的当前价格（不是荷兰式拍卖返回None）
+     * @param now 当前时间戳
+     */
+    pub fn dutch_price(&self, now: i64) -> Option<u64> {
+        match self.kind {
+            AuctionKind::English | AuctionKind::Sealed { .. } | AuctionKind::Batch => None,
+            AuctionKind::Dutch { start_price, floor_price, decay_per_second } => {
+                let elapsed = now.saturating_sub(self.start_ts).max(0) as u64;
+                let decay = decay_per_second.saturating_mul(elapsed);
+                Some(start_price.saturating_sub(decay).max(floor_price))
+            }
+        }
+    }
 }
 
 impl Sealed for Auction {}
@@ -29,11 +383,33 @@ impl IsInitialized for Auction {
 }
 
 impl Pack for Auction {
-    const LEN: usize = 232;
+    const LEN: usize = 444;
     fn unpack_from_slice(src: &[u8]) -> Result<Self, ProgramError> {
-        let src = array_ref![src, 0, 232];
-        let (seller, item, item_holder, currency, money_holder, bidder, refund_address, price) =
-            array_refs![src, 32, 32, 32, 32, 32, 32, 32, 8];
+        let src = array_ref![src, 0, 444];
+        let (
+            seller,
+            item,
+            item_holder,
+            currency,
+            money_holder,
+            bidder,
+            refund_address,
+            price,
+            status,
+            start_ts,
+            end_ts,
+            extension_secs,
+            min_increment,
+            increment_kind,
+            reserve_hash,
+            start_price,
+            authority_bump,
+            kind,
+            quantity,
+            fees,
+            bid_count,
+            top_bidder,
+        ) = array_refs![src, 32, 32, 32, 32, 32, 32, 32, 8, 1, 8, 8, 4, 8, 1, 32, 8, 1, 25, 8, 68, 8, 32];
         Ok(Auction {
             seller: Pubkey::new_from_array(*seller),
             item: Pubkey::new_from_array(*item),
@@ -43,10 +419,24 @@ impl Pack for Auction {
             bidder: Pubkey::new_from_array(*bidder),
             refund_address: Pubkey::new_from_array(*refund_address),
             price: u64::from_le_bytes(*price),
+            status: AuctionStatus::try_from(status[0]).map_err(|_| ProgramError::InvalidAccountData)?,
+            start_ts: i64::from_le_bytes(*start_ts),
+            end_ts: i64::from_le_bytes(*end_ts),
+            extension_secs: u32::from_le_bytes(*extension_secs),
+            min_increment: u64::from_le_bytes(*min_increment),
+            increment_kind: IncrementKind::try_from(increment_kind[0]).map_err(|_| ProgramError::InvalidAccountData)?,
+            reserve_hash: Hash::new_from_array(*reserve_hash),
+            start_price: u64::from_le_bytes(*start_price),
+            authority_bump: authority_bump[0],
+            kind: AuctionKind::unpack(kind)?,
+            quantity: u64::from_le_bytes(*quantity),
+            fees: FeeSplit::unpack(fees),
+            bid_count: u64::from_le_bytes(*bid_count),
+            top_bidder: Pubkey::new_from_array(*top_bidder),
         })
     }
     fn pack_into_slice(&self, dst: &mut [u8]) {
-        let dst = array_mut_ref![dst, 0, 232];
+        let dst = array_mut_ref![dst, 0, 444];
         let (
             seller_dst,
             item_dst,
@@ -56,7 +446,21 @@ impl Pack for Auction {
             bidder_dst,
             refund_address_dst,
             price_dst,
-        ) = mut_array_refs![dst, 32, 32, 32, 32, 32, 32, 32, 8];
+            status_dst,
+            start_ts_dst,
+            end_ts_dst,
+            extension_secs_dst,
+            min_increment_dst,
+            increment_kind_dst,
+            reserve_hash_dst,
+            start_price_dst,
+            authority_bump_dst,
+            kind_dst,
+            quantity_dst,
+            fees_dst,
+            bid_count_dst,
+            top_bidder_dst,
+        ) = mut_array_refs![dst, 32, 32, 32, 32, 32, 32, 32, 8, 1, 8, 8, 4, 8, 1, 32, 8, 1, 25, 8, 68, 8, 32];
         let &Auction {
             ref seller,
             ref item,
@@ -66,6 +470,20 @@ impl Pack for Auction {
             ref bidder,
             ref refund_address,
             price,
+            status,
+            start_ts,
+            end_ts,
+            extension_secs,
+            min_increment,
+            increment_kind,
+            ref reserve_hash,
+            start_price,
+            authority_bump,
+            ref kind,
+            quantity,
+            ref fees,
+            bid_count,
+            ref top_bidder,
         } = self;
         seller_dst.copy_from_slice(seller.as_ref());
         item_dst.copy_from_slice(item.as_ref());
@@ -75,6 +493,296 @@ impl Pack for Auction {
         bidder_dst.copy_from_slice(bidder.as_ref());
         refund_address_dst.copy_from_slice(refund_address.as_ref());
         *price_dst = price.to_le_bytes();
+        status_dst[0] = status.into();
+        *start_ts_dst = start_ts.to_le_bytes();
+        *end_ts_dst = end_ts.to_le_bytes();
+        *extension_secs_dst = extension_secs.to_le_bytes();
+        *min_increment_dst = min_increment.to_le_bytes();
+        increment_kind_dst[0] = increment_kind.into();
+        reserve_hash_dst.copy_from_slice(reserve_hash.as_ref());
+        *start_price_dst = start_price.to_le_bytes();
+        authority_bump_dst[0] = authority_bump;
+        kind.pack(kind_dst);
+        *quantity_dst = quantity.to_le_bytes();
+        fees.pack(fees_dst);
+        *bid_count_dst = bid_count.to_le_bytes();
+        top_bidder_dst.copy_from_slice(top_bidder.as_ref());
+    }
+}
+
+// 出价记录PDA地址的种子前缀
+pub const BID_RECORD_SEED: &[u8] = b"bid";
+
+/**
+ * 获取出价记录的PDA地址（每次出价一个记录，按照拍卖和出价序号区分）
+ * @param program_id 合约ID
+ * @param auction    拍卖账户地址
+ * @param sequence   出价序号（出价时拍卖的 bid_count）
+ */
+pub fn find_bid_record_address(program_id: &Pubkey, auction: &Pubkey, sequence: u64) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[BID_RECORD_SEED, auction.as_ref(), &sequence.to_le_bytes()], program_id)
+}
+
+// 原生SOL金库PDA地址的种子前缀（出价代币是原生SOL时，出价的lamports托管在这个合约拥有的账户里）
+pub const VAULT_SEED: &[u8] = b"vault";
+
+/**
+ * 获取原生SOL金库的PDA地址（每个拍卖一个金库）
+ * @param program_id 合约ID
+ * @param auction    拍卖账户地址
+ */
+pub fn find_vault_address(program_id: &Pubkey, auction: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[VAULT_SEED, auction.as_ref()], program_id)
+}
+
+// 批量拍卖出价簿PDA地址的种子前缀
+pub const ORDER_BOOK_SEED: &[u8] = b"orders";
+
+/**
+ * 获取批量拍卖出价簿的PDA地址（只有批量拍卖才有出价簿，创建拍卖时由卖家付租金创建）
+ * @param program_id 合约ID
+ * @param auction    拍卖账户地址
+ */
+pub fn find_order_book_address(program_id: &Pubkey, auction: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[ORDER_BOOK_SEED, auction.as_ref()], program_id)
+}
+
+/// order book of a batch auction (one pda per batch auction, other kinds of auctions don't pay rent for it).
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct BatchOrderBook {
+    pub auction: Pubkey,                    // the batch auction this order book belongs to
+    pub order_count: u8,                    // number of orders in bids
+    pub bids: [BatchOrder; MAX_BATCH_BIDS], // orders of the batch auction, in bidding order
+}
+
+impl BatchOrderBook {
+    /// 出价簿里的出价
+    pub fn orders(&self) -> &[BatchOrder] {
+        &self.bids[..self.order_count as usize]
+    }
+
+    /// 出价簿满了以后会被挤出去的出价（价格最低的，价格相同时是最后出价的）
+    pub fn lowest_order(&self) -> Option<usize> {
+        self.orders()
+            .iter()
+            .enumerate()
+            .min_by_key(|(index, order)| (order.price, std::cmp::Reverse(*index)))
+            .map(|(index, _)| index)
+    }
+
+    /**
+     * 批量拍卖的成交结果：按照单价从高到低（单价相同时先出价的优先）分配 quantity 个单位，
+     * 返回每个出价成交的数量和统一的成交价格（最后一个成交的出价的单价，没有成交时为0）
+     * @param quantity 拍卖的拍品数量
+     */
+    pub fn fills(&self, quantity: u64) -> ([u64; MAX_BATCH_BIDS], u64) {
+        let orders = self.orders();
+        let mut by_price: Vec<usize> = (0..orders.len()).collect();
+        // 稳定排序，单价相同时保持出价的顺序
+        by_price.sort_by_key(|&index| std::cmp::Reverse(orders[index].price));
+
+        let mut fills = [0u64; MAX_BATCH_BIDS];
+        let mut remaining = quantity;
+        let mut clearing_price = 0;
+        for index in by_price {
+            if remaining == 0 {
+                break;
+            }
+            fills[index] = orders[index].quantity.min(remaining);
+            remaining -= fills[index];
+            clearing_price = orders[index].price;
+        }
+        (fills, clearing_price)
+    }
+
+    /// 所有出价托管的钱
+    pub fn escrowed_amount(&self) -> u64 {
+        self.orders().iter().filter_map(BatchOrder::escrow).sum()
+    }
+}
+
+impl Sealed for BatchOrderBook {}
+impl IsInitialized for BatchOrderBook {
+    fn is_initialized(&self) -> bool {
+        self.auction != Pubkey::default()
+    }
+}
+
+impl Pack for BatchOrderBook {
+    const LEN: usize = 1065;
+    fn unpack_from_slice(src: &[u8]) -> Result<Self, ProgramError> {
+        let src = array_ref![src, 0, 1065];
+        let (auction, order_count, bids_src) = array_refs![src, 32, 1, 1032];
+        if order_count[0] as usize > MAX_BATCH_BIDS {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        let mut bids = [BatchOrder::default(); MAX_BATCH_BIDS];
+        for (bid, bid_src) in bids.iter_mut().zip(bids_src.chunks_exact(BatchOrder::LEN)) {
+            *bid = BatchOrder::unpack(array_ref![bid_src, 0, 129]);
+        }
+        Ok(BatchOrderBook {
+            auction: Pubkey::new_from_array(*auction),
+            order_count: order_count[0],
+            bids,
+        })
+    }
+    fn pack_into_slice(&self, dst: &mut [u8]) {
+        let dst = array_mut_ref![dst, 0, 1065];
+        let (auction_dst, order_count_dst, bids_dst) = mut_array_refs![dst, 32, 1, 1032];
+        auction_dst.copy_from_slice(self.auction.as_ref());
+        order_count_dst[0] = self.order_count;
+        for (bid, bid_dst) in self.bids.iter().zip(bids_dst.chunks_exact_mut(BatchOrder::LEN)) {
+            bid.pack(array_mut_ref![bid_dst, 0, 129]);
+        }
+    }
+}
+
+/// bid history record (one pda per accepted Bidding call, paid by the bidder).
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct BidRecord {
+    pub auction: Pubkey, // the auction this bid was placed on
+    pub bidder: Pubkey,  // who signed the bid
+    pub price: u64,      // bid price
+    pub timestamp: i64,  // unix timestamp of the bid
+    pub sequence: u64,   // position of this bid in the auction's bid history (starts at 0)
+}
+
+impl Sealed for BidRecord {}
+impl IsInitialized for BidRecord {
+    fn is_initialized(&self) -> bool {
+        self.auction != Pubkey::default()
+    }
+}
+
+impl Pack for BidRecord {
+    const LEN: usize = 88;
+    fn unpack_from_slice(src: &[u8]) -> Result<Self, ProgramError> {
+        let src = array_ref![src, 0, 88];
+        let (auction, bidder, price, timestamp, sequence) = array_refs![src, 32, 32, 8, 8, 8];
+        Ok(BidRecord {
+            auction: Pubkey::new_from_array(*auction),
+            bidder: Pubkey::new_from_array(*bidder),
+            price: u64::from_le_bytes(*price),
+            timestamp: i64::from_le_bytes(*timestamp),
+            sequence: u64::from_le_bytes(*sequence),
+        })
+    }
+    fn pack_into_slice(&self, dst: &mut [u8]) {
+        let dst = array_mut_ref![dst, 0, 88];
+        let (auction_dst, bidder_dst, price_dst, timestamp_dst, sequence_dst) = mut_array_refs![dst, 32, 32, 8, 8, 8];
+        auction_dst.copy_from_slice(self.auction.as_ref());
+        bidder_dst.copy_from_slice(self.bidder.as_ref());
+        *price_dst = self.price.to_le_bytes();
+        *timestamp_dst = self.timestamp.to_le_bytes();
+        *sequence_dst = self.sequence.to_le_bytes();
+    }
+}
+
+// 退款记录PDA地址的种子前缀
+pub const REFUND_CLAIM_SEED: &[u8] = b"refund";
+
+/**
+ * 获取退款记录的PDA地址（每个拍卖的每个出价者一个记录，出价被超过时退款记到这里，由出价者自己领取）
+ * @param program_id 合约ID
+ * @param auction    拍卖账户地址
+ * @param bidder     出价者的钱包地址
+ */
+pub fn find_refund_claim_address(program_id: &Pubkey, auction: &Pubkey, bidder: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[REFUND_CLAIM_SEED, auction.as_ref(), bidder.as_ref()], program_id)
+}
+
+/// pending refund of an outbid bidder (one pda per bidder per auction, withdrawn with ClaimRefund).
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct RefundClaim {
+    pub auction: Pubkey, // the auction holding the refund in money_holder
+    pub bidder: Pubkey,  // wallet allowed to claim the refund
+    pub amount: u64,     // currency not yet claimed
+}
+
+impl Sealed for RefundClaim {}
+impl IsInitialized for RefundClaim {
+    fn is_initialized(&self) -> bool {
+        self.auction != Pubkey::default()
+    }
+}
+
+impl Pack for RefundClaim {
+    const LEN: usize = 72;
+    fn unpack_from_slice(src: &[u8]) -> Result<Self, ProgramError> {
+        let src = array_ref![src, 0, 72];
+        let (auction, bidder, amount) = array_refs![src, 32, 32, 8];
+        Ok(RefundClaim {
+            auction: Pubkey::new_from_array(*auction),
+            bidder: Pubkey::new_from_array(*bidder),
+            amount: u64::from_le_bytes(*amount),
+        })
+    }
+    fn pack_into_slice(&self, dst: &mut [u8]) {
+        let dst = array_mut_ref![dst, 0, 72];
+        let (auction_dst, bidder_dst, amount_dst) = mut_array_refs![dst, 32, 32, 8];
+        auction_dst.copy_from_slice(self.auction.as_ref());
+        bidder_dst.copy_from_slice(self.bidder.as_ref());
+        *amount_dst = self.amount.to_le_bytes();
+    }
+}
+
+/// sealed bid record (one account per bidder per sealed auction, allocated by the bidder and owned by this program).
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct SealedBid {
+    pub auction: Pubkey,        // the sealed auction this bid belongs to
+    pub bidder: Pubkey,         // who committed the bid (must sign reveal and withdraw)
+    pub item_receiver: Pubkey,  // where the item goes if this bid wins
+    pub refund_address: Pubkey, // where the deposit returns when withdrawn
+    pub commitment: Hash,       // bid_commitment(price, salt)
+    pub deposit: u64,           // currency escrowed in money_holder when committing
+    pub price: u64,             // revealed price (0 before reveal)
+    pub revealed: bool,         // whether the bid has been revealed
+}
+
+impl Sealed for SealedBid {}
+impl IsInitialized for SealedBid {
+    fn is_initialized(&self) -> bool {
+        self.auction != Pubkey::default()
+    }
+}
+
+impl Pack for SealedBid {
+    const LEN: usize = 177;
+    fn unpack_from_slice(src: &[u8]) -> Result<Self, ProgramError> {
+        let src = array_ref![src, 0, 177];
+        let (auction, bidder, item_receiver, refund_address, commitment, deposit, price, revealed) =
+            array_refs![src, 32, 32, 32, 32, 32, 8, 8, 1];
+        Ok(SealedBid {
+            auction: Pubkey::new_from_array(*auction),
+            bidder: Pubkey::new_from_array(*bidder),
+            item_receiver: Pubkey::new_from_array(*item_receiver),
+            refund_address: Pubkey::new_from_array(*refund_address),
+            commitment: Hash::new_from_array(*commitment),
+            deposit: u64::from_le_bytes(*deposit),
+            price: u64::from_le_bytes(*price),
+            revealed: match revealed[0] {
+                0 => false,
+                1 => true,
+                _ => return Err(ProgramError::InvalidAccountData),
+            },
+        })
+    }
+    fn pack_into_slice(&self, dst: &mut [u8]) {
+        let dst = array_mut_ref![dst, 0, 177];
+        let (auction_dst, bidder_dst, item_receiver_dst, refund_address_dst, commitment_dst, deposit_dst, price_dst, revealed_dst) =
+            mut_array_refs![dst, 32, 32, 32, 32, 32, 8, 8, 1];
+        auction_dst.copy_from_slice(self.auction.as_ref());
+        bidder_dst.copy_from_slice(self.bidder.as_ref());
+        item_receiver_dst.copy_from_slice(self.item_receiver.as_ref());
+        refund_address_dst.copy_from_slice(self.refund_address.as_ref());
+        commitment_dst.copy_from_slice(self.commitment.as_ref());
+        *deposit_dst = self.deposit.to_le_bytes();
+        *price_dst = self.price.to_le_bytes();
+        revealed_dst[0] = self.revealed as u8;
     }
 }
 
@@ -85,7 +793,7 @@ mod test {
 
     #[test]
     fn unpack_auction() {
-        let auction = Auction::unpack_from_slice(&[
+        let mut data = vec![
             155, 223, 245, 186, 87, 123, 202, 87, 3, 244, 165, 72, 147, 80, 16, 41, 197, 14, 57,
             96, 239, 172, 90, 101, 168, 211, 132, 86, 70, 172, 14, 121, 37, 198, 132, 32, 63, 71,
             81, 180, 24, 168, 70, 238, 174, 211, 132, 126, 37, 33, 74, 50, 227, 200, 7, 48, 254,
@@ -98,9 +806,15 @@ mod test {
             211, 60, 235, 243, 101, 71, 225, 0, 169, 229, 25, 3, 152, 105, 127, 221, 19, 72, 200,
             91, 152, 253, 191, 213, 97, 49, 159, 191, 3, 181, 236, 255, 53, 161, 134, 235, 33, 246,
             120, 249, 122, 239, 68, 147, 37, 246, 205, 227, 113, 119, 200, 114, 234, 239, 50, 0, 0,
-            0, 0, 0, 0, 0,
-        ])
-            .unwrap();
+            0, 0, 0, 0, 0, 1, 0, 225, 245, 5, 0, 0, 0, 0, 128, 105, 103, 9, 0, 0, 0, 0, 44, 1, 0,
+            0, 244, 1, 0, 0, 0, 0, 0, 0, 1, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7,
+            7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 10, 0, 0, 0, 0, 0, 0, 0,
+            254, 1, 100, 0, 0, 0, 0, 0, 0, 0, 40, 0, 0, 0, 0, 0, 0, 0, 2, 0, 0, 0, 0, 0, 0, 0,
+            5, 0, 0, 0, 0, 0, 0, 0, 0,
+        ];
+        // 出价簿是空的
+        data.resize(Auction::LEN, 0);
+        let auction = Auction::unpack_from_slice(&data).unwrap();
 
         let expected_auction = Auction {
             seller: Pubkey::from_str("BVUGLStgsasiAQHjT8tc2SJMUVgxpFopwchV9PeoBCkY").unwrap(),
@@ -112,6 +826,18 @@ mod test {
             refund_address: Pubkey::from_str("DuqatgVfG5qKZVtxaXCP4U5BRyPXM7w53DJjFmS1ZWAr")
                 .unwrap(),
             price: 50,
+            status: AuctionStatus::Active,
+            start_ts: 100_000_000,
+            end_ts: 157_772_160,
+            extension_secs: 300,
+            min_increment: 500,
+            increment_kind: IncrementKind::BasisPoints,
+            reserve_hash: Hash::new_from_array([7; 32]),
+            start_price: 10,
+            authority_bump: 254,
+            kind: AuctionKind::Dutch { start_price: 100, floor_price: 40, decay_per_second: 2 },
+            quantity: 5,
+            ..Auction::default()
         };
 
         assert_eq!(auction, expected_auction);
@@ -129,12 +855,24 @@ mod test {
             refund_address: Pubkey::from_str("DuqatgVfG5qKZVtxaXCP4U5BRyPXM7w53DJjFmS1ZWAr")
                 .unwrap(),
             price: 50,
+            status: AuctionStatus::Active,
+            start_ts: 100_000_000,
+            end_ts: 157_772_160,
+            extension_secs: 300,
+            min_increment: 500,
+            increment_kind: IncrementKind::BasisPoints,
+            reserve_hash: Hash::new_from_array([7; 32]),
+            start_price: 10,
+            authority_bump: 254,
+            kind: AuctionKind::Dutch { start_price: 100, floor_price: 40, decay_per_second: 2 },
+            quantity: 5,
+            ..Auction::default()
         };
 
         let mut data_dst = vec![0x00; Auction::LEN];
         auction.pack_into_slice(&mut data_dst);
 
-        let expected_data = [
+        let mut expected_data = vec![
             155, 223, 245, 186, 87, 123, 202, 87, 3, 244, 165, 72, 147, 80, 16, 41, 197, 14, 57,
             96, 239, 172, 90, 101, 168, 211, 132, 86, 70, 172, 14, 121, 37, 198, 132, 32, 63, 71,
             81, 180, 24, 168, 70, 238, 174, 211, 132, 126, 37, 33, 74, 50, 227, 200, 7, 48, 254,
@@ -147,9 +885,306 @@ mod test {
             211, 60, 235, 243, 101, 71, 225, 0, 169, 229, 25, 3, 152, 105, 127, 221, 19, 72, 200,
             91, 152, 253, 191, 213, 97, 49, 159, 191, 3, 181, 236, 255, 53, 161, 134, 235, 33, 246,
             120, 249, 122, 239, 68, 147, 37, 246, 205, 227, 113, 119, 200, 114, 234, 239, 50, 0, 0,
-            0, 0, 0, 0, 0,
+            0, 0, 0, 0, 0, 1, 0, 225, 245, 5, 0, 0, 0, 0, 128, 105, 103, 9, 0, 0, 0, 0, 44, 1, 0,
+            0, 244, 1, 0, 0, 0, 0, 0, 0, 1, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7,
+            7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 10, 0, 0, 0, 0, 0, 0, 0,
+            254, 1, 100, 0, 0, 0, 0, 0, 0, 0, 40, 0, 0, 0, 0, 0, 0, 0, 2, 0, 0, 0, 0, 0, 0, 0,
+            5, 0, 0, 0, 0, 0, 0, 0, 0,
         ];
+        expected_data.resize(Auction::LEN, 0);
 
         assert_eq!(data_dst, expected_data);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn unpack_invalid_status() {
+        let mut data = vec![0x00; Auction::LEN];
+        Auction { status: AuctionStatus::Cancelled, ..Auction::default() }.pack_into_slice(&mut data);
+        assert_eq!(data[232], 4);
+        data[232] = 5;
+        assert_eq!(Auction::unpack_from_slice(&data), Err(ProgramError::InvalidAccountData));
+    }
+
+    #[test]
+    fn unpack_invalid_increment_kind() {
+        let mut data = vec![0x00; Auction::LEN];
+        Auction { increment_kind: IncrementKind::BasisPoints, ..Auction::default() }.pack_into_slice(&mut data);
+        assert_eq!(data[261], 1);
+        data[261] = 2;
+        assert_eq!(Auction::unpack_from_slice(&data), Err(ProgramError::InvalidAccountData));
+    }
+
+    #[test]
+    fn min_next_bid() {
+        // 没有设置加价幅度时至少要比当前价格高1
+        let auction = Auction { price: 100, ..Auction::default() };
+        assert_eq!(auction.min_next_bid(), Some(101));
+        let auction = Auction { price: 100, min_increment: 10, ..auction };
+        assert_eq!(auction.min_next_bid(), Some(110));
+        // 5% 向上取整
+        let auction = Auction { price: 101, min_increment: 500, increment_kind: IncrementKind::BasisPoints, ..auction };
+        assert_eq!(auction.min_next_bid(), Some(107));
+        let auction = Auction { price: 10, min_increment: 1, ..auction };
+        assert_eq!(auction.min_next_bid(), Some(11));
+        let auction = Auction { price: u64::MAX, ..auction };
+        assert_eq!(auction.min_next_bid(), None);
+    }
+
+    #[test]
+    fn auction_authority_per_auction() {
+        // 同一个卖家的不同拍卖使用不同的托管地址
+        let program_id = Pubkey::new_unique();
+        let seller = Pubkey::new_unique();
+        let (first, _) = find_auction_authority(&program_id, &seller, &Pubkey::new_unique());
+        let (second, _) = find_auction_authority(&program_id, &seller, &Pubkey::new_unique());
+        assert_ne!(first, second);
+
+        // 使用保存的bump可以还原出同一个地址
+        let auction_key = Pubkey::new_unique();
+        let (authority, bump) = find_auction_authority(&program_id, &seller, &auction_key);
+        let auction = Auction { seller, authority_bump: bump, ..Auction::default() };
+        let bump = [auction.authority_bump];
+        let seeds = auction.authority_seeds(&auction_key, &bump);
+        assert_eq!(Pubkey::create_program_address(&seeds, &program_id), Ok(authority));
+    }
+
+    #[test]
+    fn unpack_invalid_kind() {
+        let mut data = vec![0x00; Auction::LEN];
+        Auction::default().pack_into_slice(&mut data);
+        assert_eq!(data[303], 0);
+        data[303] = 4;
+        assert_eq!(Auction::unpack_from_slice(&data), Err(ProgramError::InvalidAccountData));
+    }
+
+    #[test]
+    fn batch_order_book_round_trip() {
+        let mut order_book = BatchOrderBook { auction: Pubkey::new_unique(), order_count: 2, ..BatchOrderBook::default() };
+        order_book.bids[0] = BatchOrder { bidder: Pubkey::new_unique(), refund_address: Pubkey::new_unique(), price: 7, quantity: 3, wallet: Pubkey::new_unique(), ..BatchOrder::default() };
+        order_book.bids[1] = BatchOrder { bidder: Pubkey::new_unique(), refund_address: Pubkey::new_unique(), price: 9, quantity: 4, wallet: Pubkey::new_unique(), filled: 2, refund: 18, claimed: true };
+        let mut data = vec![0x00; BatchOrderBook::LEN];
+        order_book.pack_into_slice(&mut data);
+        assert_eq!(BatchOrderBook::unpack_from_slice(&data), Ok(order_book));
+        assert_eq!(order_book.orders().len(), 2);
+        assert_eq!(order_book.escrowed_amount(), 57);
+        assert!(!BatchOrderBook::default().is_initialized());
+
+        // 出价数量不能超过出价簿的容量
+        assert_eq!(data[32], 2);
+        data[32] = MAX_BATCH_BIDS as u8 + 1;
+        assert_eq!(BatchOrderBook::unpack_from_slice(&data), Err(ProgramError::InvalidAccountData));
+
+        // 批量拍卖有没有人出过价看出价次数，出价簿不占用拍卖账户的空间
+        let auction = Auction { kind: AuctionKind::Batch, bid_count: 1, ..Auction::default() };
+        assert!(auction.has_bids());
+        assert_eq!(auction.escrowed_amount(), 0);
+        assert_eq!(Auction::LEN, 444);
+    }
+
+    #[test]
+    fn bid_record_round_trip() {
+        let record = BidRecord { auction: Pubkey::new_unique(), bidder: Pubkey::new_unique(), price: 120, timestamp: 1_700_000_000, sequence: 3 };
+        let mut data = vec![0x00; BidRecord::LEN];
+        record.pack_into_slice(&mut data);
+        assert_eq!(BidRecord::unpack_from_slice(&data), Ok(record));
+        assert_eq!(data[80..], 3u64.to_le_bytes());
+
+        // 同一个拍卖的每次出价使用不同的地址
+        let program_id = Pubkey::new_unique();
+        let (first, _) = find_bid_record_address(&program_id, &record.auction, 0);
+        let (second, _) = find_bid_record_address(&program_id, &record.auction, 1);
+        assert_ne!(first, second);
+
+        // 出价次数保存在分成信息的后面
+        let auction = Auction { bid_count: 7, ..Auction::default() };
+        let mut data = vec![0x00; Auction::LEN];
+        auction.pack_into_slice(&mut data);
+        assert_eq!(data[404..412], 7u64.to_le_bytes());
+        assert_eq!(Auction::unpack_from_slice(&data), Ok(auction));
+    }
+
+    #[test]
+    fn refund_claim_round_trip() {
+        let claim = RefundClaim { auction: Pubkey::new_unique(), bidder: Pubkey::new_unique(), amount: 250 };
+        let mut data = vec![0x00; RefundClaim::LEN];
+        claim.pack_into_slice(&mut data);
+        assert_eq!(RefundClaim::unpack_from_slice(&data), Ok(claim));
+        assert!(!RefundClaim::default().is_initialized());
+
+        // 每个出价者在每个拍卖里只有一个退款记录
+        let program_id = Pubkey::new_unique();
+        let (claim_address, _) = find_refund_claim_address(&program_id, &claim.auction, &claim.bidder);
+        assert_eq!(find_refund_claim_address(&program_id, &claim.auction, &claim.bidder).0, claim_address);
+        assert_ne!(find_refund_claim_address(&program_id, &claim.auction, &Pubkey::new_unique()).0, claim_address);
+
+        // 当前最高出价者的钱包保存在拍卖数据的最后
+        let auction = Auction { top_bidder: claim.bidder, ..Auction::default() };
+        let mut data = vec![0x00; Auction::LEN];
+        auction.pack_into_slice(&mut data);
+        assert_eq!(data[412..], claim.bidder.to_bytes());
+        assert_eq!(Auction::unpack_from_slice(&data), Ok(auction));
+    }
+
+    #[test]
+    fn batch_fills() {
+        let order = |price, quantity| BatchOrder { price, quantity, ..BatchOrder::default() };
+        let mut order_book = BatchOrderBook::default();
+        // 没有出价时不成交
+        assert_eq!(order_book.fills(10), ([0; MAX_BATCH_BIDS], 0));
+        assert_eq!(order_book.lowest_order(), None);
+
+        // 出价不够：全部成交，成交价是最低的出价
+        order_book.bids[..2].copy_from_slice(&[order(5, 3), order(8, 4)]);
+        order_book.order_count = 2;
+        assert_eq!(order_book.fills(10), ([3, 4, 0, 0, 0, 0, 0, 0], 5));
+
+        // 超额认购：从高到低分配，最后一个成交的出价部分成交，单价相同时先出价的优先
+        order_book.bids[2..5].copy_from_slice(&[order(6, 4), order(9, 2), order(6, 5)]);
+        order_book.order_count = 5;
+        assert_eq!(order_book.fills(10), ([0, 4, 4, 2, 0, 0, 0, 0], 6));
+        // 会被挤出去的是最低价格里最后出价的
+        order_book.bids[5] = order(5, 1);
+        order_book.order_count = 6;
+        assert_eq!(order_book.lowest_order(), Some(5));
+    }
+
+    #[test]
+    fn fee_split_round_trip() {
+        let fees = FeeSplit { creator: Pubkey::new_unique(), royalty_bps: 500, platform: Pubkey::new_unique(), platform_fee_bps: 250 };
+        let auction = Auction { fees, ..Auction::default() };
+        let mut data = vec![0x00; Auction::LEN];
+        auction.pack_into_slice(&mut data);
+        assert_eq!(Auction::unpack_from_slice(&data), Ok(auction));
+        assert_eq!(data[368..370], [244, 1]);
+    }
+
+    #[test]
+    fn fee_split_rounding() {
+        let fees = FeeSplit { creator: Pubkey::new_unique(), royalty_bps: 250, platform: Pubkey::new_unique(), platform_fee_bps: 100 };
+        // 版税和手续费向下取整，零头给卖家
+        assert_eq!(fees.split(10_000), (9_650, 250, 100));
+        assert_eq!(fees.split(99), (97, 2, 0));
+        assert_eq!(fees.split(0), (0, 0, 0));
+        // 三部分加起来总是等于成交的钱
+        for amount in [1, 7, 39, 41, 399, 12_345, u64::MAX] {
+            let (seller, royalty, platform_fee) = fees.split(amount);
+            assert_eq!(seller as u128 + royalty as u128 + platform_fee as u128, amount as u128);
+        }
+        // 没有分成时全部给卖家，分成100%时卖家只拿到取整的零头
+        assert_eq!(FeeSplit::default().split(u64::MAX), (u64::MAX, 0, 0));
+        let all = FeeSplit { royalty_bps: 6_000, platform_fee_bps: 4_000, ..fees };
+        assert_eq!(all.split(101), (1, 60, 40));
+        assert_eq!(all.split(100), (0, 60, 40));
+    }
+
+    #[test]
+    fn fee_split_validation() {
+        let fees = FeeSplit { creator: Pubkey::new_unique(), royalty_bps: 9_000, platform: Pubkey::new_unique(), platform_fee_bps: 1_000 };
+        assert!(fees.is_valid());
+        assert!(FeeSplit::default().is_valid());
+        assert!(!FeeSplit { platform_fee_bps: 1_001, ..fees }.is_valid());
+        assert!(!FeeSplit { creator: Pubkey::default(), ..fees }.is_valid());
+        assert!(!FeeSplit { platform: Pubkey::default(), ..fees }.is_valid());
+        assert!(FeeSplit { platform: Pubkey::default(), platform_fee_bps: 0, ..fees }.is_valid());
+    }
+
+    #[test]
+    fn min_bid_over() {
+        let auction = Auction { min_increment: 1_000, increment_kind: IncrementKind::BasisPoints, ..Auction::default() };
+        assert_eq!(auction.min_bid_over(50), Some(55));
+        assert_eq!(auction.min_bid_over(0), Some(1));
+        assert_eq!(auction.min_bid_over(u64::MAX), None);
+    }
+
+    #[test]
+    fn dutch_price() {
+        let auction = Auction {
+            start_ts: 1_000,
+            kind: AuctionKind::Dutch { start_price: 100, floor_price: 40, decay_per_second: 2 },
+            ..Auction::default()
+        };
+        // 开始之前和刚开始都是起始价格
+        assert_eq!(auction.dutch_price(900), Some(100));
+        assert_eq!(auction.dutch_price(1_000), Some(100));
+        assert_eq!(auction.dutch_price(1_010), Some(80));
+        // 最低降到底价
+        assert_eq!(auction.dutch_price(1_030), Some(40));
+        assert_eq!(auction.dutch_price(i64::MAX), Some(40));
+        assert_eq!(auction.escrowed_amount(), 0);
+        assert_eq!(Auction { price: 10, ..Auction::default() }.dutch_price(1_000), None);
+        assert_eq!(Auction { price: 10, ..Auction::default() }.escrowed_amount(), 10);
+    }
+
+    #[test]
+    fn sealed_kind_and_bid_round_trip() {
+        let auction = Auction {
+            end_ts: 100,
+            kind: AuctionKind::Sealed { reveal_end_ts: 200 },
+            ..Auction::default()
+        };
+        let mut data = vec![0x00; Auction::LEN];
+        auction.pack_into_slice(&mut data);
+        assert_eq!(Auction::unpack_from_slice(&data), Ok(auction));
+        assert_eq!(auction.close_ts(), 200);
+        assert_eq!(Auction { kind: AuctionKind::English, ..auction }.close_ts(), 100);
+
+        let bid = SealedBid {
+            auction: Pubkey::new_unique(),
+            bidder: Pubkey::new_unique(),
+            item_receiver: Pubkey::new_unique(),
+            refund_address: Pubkey::new_unique(),
+            commitment: bid_commitment(50, &[3; 32]),
+            deposit: 80,
+            price: 50,
+            revealed: true,
+        };
+        let mut data = vec![0x00; SealedBid::LEN];
+        bid.pack_into_slice(&mut data);
+        assert_eq!(SealedBid::unpack_from_slice(&data), Ok(bid));
+        data[176] = 2;
+        assert_eq!(SealedBid::unpack_from_slice(&data), Err(ProgramError::InvalidAccountData));
+    }
+
+    #[test]
+    fn sealed_escrowed_amount() {
+        // 密封拍卖只有在有人公开了高于起拍价的出价以后才需要付款给卖家
+        let auction = Auction { price: 10, start_price: 10, kind: AuctionKind::Sealed { reveal_end_ts: 0 }, ..Auction::default() };
+        assert_eq!(auction.escrowed_amount(), 0);
+        assert_eq!(Auction { price: 30, ..auction }.escrowed_amount(), 30);
+    }
+
+    #[test]
+    fn has_bids() {
+        let auction = Auction { price: 10, start_price: 10, ..Auction::default() };
+        assert!(!auction.has_bids());
+        assert!(Auction { price: 11, ..auction }.has_bids());
+    }
+
+    #[test]
+    fn reserve_commitment_depends_on_salt() {
+        let salt = [1u8; 32];
+        assert_eq!(reserve_commitment(100, &salt), reserve_commitment(100, &salt));
+        assert_ne!(reserve_commitment(100, &salt), reserve_commitment(101, &salt));
+        assert_ne!(reserve_commitment(100, &salt), reserve_commitment(100, &[2u8; 32]));
+        assert!(!Auction::default().has_reserve());
+        assert!(Auction { reserve_hash: reserve_commitment(100, &salt), ..Auction::default() }.has_reserve());
+    }
+
+    #[test]
+    fn auction_status_transition() {
+        use AuctionStatus::*;
+        assert_eq!(Created.transition(Active), Ok(Active));
+        assert_eq!(Active.transition(Ended), Ok(Ended));
+        assert_eq!(Ended.transition(Settled), Ok(Settled));
+        assert_eq!(Active.transition(Cancelled), Ok(Cancelled));
+        // 已经结算或者取消的拍卖不能再改变状态
+        for next in [Created, Active, Ended, Settled, Cancelled] {
+            assert_eq!(Settled.transition(next), Err(AuctionError::InvalidStatusTransition));
+            assert_eq!(Cancelled.transition(next), Err(AuctionError::InvalidStatusTransition));
+        }
+        // 不能跳过结束直接结算，也不能回到之前的状态
+        assert_eq!(Active.transition(Settled), Err(AuctionError::InvalidStatusTransition));
+        assert_eq!(Ended.transition(Active), Err(AuctionError::InvalidStatusTransition));
+        assert_eq!(Ended.transition(Cancelled), Err(AuctionError::InvalidStatusTransition));
+    }
+}
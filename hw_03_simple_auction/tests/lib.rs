@@ -0,0 +1,1816 @@
+use hw_03_simple_auction::{
+    error::AuctionError,
+    instruction::AuctionInstruction,
+    processor::Processor,
+    state::{
+        bid_commitment, find_auction_authority, find_bid_record_address, find_order_book_address, find_refund_claim_address,
+        find_vault_address, reserve_commitment, Auction, AuctionKind, AuctionStatus, BatchOrder, BatchOrderBook, FeeSplit,
+        IncrementKind, RefundClaim, SealedBid, BidRecord, MAX_BATCH_BIDS,
+    },
+};
+use solana_program::{program_option::COption, program_pack::Pack};
+use solana_program_test::*;
+use solana_sdk::{
+    account::{Account, AccountSharedData},
+    clock::{Clock, UnixTimestamp},
+    hash::Hash,
+    instruction::{AccountMeta, Instruction, InstructionError},
+    pubkey::Pubkey,
+    rent::Rent,
+    signature::{Keypair, Signer},
+    system_program,
+    transaction::{Transaction, TransactionError},
+};
+use spl_token::{
+    native_mint,
+    state::{Account as TokenAccount, AccountState, Mint},
+};
+
+mod native;
+
+// 原生模式运行拍卖合约（支持在CPI中创建账户以及CPI以后继续访问账户，见 native 模块）
+fn process_native(_first_instruction_account: usize, input: &[u8], invoke_context: &mut InvokeContext) -> Result<(), InstructionError> {
+    native::process_instruction(Processor::process, input, invoke_context)
+}
+
+// 原生模式运行Token合约（测试环境自带的Token合约是BPF程序）
+fn process_spl_token(_first_instruction_account: usize, input: &[u8], invoke_context: &mut InvokeContext) -> Result<(), InstructionError> {
+    native::process_instruction(spl_token::processor::Processor::process, input, invoke_context)
+}
+
+// 出价代币的精度
+const CURRENCY_DECIMALS: u8 = 6;
+// 每个用户初始拥有的出价代币数量
+const INITIAL_BALANCE: u64 = 1_000;
+
+/**
+ * 预置账户（账户数据是打包后的 data）
+ * @param address 账户地址
+ * @param owner   账户所属的合约
+ */
+fn add_packable_account<T: Pack>(program_test: &mut ProgramTest, address: Pubkey, data: T, owner: &Pubkey) {
+    let mut buf = vec![0u8; T::LEN];
+    T::pack(data, &mut buf).unwrap();
+    program_test.add_account(address, Account {
+        lamports: Rent::default().minimum_balance(T::LEN),
+        data: buf,
+        owner: *owner,
+        ..Account::default()
+    });
+}
+
+// 预置代币账户
+fn add_token_account(program_test: &mut ProgramTest, mint: &Pubkey, owner: &Pubkey, amount: u64) -> Pubkey {
+    let address = Pubkey::new_unique();
+    add_packable_account(program_test, address, TokenAccount {
+        mint: *mint,
+        owner: *owner,
+        amount,
+        state: AccountState::Initialized,
+        ..TokenAccount::default()
+    }, &spl_token::id());
+    address
+}
+
+// 预置代币
+fn add_mint(program_test: &mut ProgramTest, decimals: u8) -> Pubkey {
+    let address = Pubkey::new_unique();
+    add_packable_account(program_test, address, Mint {
+        mint_authority: COption::Some(Pubkey::new_unique()),
+        supply: u64::MAX,
+        decimals,
+        is_initialized: true,
+        freeze_authority: COption::None,
+    }, &spl_token::id());
+    address
+}
+
+// 出价者以及出价者的代币账户
+struct Bidder {
+    keypair: Keypair,
+    item_account: Pubkey,
+    currency_account: Pubkey,
+    refund_account: Pubkey,
+    // 密封出价账户（属于合约，数据由 CommitBid 初始化）
+    bid_account: Pubkey,
+}
+
+// 拍卖测试环境（拍品、出价代币、托管账户以及出价者的代币账户都已经预置好）
+struct AuctionTest {
+    context: ProgramTestContext,
+    program_id: Pubkey,
+    auction: Pubkey,
+    seller: Keypair,
+    pda: Pubkey,
+    item: Pubkey,
+    item_holder: Pubkey,
+    currency: Pubkey,
+    money_holder: Pubkey,
+    seller_item_account: Pubkey,
+    seller_currency_account: Pubkey,
+    // 接收版税和平台手续费的代币账户
+    creator_account: Pubkey,
+    platform_account: Pubkey,
+    bidders: Vec<Bidder>,
+}
+
+impl AuctionTest {
+    /**
+     * 模拟部署合约并预置拍卖需要的账户
+     * @param bidder_count 出价者数量
+     */
+    async fn start(bidder_count: usize) -> Self {
+        let program_id = Pubkey::new_unique();
+        // 模拟部署合约（加上 test-bpf 特性时加载编译好的BPF程序）
+        let mut program_test = ProgramTest::default();
+        if cfg!(feature = "test-bpf") {
+            program_test.add_program("hw_03_simple_auction", program_id, None);
+        } else {
+            program_test.add_builtin_program("hw_03_simple_auction", program_id, process_native);
+            program_test.add_builtin_program("spl_token", spl_token::id(), process_spl_token);
+        }
+
+        let seller = Keypair::new();
+        // 卖家支付原生SOL金库和批量拍卖出价簿的租金
+        program_test.add_account(seller.pubkey(), Account { lamports: 1_000_000_000, ..Account::default() });
+        let auction = Pubkey::new_unique();
+        let (pda, _) = find_auction_authority(&program_id, &seller.pubkey(), &auction);
+        let item = add_mint(&mut program_test, 0);
+        let currency = add_mint(&mut program_test, CURRENCY_DECIMALS);
+        let item_holder = add_token_account(&mut program_test, &item, &pda, 0);
+        let money_holder = add_token_account(&mut program_test, &currency, &pda, 0);
+        let seller_item_account = add_token_account(&mut program_test, &item, &seller.pubkey(), 1);
+        let seller_currency_account = add_token_account(&mut program_test, &currency, &seller.pubkey(), INITIAL_BALANCE);
+        let creator_account = add_token_account(&mut program_test, &currency, &Pubkey::new_unique(), 0);
+        let platform_account = add_token_account(&mut program_test, &currency, &Pubkey::new_unique(), 0);
+        let bidders = (0..bidder_count)
+            .map(|_| {
+                let keypair = Keypair::new();
+                // 出价者支付出价记录账户的租金
+                program_test.add_account(keypair.pubkey(), Account { lamports: 1_000_000_000, ..Account::default() });
+                let item_account = add_token_account(&mut program_test, &item, &keypair.pubkey(), 0);
+                let currency_account = add_token_account(&mut program_test, &currency, &keypair.pubkey(), INITIAL_BALANCE);
+                let refund_account = add_token_account(&mut program_test, &currency, &keypair.pubkey(), 0);
+                let bid_account = Pubkey::new_unique();
+                program_test.add_account(bid_account, Account {
+                    lamports: Rent::default().minimum_balance(SealedBid::LEN),
+                    data: vec![0u8; SealedBid::LEN],
+                    owner: program_id,
+                    ..Account::default()
+                });
+                Bidder { keypair, item_account, currency_account, refund_account, bid_account }
+            })
+            .collect();
+
+        // 拍卖账户（数据由合约初始化）
+        program_test.add_account(auction, Account {
+            lamports: Rent::default().minimum_balance(Auction::LEN),
+            data: vec![0u8; Auction::LEN],
+            owner: program_id,
+            ..Account::default()
+        });
+
+        let context = program_test.start_with_context().await;
+        AuctionTest {
+            context,
+            program_id,
+            auction,
+            seller,
+            pda,
+            item,
+            item_holder,
+            currency,
+            money_holder,
+            seller_item_account,
+            seller_currency_account,
+            creator_account,
+            platform_account,
+            bidders,
+        }
+    }
+
+    // 当前链上时间
+    async fn now(&mut self) -> UnixTimestamp {
+        self.context.banks_client.get_sysvar::<Clock>().await.unwrap().unix_timestamp
+    }
+
+    // 修改链上时间（先推进到下一个slot，保证后面的交易使用新的blockhash）
+    async fn warp_to_timestamp(&mut self, unix_timestamp: UnixTimestamp) {
+        let clock = self.context.banks_client.get_sysvar::<Clock>().await.unwrap();
+        self.context.warp_to_slot(clock.slot + 1).unwrap();
+        let clock = self.context.banks_client.get_sysvar::<Clock>().await.unwrap();
+        self.context.set_sysvar(&Clock { unix_timestamp, ..clock });
+    }
+
+    /**
+     * 发送交易（手续费由测试环境的 payer 支付）
+     * @param signers 除了 payer 以外的签名者
+     */
+    async fn send(&mut self, signers: &[&Keypair], instruction: Instruction) -> Result<(), TransactionError> {
+        let mut all_signers = vec![&self.context.payer];
+        all_signers.extend_from_slice(signers);
+        let transaction = Transaction::new_signed_with_payer(
+            &[instruction],
+            Some(&self.context.payer.pubkey()),
+            &all_signers,
+            self.context.last_blockhash,
+        );
+        self.context.banks_client.process_transaction(transaction).await.map_err(|e| e.unwrap())
+    }
+
+    // 分成的接收账户（在交易里加上这些账户，合约才能转账给它们）
+    fn fee_accounts(fees: &FeeSplit) -> Vec<AccountMeta> {
+        [fees.creator, fees.platform]
+            .iter()
+            .filter(|receiver| **receiver != Pubkey::default())
+            .map(|receiver| AccountMeta::new(*receiver, false))
+            .collect()
+    }
+
+    async fn get_auction(&mut self) -> Auction {
+        let account = self.context.banks_client.get_account(self.auction).await.unwrap().unwrap();
+        Auction::unpack_unchecked(&account.data).unwrap()
+    }
+
+    // 直接写入拍卖账户的数据（不需要通过CPI托管拍品就可以测试出价和关闭拍卖的校验）
+    fn set_auction(&mut self, auction: Auction) {
+        let program_id = self.program_id;
+        self.set_auction_owned_by(auction, program_id);
+    }
+
+    /**
+     * 直接写入拍卖账户的数据
+     * @param owner 拍卖账户所属的合约（用来模拟别的合约伪造的拍卖账户）
+     */
+    fn set_auction_owned_by(&mut self, auction: Auction, owner: Pubkey) {
+        let mut data = vec![0u8; Auction::LEN];
+        Auction::pack(auction, &mut data).unwrap();
+        self.context.set_account(&self.auction, &AccountSharedData::from(Account {
+            lamports: Rent::default().minimum_balance(Auction::LEN),
+            data,
+            owner,
+            ..Account::default()
+        }));
+    }
+
+    // 批量拍卖的出价簿地址
+    fn order_book(&self) -> Pubkey {
+        find_order_book_address(&self.program_id, &self.auction).0
+    }
+
+    // 直接写入批量拍卖的出价簿
+    fn set_order_book(&mut self, bids: &[BatchOrder]) {
+        let mut order_book = BatchOrderBook { auction: self.auction, order_count: bids.len() as u8, ..BatchOrderBook::default() };
+        order_book.bids[..bids.len()].copy_from_slice(bids);
+        let mut data = vec![0u8; BatchOrderBook::LEN];
+        BatchOrderBook::pack(order_book, &mut data).unwrap();
+        let address = self.order_book();
+        self.context.set_account(&address, &AccountSharedData::from(Account {
+            lamports: Rent::default().minimum_balance(BatchOrderBook::LEN),
+            data,
+            owner: self.program_id,
+            ..Account::default()
+        }));
+    }
+
+    async fn get_order_book(&mut self) -> BatchOrderBook {
+        let account = self.context.banks_client.get_account(self.order_book()).await.unwrap().unwrap();
+        BatchOrderBook::unpack(&account.data).unwrap()
+    }
+
+    // 一个正在出价中的拍卖（卖家自己是当前的最高出价者）
+    fn active_auction(&self, price: u64, start_ts: i64, end_ts: i64) -> Auction {
+        Auction {
+            seller: self.seller.pubkey(),
+            item: self.item,
+            item_holder: self.item_holder,
+            currency: self.currency,
+            money_holder: self.money_holder,
+            bidder: self.seller_item_account,
+            refund_address: self.seller_currency_account,
+            price,
+            status: AuctionStatus::Active,
+            start_ts,
+            end_ts,
+            start_price: price,
+            quantity: 1,
+            authority_bump: find_auction_authority(&self.program_id, &self.seller.pubkey(), &self.auction).1,
+            ..Auction::default()
+        }
+    }
+
+    // 直接写入出价者的密封出价账户数据
+    fn set_sealed_bid(&mut self, bidder: usize, bid: SealedBid) {
+        let mut data = vec![0u8; SealedBid::LEN];
+        SealedBid::pack(bid, &mut data).unwrap();
+        let bid_account = self.bidders[bidder].bid_account;
+        self.context.set_account(&bid_account, &AccountSharedData::from(Account {
+            lamports: Rent::default().minimum_balance(SealedBid::LEN),
+            data,
+            owner: self.program_id,
+            ..Account::default()
+        }));
+    }
+
+    // 出价者已经提交但还没有公开的密封出价
+    fn sealed_bid(&self, bidder: usize, price: u64, salt: &[u8; 32], deposit: u64) -> SealedBid {
+        let bidder = &self.bidders[bidder];
+        SealedBid {
+            auction: self.auction,
+            bidder: bidder.keypair.pubkey(),
+            item_receiver: bidder.item_account,
+            refund_address: bidder.refund_account,
+            commitment: bid_commitment(price, salt),
+            deposit,
+            price: 0,
+            revealed: false,
+        }
+    }
+
+    // 直接修改代币账户的余额
+    fn set_token_balance(&mut self, address: Pubkey, mint: Pubkey, owner: Pubkey, amount: u64) {
+        let mut data = vec![0u8; TokenAccount::LEN];
+        TokenAccount::pack(TokenAccount { mint, owner, amount, state: AccountState::Initialized, ..TokenAccount::default() }, &mut data).unwrap();
+        self.context.set_account(&address, &AccountSharedData::from(Account {
+            lamports: Rent::default().minimum_balance(TokenAccount::LEN),
+            data,
+            owner: spl_token::id(),
+            ..Account::default()
+        }));
+    }
+
+    // 改用原生SOL出价（出价托管在金库PDA里，卖家和出价者都直接使用钱包地址收付款）
+    fn use_native_currency(&mut self) {
+        self.currency = native_mint::id();
+        self.money_holder = find_vault_address(&self.program_id, &self.auction).0;
+        self.seller_currency_account = self.seller.pubkey();
+        for bidder in self.bidders.iter_mut() {
+            bidder.currency_account = bidder.keypair.pubkey();
+            bidder.refund_account = bidder.keypair.pubkey();
+        }
+    }
+
+    // 原生SOL的拍卖里金库领取完时关闭，租金退还给卖家的钱包（其他代币不需要这个账户）
+    fn vault_close_accounts(&self) -> Vec<AccountMeta> {
+        if self.currency == native_mint::id() {
+            vec![AccountMeta::new(self.money_holder, false), AccountMeta::new(self.seller.pubkey(), false)]
+        } else {
+            vec![]
+        }
+    }
+
+    // 往地址里预先转入 lamports（运行时不允许转账创建低于免租金余额的账户，所以直接写入账户）
+    fn prefund(&mut self, address: Pubkey, lamports: u64) {
+        self.context.set_account(&address, &AccountSharedData::from(Account { lamports, ..Account::default() }));
+    }
+
+    async fn lamports(&mut self, address: Pubkey) -> u64 {
+        self.context.banks_client.get_balance(address).await.unwrap()
+    }
+
+    async fn token_balance(&mut self, address: Pubkey) -> u64 {
+        let account = self.context.banks_client.get_account(address).await.unwrap().unwrap();
+        TokenAccount::unpack(&account.data).unwrap().amount
+    }
+
+    // 创建拍卖（没有最低加价幅度和保留价）
+    async fn create_auction(&mut self, start_price: u64, start_ts: i64, end_ts: i64, extension_secs: u32) -> Result<(), TransactionError> {
+        self.create(AuctionInstruction::CreateAuction {
+            start_price,
+            start_ts,
+            end_ts,
+            extension_secs,
+            min_increment: 0,
+            increment_kind: IncrementKind::Absolute,
+            reserve_hash: Hash::default(),
+            kind: AuctionKind::English,
+            quantity: 1,
+            fees: FeeSplit::default(),
+        })
+        .await
+    }
+
+    // 创建拍卖（卖家托管拍品和起拍价，data 是 CreateAuction 指令）
+    async fn create(&mut self, data: AuctionInstruction) -> Result<(), TransactionError> {
+        let mut instruction = Instruction {
+            program_id: self.program_id,
+            accounts: vec![
+                AccountMeta::new(self.auction, false),
+                AccountMeta::new(self.seller.pubkey(), true),
+                AccountMeta::new_readonly(self.item, false),
+                AccountMeta::new(self.item_holder, false),
+                AccountMeta::new_readonly(self.currency, false),
+                AccountMeta::new(self.money_holder, false),
+                AccountMeta::new(self.seller_item_account, false),
+                AccountMeta::new(self.seller_currency_account, false),
+                AccountMeta::new_readonly(spl_token::id(), false),
+            ],
+            data: data.pack(),
+        };
+        if let AuctionInstruction::CreateAuction { ref fees, kind, .. } = data {
+            instruction.accounts.extend(Self::fee_accounts(fees));
+            // 批量拍卖的出价簿由卖家付租金创建
+            if kind == AuctionKind::Batch {
+                instruction.accounts.push(AccountMeta::new(self.order_book(), false));
+            }
+        }
+        // 原生SOL的金库账户由卖家付租金创建
+        instruction.accounts.push(AccountMeta::new_readonly(system_program::id(), false));
+        let seller = Keypair::from_bytes(&self.seller.to_bytes()).unwrap();
+        self.send(&[&seller], instruction).await
+    }
+
+    // 第 sequence 次出价的出价记录地址
+    fn bid_record(&self, sequence: u64) -> Pubkey {
+        find_bid_record_address(&self.program_id, &self.auction, sequence).0
+    }
+
+    // 出价（付款和接收退款使用不同的代币账户）
+    async fn bid(&mut self, bidder: usize, price: u64) -> Result<(), TransactionError> {
+        let currency = self.currency;
+        let sequence = self.get_auction().await.bid_count;
+        let bid_record = self.bid_record(sequence);
+        self.bid_with_accounts(bidder, price, currency, bid_record).await
+    }
+
+    /**
+     * 出价
+     * @param currency   传给合约的出价代币账户
+     * @param bid_record 传给合约的出价记录账户
+     */
+    async fn bid_with_accounts(&mut self, bidder: usize, price: u64, currency: Pubkey, bid_record: Pubkey) -> Result<(), TransactionError> {
+        let auction = self.get_auction().await;
+        let bidder = &self.bidders[bidder];
+        let instruction = Instruction {
+            program_id: self.program_id,
+            accounts: vec![
+                AccountMeta::new(self.auction, false),
+                AccountMeta::new(bidder.keypair.pubkey(), true),
+                AccountMeta::new_readonly(bidder.item_account, false),
+                AccountMeta::new(bidder.refund_account, false),
+                AccountMeta::new(bidder.currency_account, false),
+                AccountMeta::new(self.money_holder, false),
+                AccountMeta::new_readonly(currency, false),
+                AccountMeta::new(bid_record, false),
+                AccountMeta::new(self.refund_claim(&auction.top_bidder), false),
+                AccountMeta::new(self.refund_claim(&bidder.keypair.pubkey()), false),
+                AccountMeta::new_readonly(spl_token::id(), false),
+                AccountMeta::new_readonly(system_program::id(), false),
+            ],
+            data: AuctionInstruction::Bidding { price }.pack(),
+        };
+        let keypair = Keypair::from_bytes(&bidder.keypair.to_bytes()).unwrap();
+        self.send(&[&keypair], instruction).await
+    }
+
+    // 钱包在这个拍卖里的退款记录地址
+    fn refund_claim(&self, wallet: &Pubkey) -> Pubkey {
+        find_refund_claim_address(&self.program_id, &self.auction, wallet).0
+    }
+
+    // 直接写入退款记录
+    fn set_refund_claim(&mut self, wallet: Pubkey, amount: u64) {
+        let mut data = vec![0u8; RefundClaim::LEN];
+        RefundClaim::pack(RefundClaim { auction: self.auction, bidder: wallet, amount }, &mut data).unwrap();
+        let address = self.refund_claim(&wallet);
+        self.context.set_account(&address, &AccountSharedData::from(Account {
+            lamports: Rent::default().minimum_balance(RefundClaim::LEN),
+            data,
+            owner: self.program_id,
+            ..Account::default()
+        }));
+    }
+
+    async fn refund_claim_amount(&mut self, wallet: &Pubkey) -> u64 {
+        let address = self.refund_claim(wallet);
+        let account = self.context.banks_client.get_account(address).await.unwrap().unwrap();
+        RefundClaim::unpack(&account.data).unwrap().amount
+    }
+
+    /**
+     * 领取出价被超过时记下的退款
+     * @param wallet      出价时签名的钱包（卖家领取被第一个出价退还的起拍价）
+     * @param destination 接收退款的出价代币账户
+     */
+    async fn claim_refund(&mut self, wallet: &Keypair, destination: Pubkey) -> Result<(), TransactionError> {
+        let mut instruction = Instruction {
+            program_id: self.program_id,
+            accounts: vec![
+                AccountMeta::new_readonly(self.auction, false),
+                AccountMeta::new_readonly(wallet.pubkey(), true),
+                AccountMeta::new(self.refund_claim(&wallet.pubkey()), false),
+                AccountMeta::new(destination, false),
+                AccountMeta::new(self.money_holder, false),
+                AccountMeta::new_readonly(self.currency, false),
+                AccountMeta::new_readonly(self.pda, false),
+                AccountMeta::new_readonly(spl_token::id(), false),
+            ],
+            data: AuctionInstruction::ClaimRefund.pack(),
+        };
+        instruction.accounts.extend(self.vault_close_accounts());
+        self.send(&[wallet], instruction).await
+    }
+
+    // 关闭拍卖（不需要卖家签名）
+    async fn close_auction(&mut self) -> Result<(), TransactionError> {
+        self.close_auction_with_reveal(None).await
+    }
+
+    // 关闭拍卖并公开保留价
+    async fn close_auction_with_reveal(&mut self, reserve_reveal: Option<(u64, [u8; 32])>) -> Result<(), TransactionError> {
+        let auction = self.get_auction().await;
+        let mut instruction = Instruction {
+            program_id: self.program_id,
+            accounts: vec![
+                AccountMeta::new(self.auction, false),
+                AccountMeta::new_readonly(self.seller.pubkey(), false),
+                AccountMeta::new(self.seller_currency_account, false),
+                AccountMeta::new(self.seller_item_account, false),
+                AccountMeta::new_readonly(self.currency, false),
+                AccountMeta::new_readonly(self.item, false),
+                AccountMeta::new(self.money_holder, false),
+                AccountMeta::new(self.item_holder, false),
+                AccountMeta::new(auction.bidder, false),
+                AccountMeta::new(auction.refund_address, false),
+                // 没有达到保留价时最高出价记到出价者的退款记录里
+                AccountMeta::new(self.refund_claim(&auction.top_bidder), false),
+                AccountMeta::new_readonly(self.pda, false),
+                AccountMeta::new_readonly(spl_token::id(), false),
+            ],
+            data: AuctionInstruction::CloseAuction { reserve_reveal }.pack(),
+        };
+        instruction.accounts.extend(Self::fee_accounts(&auction.fees));
+        if auction.kind == AuctionKind::Batch {
+            instruction.accounts.push(AccountMeta::new(self.order_book(), false));
+        }
+        self.send(&[], instruction).await
+    }
+
+    // 批量拍卖关闭以后发送第 index 个出价的成交拍品和退款（不需要出价者签名）
+    async fn claim_batch_order(&mut self, index: u8) -> Result<(), TransactionError> {
+        let order = self.get_order_book().await.bids[index as usize];
+        let mut instruction = Instruction {
+            program_id: self.program_id,
+            accounts: vec![
+                AccountMeta::new(self.auction, false),
+                AccountMeta::new(order.bidder, false),
+                AccountMeta::new(order.refund_address, false),
+                AccountMeta::new_readonly(self.currency, false),
+                AccountMeta::new_readonly(self.item, false),
+                AccountMeta::new(self.money_holder, false),
+                AccountMeta::new(self.item_holder, false),
+                AccountMeta::new_readonly(self.pda, false),
+                AccountMeta::new_readonly(spl_token::id(), false),
+                AccountMeta::new(self.order_book(), false),
+            ],
+            data: AuctionInstruction::ClaimBatchOrder { index }.pack(),
+        };
+        instruction.accounts.extend(self.vault_close_accounts());
+        self.send(&[], instruction).await
+    }
+
+    /**
+     * 荷兰式拍卖按照当前价格购买
+     * @param max_price 可以接受的最高价格
+     */
+    async fn buy(&mut self, buyer: usize, max_price: u64) -> Result<(), TransactionError> {
+        let auction = self.get_auction().await;
+        let buyer = &self.bidders[buyer];
+        let mut instruction = Instruction {
+            program_id: self.program_id,
+            accounts: vec![
+                AccountMeta::new(self.auction, false),
+                AccountMeta::new_readonly(buyer.keypair.pubkey(), true),
+                AccountMeta::new(buyer.item_account, false),
+                AccountMeta::new(buyer.currency_account, false),
+                AccountMeta::new(self.seller_currency_account, false),
+                AccountMeta::new_readonly(self.currency, false),
+                AccountMeta::new_readonly(self.item, false),
+                AccountMeta::new(self.item_holder, false),
+                AccountMeta::new_readonly(self.pda, false),
+                AccountMeta::new_readonly(spl_token::id(), false),
+            ],
+            data: AuctionInstruction::Buy { max_price }.pack(),
+        };
+        instruction.accounts.extend(Self::fee_accounts(&auction.fees));
+        instruction.accounts.extend(self.vault_close_accounts());
+        let keypair = Keypair::from_bytes(&buyer.keypair.to_bytes()).unwrap();
+        self.send(&[&keypair], instruction).await
+    }
+
+    /**
+     * 批量拍卖出价（出价簿满了的时候要传入会被挤出去的出价者的退款记录）
+     * @param price    单价
+     * @param quantity 数量
+     */
+    async fn batch_bid(&mut self, bidder: usize, price: u64, quantity: u64) -> Result<(), TransactionError> {
+        // 不是批量拍卖的时候没有出价簿
+        let order_book = match self.context.banks_client.get_account(self.order_book()).await.unwrap() {
+            Some(account) => BatchOrderBook::unpack(&account.data).unwrap(),
+            None => BatchOrderBook::default(),
+        };
+        let bidder = &self.bidders[bidder];
+        let mut accounts = vec![
+            AccountMeta::new(self.auction, false),
+            AccountMeta::new(bidder.keypair.pubkey(), true),
+            AccountMeta::new_readonly(bidder.item_account, false),
+            AccountMeta::new_readonly(bidder.refund_account, false),
+            AccountMeta::new(bidder.currency_account, false),
+            AccountMeta::new(self.money_holder, false),
+            AccountMeta::new_readonly(self.currency, false),
+            AccountMeta::new(self.refund_claim(&bidder.keypair.pubkey()), false),
+            AccountMeta::new_readonly(self.pda, false),
+            AccountMeta::new_readonly(spl_token::id(), false),
+            AccountMeta::new_readonly(system_program::id(), false),
+            AccountMeta::new(self.order_book(), false),
+        ];
+        if order_book.order_count as usize == MAX_BATCH_BIDS {
+            let lowest = order_book.lowest_order().unwrap();
+            accounts.push(AccountMeta::new(self.refund_claim(&order_book.bids[lowest].wallet), false));
+        }
+        let instruction = Instruction {
+            program_id: self.program_id,
+            accounts,
+            data: AuctionInstruction::BatchBid { price, quantity }.pack(),
+        };
+        let keypair = Keypair::from_bytes(&bidder.keypair.to_bytes()).unwrap();
+        self.send(&[&keypair], instruction).await
+    }
+
+    /**
+     * 提交密封出价（保证金从出价者的代币账户转出，退款到出价者的退款账户）
+     * @param price   隐藏的出价
+     * @param salt    生成承诺用的随机数
+     * @param deposit 保证金
+     */
+    async fn commit_bid(&mut self, bidder: usize, price: u64, salt: &[u8; 32], deposit: u64) -> Result<(), TransactionError> {
+        let bidder = &self.bidders[bidder];
+        let instruction = Instruction {
+            program_id: self.program_id,
+            accounts: vec![
+                AccountMeta::new_readonly(self.auction, false),
+                AccountMeta::new_readonly(bidder.keypair.pubkey(), true),
+                AccountMeta::new(bidder.bid_account, false),
+                AccountMeta::new_readonly(bidder.item_account, false),
+                AccountMeta::new(bidder.currency_account, false),
+                AccountMeta::new(self.money_holder, false),
+                AccountMeta::new_readonly(self.currency, false),
+                AccountMeta::new_readonly(spl_token::id(), false),
+            ],
+            data: AuctionInstruction::CommitBid { commitment: bid_commitment(price, salt), deposit }.pack(),
+        };
+        let keypair = Keypair::from_bytes(&bidder.keypair.to_bytes()).unwrap();
+        self.send(&[&keypair], instruction).await
+    }
+
+    // 公开密封出价
+    async fn reveal_bid(&mut self, bidder: usize, price: u64, salt: [u8; 32]) -> Result<(), TransactionError> {
+        let bidder = &self.bidders[bidder];
+        let instruction = Instruction {
+            program_id: self.program_id,
+            accounts: vec![
+                AccountMeta::new(self.auction, false),
+                AccountMeta::new_readonly(bidder.keypair.pubkey(), true),
+                AccountMeta::new(bidder.bid_account, false),
+            ],
+            data: AuctionInstruction::RevealBid { price, salt }.pack(),
+        };
+        let keypair = Keypair::from_bytes(&bidder.keypair.to_bytes()).unwrap();
+        self.send(&[&keypair], instruction).await
+    }
+
+    // 拍卖结算以后取回保证金（获胜者取回保证金超过出价的部分，退还到提交出价时付保证金的账户）
+    async fn withdraw_deposit(&mut self, bidder: usize) -> Result<(), TransactionError> {
+        let bid_account = self.bidders[bidder].bid_account;
+        let account = self.context.banks_client.get_account(bid_account).await.unwrap().unwrap();
+        let refund_address = SealedBid::unpack_unchecked(&account.data).unwrap().refund_address;
+        let bidder = &self.bidders[bidder];
+        let mut instruction = Instruction {
+            program_id: self.program_id,
+            accounts: vec![
+                AccountMeta::new_readonly(self.auction, false),
+                AccountMeta::new(bidder.keypair.pubkey(), true),
+                AccountMeta::new(bidder.bid_account, false),
+                AccountMeta::new(refund_address, false),
+                AccountMeta::new_readonly(self.currency, false),
+                AccountMeta::new(self.money_holder, false),
+                AccountMeta::new_readonly(self.pda, false),
+                AccountMeta::new_readonly(spl_token::id(), false),
+            ],
+            data: AuctionInstruction::WithdrawDeposit.pack(),
+        };
+        instruction.accounts.extend(self.vault_close_accounts());
+        let keypair = Keypair::from_bytes(&bidder.keypair.to_bytes()).unwrap();
+        self.send(&[&keypair], instruction).await
+    }
+
+    /**
+     * 卖家取消拍卖
+     * @param signed 卖家是否签名
+     */
+    async fn cancel_auction(&mut self, signed: bool) -> Result<(), TransactionError> {
+        let auction = self.get_auction().await;
+        let mut instruction = Instruction {
+            program_id: self.program_id,
+            accounts: vec![
+                AccountMeta::new(self.auction, false),
+                AccountMeta::new(self.seller.pubkey(), signed),
+                AccountMeta::new(self.seller_item_account, false),
+                AccountMeta::new_readonly(self.currency, false),
+                AccountMeta::new_readonly(self.item, false),
+                AccountMeta::new(self.money_holder, false),
+                AccountMeta::new(self.item_holder, false),
+                AccountMeta::new(auction.refund_address, false),
+                AccountMeta::new_readonly(self.pda, false),
+                AccountMeta::new_readonly(spl_token::id(), false),
+            ],
+            data: AuctionInstruction::CancelAuction.pack(),
+        };
+        // 批量拍卖的出价簿也一起关闭
+        if auction.kind == AuctionKind::Batch {
+            instruction.accounts.push(AccountMeta::new(self.order_book(), false));
+        }
+        let seller = Keypair::from_bytes(&self.seller.to_bytes()).unwrap();
+        if signed {
+            self.send(&[&seller], instruction).await
+        } else {
+            self.send(&[], instruction).await
+        }
+    }
+}
+
+// 合约返回的自定义错误
+fn auction_error(error: AuctionError) -> TransactionError {
+    TransactionError::InstructionError(0, InstructionError::Custom(error as u32))
+}
+
+#[tokio::test]
+async fn test_bidding_window() {
+    let mut test = AuctionTest::start(1).await;
+    let now = test.now().await;
+    test.create_auction(10, now + 100, now + 200, 0).await.unwrap();
+    assert_eq!(test.get_auction().await.status, AuctionStatus::Active);
+
+    // 还没有开始出价
+    assert_eq!(test.bid(0, 20).await.unwrap_err(), auction_error(AuctionError::AuctionNotStarted));
+
+    test.warp_to_timestamp(now + 100).await;
+    test.bid(0, 20).await.unwrap();
+    // 起拍价记到卖家的退款记录里，由卖家自己领取
+    assert_eq!(test.token_balance(test.money_holder).await, 30);
+    let seller = Keypair::from_bytes(&test.seller.to_bytes()).unwrap();
+    assert_eq!(test.refund_claim_amount(&seller.pubkey()).await, 10);
+    let seller_currency_account = test.seller_currency_account;
+    test.claim_refund(&seller, seller_currency_account).await.unwrap();
+    assert_eq!(test.token_balance(test.money_holder).await, 20);
+    assert_eq!(test.token_balance(test.seller_currency_account).await, INITIAL_BALANCE);
+
+    // 已经结束出价
+    test.warp_to_timestamp(now + 200).await;
+    assert_eq!(test.bid(0, 30).await.unwrap_err(), auction_error(AuctionError::AuctionEnded));
+}
+
+#[tokio::test]
+async fn test_create_auction_invalid_window() {
+    let mut test = AuctionTest::start(0).await;
+    let now = test.now().await;
+    assert_eq!(
+        test.create_auction(10, now + 100, now + 100, 0).await.unwrap_err(),
+        auction_error(AuctionError::InvalidTimeWindow)
+    );
+    assert_eq!(test.get_auction().await.status, AuctionStatus::Created);
+}
+
+#[tokio::test]
+async fn test_late_bid_extends_auction() {
+    let mut test = AuctionTest::start(2).await;
+    let now = test.now().await;
+    test.create_auction(10, now, now + 100, 60).await.unwrap();
+
+    // 离结束还有很长时间，不延长
+    test.warp_to_timestamp(now + 10).await;
+    test.bid(0, 20).await.unwrap();
+    assert_eq!(test.get_auction().await.end_ts, now + 100);
+
+    // 结束前60秒内出价，结束时间延长到出价后60秒
+    test.warp_to_timestamp(now + 90).await;
+    test.bid(1, 30).await.unwrap();
+    assert_eq!(test.get_auction().await.end_ts, now + 150);
+
+    // 原来的结束时间已经过了，仍然可以出价
+    test.warp_to_timestamp(now + 120).await;
+    test.bid(0, 40).await.unwrap();
+    let auction = test.get_auction().await;
+    assert_eq!(auction.end_ts, now + 180);
+    assert_eq!(auction.price, 40);
+    assert_eq!(auction.bidder, test.bidders[0].item_account);
+}
+
+#[tokio::test]
+async fn test_anyone_can_close_after_end() {
+    let mut test = AuctionTest::start(1).await;
+    let now = test.now().await;
+    test.create_auction(10, now, now + 100, 0).await.unwrap();
+    test.warp_to_timestamp(now + 10).await;
+    test.bid(0, 20).await.unwrap();
+
+    // 还没有结束不能关闭
+    assert_eq!(test.close_auction().await.unwrap_err(), auction_error(AuctionError::AuctionNotEnded));
+
+    // 结束以后由测试环境的 payer（不是卖家）关闭拍卖
+    test.warp_to_timestamp(now + 100).await;
+    test.close_auction().await.unwrap();
+    assert_eq!(test.get_auction().await.status, AuctionStatus::Settled);
+    // 起拍价还在卖家的退款记录里，没有领取
+    assert_eq!(test.token_balance(test.seller_currency_account).await, INITIAL_BALANCE - 10 + 20);
+    let winner_item_account = test.bidders[0].item_account;
+    assert_eq!(test.token_balance(winner_item_account).await, 1);
+
+    // 已经结算的拍卖不能再关闭
+    test.warp_to_timestamp(now + 110).await;
+    assert_eq!(test.close_auction().await.unwrap_err(), auction_error(AuctionError::InvalidStatusTransition));
+}
+
+#[tokio::test]
+async fn test_bid_below_min_increment() {
+    let mut test = AuctionTest::start(1).await;
+    let now = test.now().await;
+    // 每次至少加价5%
+    test.set_auction(Auction {
+        min_increment: 500,
+        increment_kind: IncrementKind::BasisPoints,
+        ..test.active_auction(100, now, now + 100)
+    });
+    assert_eq!(test.bid(0, 100).await.unwrap_err(), auction_error(AuctionError::BidTooLow));
+    assert_eq!(test.bid(0, 104).await.unwrap_err(), auction_error(AuctionError::BidTooLow));
+}
+
+#[tokio::test]
+async fn test_close_requires_reserve_reveal() {
+    let mut test = AuctionTest::start(0).await;
+    let now = test.now().await;
+    let salt = [9u8; 32];
+    test.set_auction(Auction {
+        reserve_hash: reserve_commitment(50, &salt),
+        ..test.active_auction(10, now - 100, now)
+    });
+
+    // 保留价公开期限之内必须公开保留价
+    assert_eq!(test.close_auction().await.unwrap_err(), auction_error(AuctionError::ReserveNotRevealed));
+    // 公开的保留价和承诺值不一致
+    assert_eq!(
+        test.close_auction_with_reveal(Some((40, salt))).await.unwrap_err(),
+        auction_error(AuctionError::InvalidReserveReveal)
+    );
+    assert_eq!(test.get_auction().await.status, AuctionStatus::Active);
+}
+
+#[tokio::test]
+async fn test_reserve_not_met_returns_item() {
+    let mut test = AuctionTest::start(1).await;
+    let now = test.now().await;
+    let salt = [9u8; 32];
+    test.create(AuctionInstruction::CreateAuction {
+        start_price: 10,
+        start_ts: now,
+        end_ts: now + 100,
+        extension_secs: 0,
+        min_increment: 5,
+        increment_kind: IncrementKind::Absolute,
+        reserve_hash: reserve_commitment(50, &salt),
+        kind: AuctionKind::English,
+        quantity: 1,
+        fees: FeeSplit::default(),
+    })
+    .await
+    .unwrap();
+    test.warp_to_timestamp(now + 10).await;
+    assert_eq!(test.bid(0, 14).await.unwrap_err(), auction_error(AuctionError::BidTooLow));
+    test.bid(0, 20).await.unwrap();
+
+    // 最高出价没有达到保留价：钱记到出价者的退款记录里，拍品退还给卖家
+    test.warp_to_timestamp(now + 100).await;
+    test.close_auction_with_reveal(Some((50, salt))).await.unwrap();
+    assert_eq!(test.get_auction().await.status, AuctionStatus::Settled);
+    let (bidder0, refund_account) = (test.bidders[0].keypair.pubkey(), test.bidders[0].refund_account);
+    assert_eq!(test.token_balance(refund_account).await, 0);
+    assert_eq!(test.refund_claim_amount(&bidder0).await, 20);
+    assert_eq!(test.token_balance(test.seller_item_account).await, 1);
+    assert_eq!(test.token_balance(test.seller_currency_account).await, INITIAL_BALANCE - 10);
+    let seller = test.seller.pubkey();
+    assert_eq!(test.refund_claim_amount(&seller).await, 10);
+}
+
+#[tokio::test]
+async fn test_cancel_auction_checks() {
+    let mut test = AuctionTest::start(0).await;
+    let now = test.now().await;
+    test.set_auction(test.active_auction(10, now, now + 100));
+    assert_eq!(
+        test.cancel_auction(false).await.unwrap_err(),
+        TransactionError::InstructionError(0, InstructionError::MissingRequiredSignature)
+    );
+
+    // 已经有人出价以后不能取消
+    test.set_auction(Auction { price: 20, ..test.active_auction(10, now, now + 100) });
+    assert_eq!(test.cancel_auction(true).await.unwrap_err(), auction_error(AuctionError::AuctionHasBids));
+
+    // 已经结算的拍卖不能取消（推进到下一个slot，否则和上面是完全相同的交易）
+    test.warp_to_timestamp(now).await;
+    test.set_auction(Auction { status: AuctionStatus::Settled, ..test.active_auction(10, now, now + 100) });
+    assert_eq!(test.cancel_auction(true).await.unwrap_err(), auction_error(AuctionError::InvalidStatusTransition));
+}
+
+#[tokio::test]
+async fn test_cancel_auction_returns_item_and_rent() {
+    let mut test = AuctionTest::start(0).await;
+    let now = test.now().await;
+    test.create_auction(10, now, now + 100, 0).await.unwrap();
+    assert_eq!(test.token_balance(test.seller_item_account).await, 0);
+    let auction_lamports = test.context.banks_client.get_balance(test.auction).await.unwrap();
+    let seller_lamports = test.context.banks_client.get_balance(test.seller.pubkey()).await.unwrap();
+
+    test.cancel_auction(true).await.unwrap();
+
+    // 拍品和起拍价退还给卖家，拍卖账户被关闭，租金退还给卖家
+    assert_eq!(test.token_balance(test.seller_item_account).await, 1);
+    assert_eq!(test.token_balance(test.seller_currency_account).await, INITIAL_BALANCE);
+    assert_eq!(test.context.banks_client.get_account(test.auction).await.unwrap(), None);
+    assert_eq!(
+        test.context.banks_client.get_balance(test.seller.pubkey()).await.unwrap(),
+        seller_lamports + auction_lamports
+    );
+}
+
+#[tokio::test]
+async fn test_bid_rejects_wrong_mint() {
+    let mut test = AuctionTest::start(1).await;
+    let now = test.now().await;
+    test.set_auction(test.active_auction(10, now, now + 100));
+
+    // 传入的出价代币和拍卖记录的不一致
+    let (item, bid_record) = (test.item, test.bid_record(0));
+    assert_eq!(
+        test.bid_with_accounts(0, 20, item, bid_record).await.unwrap_err(),
+        TransactionError::InstructionError(0, InstructionError::InvalidAccountData)
+    );
+
+}
+
+#[tokio::test]
+async fn test_create_auction_rejects_forged_mint() {
+    let mut test = AuctionTest::start(0).await;
+    let now = test.now().await;
+
+    // 拍品代币账户不属于Token合约（精度不可信）
+    let mut data = vec![0u8; Mint::LEN];
+    Mint { decimals: 0, is_initialized: true, ..Mint::default() }.pack_into_slice(&mut data);
+    let item = test.item;
+    test.context.set_account(&item, &AccountSharedData::from(Account {
+        lamports: Rent::default().minimum_balance(Mint::LEN),
+        data,
+        owner: test.program_id,
+        ..Account::default()
+    }));
+    assert_eq!(
+        test.create_auction(10, now, now + 100, 0).await.unwrap_err(),
+        TransactionError::InstructionError(0, InstructionError::IncorrectProgramId)
+    );
+}
+
+#[tokio::test]
+async fn test_dutch_auction_checks() {
+    let mut test = AuctionTest::start(1).await;
+    let now = test.now().await;
+
+    // 底价不能高于起始价格
+    let create = |kind| AuctionInstruction::CreateAuction {
+        start_price: 100,
+        start_ts: now,
+        end_ts: now + 100,
+        extension_secs: 0,
+        min_increment: 0,
+        increment_kind: IncrementKind::Absolute,
+        reserve_hash: Hash::default(),
+        kind,
+        quantity: 1,
+        fees: FeeSplit::default(),
+    };
+    assert_eq!(
+        test.create(create(AuctionKind::Dutch { start_price: 100, floor_price: 101, decay_per_second: 1 })).await.unwrap_err(),
+        auction_error(AuctionError::InvalidPriceSchedule)
+    );
+    assert_eq!(
+        test.create(create(AuctionKind::Dutch { start_price: 90, floor_price: 40, decay_per_second: 1 })).await.unwrap_err(),
+        auction_error(AuctionError::InvalidPriceSchedule)
+    );
+
+    // 英式拍卖不能直接购买
+    test.set_auction(test.active_auction(10, now, now + 100));
+    assert_eq!(test.buy(0, 100).await.unwrap_err(), auction_error(AuctionError::WrongAuctionKind));
+
+    // 荷兰式拍卖不能出价，当前价格高于买家可以接受的价格时不能购买
+    test.set_auction(Auction {
+        kind: AuctionKind::Dutch { start_price: 100, floor_price: 40, decay_per_second: 1 },
+        ..test.active_auction(100, now, now + 100)
+    });
+    assert_eq!(test.bid(0, 200).await.unwrap_err(), auction_error(AuctionError::WrongAuctionKind));
+    assert_eq!(test.buy(0, 99).await.unwrap_err(), auction_error(AuctionError::PriceAboveLimit));
+}
+
+#[tokio::test]
+async fn test_dutch_auction_buy() {
+    let mut test = AuctionTest::start(1).await;
+    let now = test.now().await;
+    test.create(AuctionInstruction::CreateAuction {
+        start_price: 100,
+        start_ts: now,
+        end_ts: now + 100,
+        extension_secs: 0,
+        min_increment: 0,
+        increment_kind: IncrementKind::Absolute,
+        reserve_hash: Hash::default(),
+        kind: AuctionKind::Dutch { start_price: 100, floor_price: 40, decay_per_second: 2 },
+        quantity: 1,
+        fees: FeeSplit::default(),
+    })
+    .await
+    .unwrap();
+    // 卖家不需要托管起拍价
+    assert_eq!(test.token_balance(test.money_holder).await, 0);
+
+    // 30秒以后价格降到40，直接成交
+    test.warp_to_timestamp(now + 30).await;
+    test.buy(0, 50).await.unwrap();
+    let auction = test.get_auction().await;
+    assert_eq!(auction.status, AuctionStatus::Settled);
+    assert_eq!(auction.price, 40);
+    let buyer = &test.bidders[0];
+    let (item_account, currency_account) = (buyer.item_account, buyer.currency_account);
+    assert_eq!(test.token_balance(item_account).await, 1);
+    assert_eq!(test.token_balance(currency_account).await, INITIAL_BALANCE - 40);
+    assert_eq!(test.token_balance(test.seller_currency_account).await, INITIAL_BALANCE + 40);
+
+    // 已经成交的拍卖不能再购买
+    assert_eq!(test.buy(0, 100).await.unwrap_err(), auction_error(AuctionError::AuctionNotActive));
+}
+
+#[tokio::test]
+async fn test_sealed_auction_checks() {
+    let mut test = AuctionTest::start(2).await;
+    let now = test.now().await;
+    let salt = [7u8; 32];
+
+    // 公开阶段必须在出价结束以后才结束
+    let create = |reveal_end_ts| AuctionInstruction::CreateAuction {
+        start_price: 10,
+        start_ts: now,
+        end_ts: now + 100,
+        extension_secs: 0,
+        min_increment: 0,
+        increment_kind: IncrementKind::Absolute,
+        reserve_hash: Hash::default(),
+        kind: AuctionKind::Sealed { reveal_end_ts },
+        quantity: 1,
+        fees: FeeSplit::default(),
+    };
+    assert_eq!(test.create(create(now + 100)).await.unwrap_err(), auction_error(AuctionError::InvalidTimeWindow));
+
+    // 英式拍卖不接受密封出价
+    test.set_auction(test.active_auction(10, now, now + 100));
+    assert_eq!(test.commit_bid(0, 50, &salt, 60).await.unwrap_err(), auction_error(AuctionError::WrongAuctionKind));
+
+    // 密封拍卖不能公开出价，出价阶段不能公开，开始以后卖家也不能取消
+    test.set_auction(Auction {
+        kind: AuctionKind::Sealed { reveal_end_ts: now + 200 },
+        ..test.active_auction(10, now, now + 100)
+    });
+    test.set_sealed_bid(0, test.sealed_bid(0, 50, &salt, 60));
+    test.set_sealed_bid(1, test.sealed_bid(1, 80, &salt, 70));
+    assert_eq!(test.bid(0, 50).await.unwrap_err(), auction_error(AuctionError::WrongAuctionKind));
+    assert_eq!(test.reveal_bid(0, 50, salt).await.unwrap_err(), auction_error(AuctionError::AuctionNotEnded));
+    assert_eq!(test.cancel_auction(true).await.unwrap_err(), auction_error(AuctionError::AuctionHasBids));
+
+    // 公开阶段：价格或随机数不对不能公开，出价高于保证金无效
+    test.warp_to_timestamp(now + 150).await;
+    assert_eq!(test.reveal_bid(0, 51, salt).await.unwrap_err(), auction_error(AuctionError::InvalidBidReveal));
+    assert_eq!(test.reveal_bid(0, 50, [8u8; 32]).await.unwrap_err(), auction_error(AuctionError::InvalidBidReveal));
+    assert_eq!(test.reveal_bid(1, 80, salt).await.unwrap_err(), auction_error(AuctionError::DepositTooLow));
+
+    // 公开成功的最高出价成为获胜者，同一个出价不能公开两次
+    test.reveal_bid(0, 50, salt).await.unwrap();
+    let auction = test.get_auction().await;
+    assert_eq!((auction.price, auction.bidder, auction.refund_address), (50, test.bidders[0].item_account, test.bidders[0].bid_account));
+    test.warp_to_timestamp(now + 151).await;
+    assert_eq!(test.reveal_bid(0, 50, salt).await.unwrap_err(), auction_error(AuctionError::InvalidBidReveal));
+
+    // 公开阶段结束以前不能关闭拍卖，结算以前不能取回保证金
+    assert_eq!(test.close_auction().await.unwrap_err(), auction_error(AuctionError::AuctionNotEnded));
+    assert_eq!(test.withdraw_deposit(1).await.unwrap_err(), auction_error(AuctionError::AuctionNotEnded));
+    test.warp_to_timestamp(now + 200).await;
+    assert_eq!(test.reveal_bid(1, 70, salt).await.unwrap_err(), auction_error(AuctionError::AuctionEnded));
+}
+
+#[tokio::test]
+async fn test_sealed_auction_settles_highest_reveal() {
+    let mut test = AuctionTest::start(3).await;
+    let now = test.now().await;
+    test.create(AuctionInstruction::CreateAuction {
+        start_price: 10,
+        start_ts: now,
+        end_ts: now + 100,
+        extension_secs: 0,
+        min_increment: 0,
+        increment_kind: IncrementKind::Absolute,
+        reserve_hash: Hash::default(),
+        kind: AuctionKind::Sealed { reveal_end_ts: now + 200 },
+        quantity: 1,
+        fees: FeeSplit::default(),
+    })
+    .await
+    .unwrap();
+    // 卖家不需要托管起拍价
+    assert_eq!(test.token_balance(test.money_holder).await, 0);
+
+    // 出价者2提交了出价但没有公开
+    let salt = [3u8; 32];
+    test.commit_bid(0, 50, &salt, 100).await.unwrap();
+    test.commit_bid(1, 80, &salt, 90).await.unwrap();
+    test.commit_bid(2, 90, &salt, 90).await.unwrap();
+    assert_eq!(test.token_balance(test.money_holder).await, 280);
+
+    test.warp_to_timestamp(now + 150).await;
+    test.reveal_bid(1, 80, salt).await.unwrap();
+    test.reveal_bid(0, 50, salt).await.unwrap();
+    test.warp_to_timestamp(now + 200).await;
+    test.close_auction().await.unwrap();
+    assert_eq!(test.get_auction().await.status, AuctionStatus::Settled);
+    let winner = test.bidders[1].item_account;
+    assert_eq!(test.token_balance(winner).await, 1);
+    assert_eq!(test.token_balance(test.seller_currency_account).await, INITIAL_BALANCE + 80);
+
+    // 获胜者取回多出的保证金，其他人（包括没有公开的）取回全部保证金
+    for (bidder, deposit, refund) in [(0, 100, 100), (1, 90, 10), (2, 90, 90)] {
+        test.withdraw_deposit(bidder).await.unwrap();
+        let currency_account = test.bidders[bidder].currency_account;
+        assert_eq!(test.token_balance(currency_account).await, INITIAL_BALANCE - deposit + refund);
+    }
+    assert_eq!(test.token_balance(test.money_holder).await, 0);
+    let bid_account = test.bidders[0].bid_account;
+    assert!(test.context.banks_client.get_account(bid_account).await.unwrap().is_none());
+}
+
+#[tokio::test]
+async fn test_batch_auction_checks() {
+    let mut test = AuctionTest::start(1).await;
+    let now = test.now().await;
+
+    // 至少要卖一个单位，批量拍卖不能设置保留价
+    let create = |quantity, reserve_hash| AuctionInstruction::CreateAuction {
+        start_price: 10,
+        start_ts: now,
+        end_ts: now + 100,
+        extension_secs: 0,
+        min_increment: 0,
+        increment_kind: IncrementKind::Absolute,
+        reserve_hash,
+        kind: AuctionKind::Batch,
+        quantity,
+        fees: FeeSplit::default(),
+    };
+    assert_eq!(test.create(create(0, Hash::default())).await.unwrap_err(), auction_error(AuctionError::InvalidQuantity));
+    assert_eq!(
+        test.create(create(5, reserve_commitment(20, &[1u8; 32]))).await.unwrap_err(),
+        auction_error(AuctionError::InvalidPriceSchedule)
+    );
+
+    // 英式拍卖不接受批量出价，批量拍卖也不接受普通出价
+    test.set_auction(test.active_auction(10, now, now + 100));
+    assert_eq!(test.batch_bid(0, 20, 1).await.unwrap_err(), auction_error(AuctionError::WrongAuctionKind));
+    let batch = Auction { kind: AuctionKind::Batch, quantity: 5, ..test.active_auction(10, now, now + 100) };
+    test.set_auction(batch);
+    test.set_order_book(&[]);
+    assert_eq!(test.bid(0, 20).await.unwrap_err(), auction_error(AuctionError::WrongAuctionKind));
+
+    // 数量必须在 1 到 quantity 之间，单价不能低于起拍价
+    assert_eq!(test.batch_bid(0, 20, 0).await.unwrap_err(), auction_error(AuctionError::InvalidQuantity));
+    assert_eq!(test.batch_bid(0, 20, 6).await.unwrap_err(), auction_error(AuctionError::InvalidQuantity));
+    assert_eq!(test.batch_bid(0, 9, 1).await.unwrap_err(), auction_error(AuctionError::BidTooLow));
+
+    // 出价簿满了以后必须超过最低的出价，有人出价以后不能取消
+    let order = BatchOrder { bidder: Pubkey::new_unique(), refund_address: Pubkey::new_unique(), price: 20, quantity: 1, wallet: Pubkey::new_unique(), ..BatchOrder::default() };
+    test.set_auction(Auction { bid_count: MAX_BATCH_BIDS as u64, ..batch });
+    test.set_order_book(&[order; MAX_BATCH_BIDS]);
+    assert_eq!(test.batch_bid(0, 20, 1).await.unwrap_err(), auction_error(AuctionError::BidTooLow));
+    assert_eq!(test.cancel_auction(true).await.unwrap_err(), auction_error(AuctionError::AuctionHasBids));
+}
+
+#[tokio::test]
+async fn test_claim_batch_order_checks() {
+    let mut test = AuctionTest::start(1).await;
+    let now = test.now().await;
+    let order = BatchOrder {
+        bidder: test.bidders[0].item_account,
+        refund_address: test.bidders[0].refund_account,
+        price: 20,
+        quantity: 1,
+        wallet: test.bidders[0].keypair.pubkey(),
+        ..BatchOrder::default()
+    };
+    let mut batch = Auction { kind: AuctionKind::Batch, quantity: 5, bid_count: 1, ..test.active_auction(10, now - 100, now) };
+    test.set_order_book(&[order]);
+
+    // 只有关闭以后的批量拍卖可以领取
+    test.set_auction(Auction { status: AuctionStatus::Settled, ..test.active_auction(10, now - 100, now) });
+    assert_eq!(test.claim_batch_order(0).await.unwrap_err(), auction_error(AuctionError::WrongAuctionKind));
+    test.warp_to_timestamp(now + 1).await;
+    test.set_auction(batch);
+    assert_eq!(test.claim_batch_order(0).await.unwrap_err(), auction_error(AuctionError::AuctionNotEnded));
+
+    // 出价不存在、没有成交也没有退款、已经领取过（换一个区块，相同的交易不会重复执行）
+    test.warp_to_timestamp(now + 10).await;
+    batch.status = AuctionStatus::Settled;
+    test.set_auction(batch);
+    assert_eq!(
+        test.claim_batch_order(1).await.unwrap_err(),
+        TransactionError::InstructionError(0, InstructionError::InvalidArgument)
+    );
+    assert_eq!(test.claim_batch_order(0).await.unwrap_err(), auction_error(AuctionError::NothingToClaim));
+    test.set_order_book(&[BatchOrder { refund: 20, claimed: true, ..order }]);
+    test.warp_to_timestamp(now + 20).await;
+    assert_eq!(test.claim_batch_order(0).await.unwrap_err(), auction_error(AuctionError::NothingToClaim));
+}
+
+#[tokio::test]
+async fn test_batch_auction_uniform_clearing_price() {
+    let mut test = AuctionTest::start(3).await;
+    let now = test.now().await;
+    let (seller_item_account, item, seller) = (test.seller_item_account, test.item, test.seller.pubkey());
+    test.set_token_balance(seller_item_account, item, seller, 5);
+    test.create(AuctionInstruction::CreateAuction {
+        start_price: 10,
+        start_ts: now,
+        end_ts: now + 100,
+        extension_secs: 0,
+        min_increment: 0,
+        increment_kind: IncrementKind::Absolute,
+        reserve_hash: Hash::default(),
+        kind: AuctionKind::Batch,
+        quantity: 5,
+        fees: FeeSplit::default(),
+    })
+    .await
+    .unwrap();
+    assert_eq!(test.token_balance(test.item_holder).await, 5);
+    assert_eq!(test.token_balance(test.money_holder).await, 0);
+
+    // 5个单位：出价者1的2个（15）先成交，出价者0的3个（12）成交，出价者2（12，出价更晚）没有成交
+    test.batch_bid(0, 12, 3).await.unwrap();
+    test.batch_bid(1, 15, 2).await.unwrap();
+    test.batch_bid(2, 12, 2).await.unwrap();
+    assert_eq!(test.token_balance(test.money_holder).await, 36 + 30 + 24);
+
+    // 还没有关闭拍卖不能领取
+    assert_eq!(test.claim_batch_order(0).await.unwrap_err(), auction_error(AuctionError::AuctionNotEnded));
+
+    test.warp_to_timestamp(now + 100).await;
+    test.close_auction().await.unwrap();
+    let auction = test.get_auction().await;
+    assert_eq!((auction.status, auction.price), (AuctionStatus::Settled, 12));
+    let order_book = test.get_order_book().await;
+    // 关闭拍卖时只把钱转给卖家，每个出价的成交数量和退款记在出价簿里
+    assert_eq!(test.token_balance(test.seller_currency_account).await, INITIAL_BALANCE + 60);
+    assert_eq!(test.token_balance(test.money_holder).await, 30);
+    assert_eq!(test.token_balance(test.item_holder).await, 5);
+
+    // 所有人都按照成交价格12付款，多托管的钱退还
+    for (index, units, refund) in [(0, 3, 0), (1, 2, 6), (2, 0, 24)] {
+        let order = order_book.bids[index];
+        assert_eq!((order.filled, order.refund, order.claimed), (units, refund, false));
+        test.claim_batch_order(index as u8).await.unwrap();
+        let (item_account, refund_account) = (test.bidders[index].item_account, test.bidders[index].refund_account);
+        assert_eq!(test.token_balance(item_account).await, units);
+        assert_eq!(test.token_balance(refund_account).await, refund);
+    }
+    assert_eq!(test.token_balance(test.money_holder).await, 0);
+    assert_eq!(test.token_balance(test.item_holder).await, 0);
+
+    // 每个出价只能领取一次
+    test.warp_to_timestamp(now + 110).await;
+    assert_eq!(test.claim_batch_order(1).await.unwrap_err(), auction_error(AuctionError::NothingToClaim));
+}
+
+#[tokio::test]
+async fn test_batch_bid_evicts_lowest_order() {
+    let mut test = AuctionTest::start(MAX_BATCH_BIDS + 1).await;
+    let now = test.now().await;
+    test.create(AuctionInstruction::CreateAuction {
+        start_price: 10,
+        start_ts: now,
+        end_ts: now + 100,
+        extension_secs: 0,
+        min_increment: 0,
+        increment_kind: IncrementKind::Absolute,
+        reserve_hash: Hash::default(),
+        kind: AuctionKind::Batch,
+        quantity: 1,
+        fees: FeeSplit::default(),
+    })
+    .await
+    .unwrap();
+    for bidder in 0..MAX_BATCH_BIDS {
+        test.batch_bid(bidder, 10 + bidder as u64, 1).await.unwrap();
+    }
+
+    // 出价簿满了，新的出价挤掉价格最低的出价者0，出价者0的钱记到他的退款记录里
+    test.batch_bid(MAX_BATCH_BIDS, 30, 1).await.unwrap();
+    let order_book = test.get_order_book().await;
+    assert_eq!(order_book.order_count as usize, MAX_BATCH_BIDS);
+    assert_eq!(order_book.bids[0].price, 11);
+    assert_eq!(order_book.bids[MAX_BATCH_BIDS - 1].price, 30);
+    assert_eq!(test.get_auction().await.bid_count, MAX_BATCH_BIDS as u64 + 1);
+    let bidder0 = test.bidders[0].keypair.pubkey();
+    assert_eq!(test.refund_claim_amount(&bidder0).await, 10);
+    let bidder = Keypair::from_bytes(&test.bidders[0].keypair.to_bytes()).unwrap();
+    let refund_account = test.bidders[0].refund_account;
+    test.claim_refund(&bidder, refund_account).await.unwrap();
+    assert_eq!(test.token_balance(refund_account).await, 10);
+}
+
+#[tokio::test]
+async fn test_batch_order_book_closed_on_cancel() {
+    let mut test = AuctionTest::start(0).await;
+    let now = test.now().await;
+    let seller = test.seller.pubkey();
+    let seller_lamports = test.lamports(seller).await;
+    let auction_lamports = test.lamports(test.auction).await;
+
+    // 批量拍卖由卖家付租金创建出价簿，取消拍卖时出价簿的租金退还给卖家
+    test.create(AuctionInstruction::CreateAuction {
+        start_price: 10,
+        start_ts: now,
+        end_ts: now + 100,
+        extension_secs: 0,
+        min_increment: 0,
+        increment_kind: IncrementKind::Absolute,
+        reserve_hash: Hash::default(),
+        kind: AuctionKind::Batch,
+        quantity: 1,
+        fees: FeeSplit::default(),
+    })
+    .await
+    .unwrap();
+    let order_book_rent = Rent::default().minimum_balance(BatchOrderBook::LEN);
+    assert_eq!(test.lamports(test.order_book()).await, order_book_rent);
+    assert_eq!(test.get_order_book().await, BatchOrderBook { auction: test.auction, ..BatchOrderBook::default() });
+    assert_eq!(test.lamports(seller).await, seller_lamports - order_book_rent);
+    test.cancel_auction(true).await.unwrap();
+    assert_eq!(test.context.banks_client.get_account(test.order_book()).await.unwrap(), None);
+    assert_eq!(test.lamports(seller).await, seller_lamports + auction_lamports);
+}
+
+#[tokio::test]
+async fn test_create_auction_rejects_invalid_fees() {
+    let mut test = AuctionTest::start(0).await;
+    let now = test.now().await;
+    let create = |fees| AuctionInstruction::CreateAuction {
+        start_price: 10,
+        start_ts: now,
+        end_ts: now + 100,
+        extension_secs: 0,
+        min_increment: 0,
+        increment_kind: IncrementKind::Absolute,
+        reserve_hash: Hash::default(),
+        kind: AuctionKind::English,
+        quantity: 1,
+        fees,
+    };
+
+    // 版税和手续费合计超过100%
+    let fees = FeeSplit { creator: test.creator_account, royalty_bps: 6_000, platform: test.platform_account, platform_fee_bps: 5_000 };
+    assert_eq!(test.create(create(fees)).await.unwrap_err(), auction_error(AuctionError::InvalidFeeSplit));
+
+    // 接收版税的不是出价代币的账户
+    let fees = FeeSplit { creator: test.seller_item_account, royalty_bps: 500, ..FeeSplit::default() };
+    assert_eq!(test.create(create(fees)).await.unwrap_err(), auction_error(AuctionError::InvalidFeeSplit));
+}
+
+#[tokio::test]
+async fn test_close_auction_splits_fees() {
+    let mut test = AuctionTest::start(1).await;
+    let now = test.now().await;
+    let fees = FeeSplit { creator: test.creator_account, royalty_bps: 500, platform: test.platform_account, platform_fee_bps: 250 };
+    test.create(AuctionInstruction::CreateAuction {
+        start_price: 10,
+        start_ts: now,
+        end_ts: now + 100,
+        extension_secs: 0,
+        min_increment: 0,
+        increment_kind: IncrementKind::Absolute,
+        reserve_hash: Hash::default(),
+        kind: AuctionKind::English,
+        quantity: 1,
+        fees,
+    })
+    .await
+    .unwrap();
+    test.bid(0, 101).await.unwrap();
+    test.warp_to_timestamp(now + 100).await;
+    test.close_auction().await.unwrap();
+
+    // 版税 5.05 和手续费 2.525 向下取整，零头给卖家，一共正好是 101
+    assert_eq!(test.token_balance(test.creator_account).await, 5);
+    assert_eq!(test.token_balance(test.platform_account).await, 2);
+    // 托管账户里只剩下卖家还没有领取的起拍价
+    assert_eq!(test.token_balance(test.seller_currency_account).await, INITIAL_BALANCE - 10 + 94);
+    assert_eq!(test.token_balance(test.money_holder).await, 10);
+}
+
+#[tokio::test]
+async fn test_close_without_bids_takes_no_fees() {
+    let mut test = AuctionTest::start(0).await;
+    let now = test.now().await;
+    let fees = FeeSplit { creator: test.creator_account, royalty_bps: 500, platform: test.platform_account, platform_fee_bps: 250 };
+    test.create(AuctionInstruction::CreateAuction {
+        start_price: 100,
+        start_ts: now,
+        end_ts: now + 100,
+        extension_secs: 0,
+        min_increment: 0,
+        increment_kind: IncrementKind::Absolute,
+        reserve_hash: Hash::default(),
+        kind: AuctionKind::English,
+        quantity: 1,
+        fees,
+    })
+    .await
+    .unwrap();
+    test.warp_to_timestamp(now + 100).await;
+    test.close_auction().await.unwrap();
+
+    // 没有人出价：起拍价全部退还给卖家，创作者和平台不分成
+    assert_eq!(test.token_balance(test.seller_currency_account).await, INITIAL_BALANCE);
+    assert_eq!(test.token_balance(test.creator_account).await, 0);
+    assert_eq!(test.token_balance(test.platform_account).await, 0);
+    assert_eq!(test.token_balance(test.money_holder).await, 0);
+    assert_eq!(test.token_balance(test.seller_item_account).await, 1);
+}
+
+#[tokio::test]
+async fn test_bid_requires_sequential_bid_record() {
+    let mut test = AuctionTest::start(1).await;
+    let now = test.now().await;
+    test.set_auction(Auction { bid_count: 3, ..test.active_auction(10, now, now + 100) });
+
+    // 出价记录的地址必须使用当前的出价序号
+    let (currency, stale_record) = (test.currency, test.bid_record(2));
+    assert_eq!(
+        test.bid_with_accounts(0, 20, currency, stale_record).await.unwrap_err(),
+        TransactionError::InstructionError(0, InstructionError::InvalidSeeds)
+    );
+}
+
+#[tokio::test]
+async fn test_bids_write_history_records() {
+    let mut test = AuctionTest::start(2).await;
+    let now = test.now().await;
+    test.create_auction(10, now, now + 100, 0).await.unwrap();
+    test.warp_to_timestamp(now + 10).await;
+    test.bid(0, 20).await.unwrap();
+    test.warp_to_timestamp(now + 20).await;
+    test.bid(1, 30).await.unwrap();
+    assert_eq!(test.get_auction().await.bid_count, 2);
+
+    // 每次出价都有一个记录，可以还原完整的出价顺序
+    let expected = [(0, 20, now + 10), (1, 30, now + 20)];
+    for (sequence, (bidder, price, timestamp)) in expected.into_iter().enumerate() {
+        let address = test.bid_record(sequence as u64);
+        let account = test.context.banks_client.get_account(address).await.unwrap().unwrap();
+        assert_eq!(account.owner, test.program_id);
+        assert_eq!(
+            BidRecord::unpack(&account.data).unwrap(),
+            BidRecord {
+                auction: test.auction,
+                bidder: test.bidders[bidder].keypair.pubkey(),
+                price,
+                timestamp,
+                sequence: sequence as u64,
+            }
+        );
+    }
+}
+
+#[tokio::test]
+async fn test_prefunded_bid_record_does_not_block_bids() {
+    let mut test = AuctionTest::start(1).await;
+    let now = test.now().await;
+    test.create_auction(10, now, now + 100, 0).await.unwrap();
+
+    // 出价记录的地址是可以预测的，提前往下一个出价记录的地址转入 1 lamport
+    let bid_record = test.bid_record(0);
+    test.prefund(bid_record, 1);
+    test.warp_to_timestamp(now + 10).await;
+    test.bid(0, 20).await.unwrap();
+
+    let account = test.context.banks_client.get_account(bid_record).await.unwrap().unwrap();
+    assert_eq!(account.owner, test.program_id);
+    assert_eq!(account.lamports, Rent::default().minimum_balance(BidRecord::LEN));
+    assert_eq!(BidRecord::unpack(&account.data).unwrap().price, 20);
+    assert_eq!(test.get_auction().await.bid_count, 1);
+}
+
+#[tokio::test]
+async fn test_prefunded_refund_claims_do_not_block_bids() {
+    let mut test = AuctionTest::start(1).await;
+    let now = test.now().await;
+    test.create_auction(10, now, now + 100, 0).await.unwrap();
+
+    // 退款记录的地址也是可以预测的，提前往卖家和出价者的退款记录地址转入 1 lamport
+    let (seller, bidder0) = (test.seller.pubkey(), test.bidders[0].keypair.pubkey());
+    for wallet in [seller, bidder0] {
+        let claim = test.refund_claim(&wallet);
+        test.prefund(claim, 1);
+    }
+    test.warp_to_timestamp(now + 10).await;
+    test.bid(0, 20).await.unwrap();
+
+    for (wallet, amount) in [(seller, 10), (bidder0, 0)] {
+        let account = test.context.banks_client.get_account(test.refund_claim(&wallet)).await.unwrap().unwrap();
+        assert_eq!(account.owner, test.program_id);
+        assert_eq!(account.lamports, Rent::default().minimum_balance(RefundClaim::LEN));
+        assert_eq!(RefundClaim::unpack(&account.data).unwrap(), RefundClaim { auction: test.auction, bidder: wallet, amount });
+    }
+}
+
+#[tokio::test]
+async fn test_native_currency_checks() {
+    let mut test = AuctionTest::start(1).await;
+    let now = test.now().await;
+
+    // 原生SOL的托管账户必须是拍卖的金库地址
+    test.currency = native_mint::id();
+    assert_eq!(
+        test.create_auction(10, now, now + 100, 0).await.unwrap_err(),
+        TransactionError::InstructionError(0, InstructionError::InvalidSeeds)
+    );
+
+    // 原生SOL的退款地址必须是钱包地址，不能是代币账户
+    test.use_native_currency();
+    test.set_auction(test.active_auction(10, now, now + 100));
+    test.bidders[0].refund_account = Pubkey::new_unique();
+    let refund_account = test.bidders[0].refund_account;
+    test.set_token_balance(refund_account, native_mint::id(), test.bidders[0].keypair.pubkey(), 0);
+    let bid_record = test.bid_record(0);
+    assert_eq!(
+        test.bid_with_accounts(0, 20, native_mint::id(), bid_record).await.unwrap_err(),
+        TransactionError::InstructionError(0, InstructionError::InvalidAccountData)
+    );
+}
+
+#[tokio::test]
+async fn test_native_sol_auction() {
+    let mut test = AuctionTest::start(2).await;
+    test.use_native_currency();
+    let rent = test.context.banks_client.get_rent().await.unwrap();
+    let seller = test.seller.pubkey();
+    let (bidder0, bidder1) = (test.bidders[0].keypair.pubkey(), test.bidders[1].keypair.pubkey());
+    let seller_lamports = test.lamports(seller).await;
+    let bidder_lamports = test.lamports(bidder0).await;
+
+    let now = test.now().await;
+    test.create_auction(10, now, now + 100, 0).await.unwrap();
+    let vault = test.context.banks_client.get_account(test.money_holder).await.unwrap().unwrap();
+    assert_eq!(vault.owner, test.program_id);
+    assert_eq!(vault.lamports, rent.minimum_balance(0) + 10);
+
+    // 出价的lamports托管在金库里
+    test.warp_to_timestamp(now + 10).await;
+    test.bid(0, 20).await.unwrap();
+    test.warp_to_timestamp(now + 20).await;
+    test.bid(1, 30).await.unwrap();
+    // 出价者支付出价记录和自己的退款记录的租金，第一个出价者还要支付卖家的退款记录的租金
+    let record_rent = rent.minimum_balance(BidRecord::LEN) + rent.minimum_balance(RefundClaim::LEN);
+    let claim_rent = rent.minimum_balance(RefundClaim::LEN);
+    assert_eq!(test.lamports(bidder0).await, bidder_lamports - record_rent - claim_rent - 20);
+    assert_eq!(test.lamports(bidder1).await, bidder_lamports - record_rent - 30);
+
+    // 被超过的出价直接从金库退回钱包
+    let bidder = Keypair::from_bytes(&test.bidders[0].keypair.to_bytes()).unwrap();
+    test.claim_refund(&bidder, bidder0).await.unwrap();
+    assert_eq!(test.lamports(bidder0).await, bidder_lamports - record_rent - claim_rent);
+
+    // 卖家还没有领取起拍价的退款，关闭拍卖以后金库还在
+    test.warp_to_timestamp(now + 100).await;
+    test.close_auction().await.unwrap();
+    assert_eq!(test.lamports(seller).await, seller_lamports - rent.minimum_balance(0) - 10 + 30);
+    assert_eq!(test.lamports(test.money_holder).await, rent.minimum_balance(0) + 10);
+    assert_eq!(test.token_balance(test.bidders[1].item_account).await, 1);
+
+    // 最后一笔退款领取以后金库关闭，租金退还给卖家
+    let seller_keypair = Keypair::from_bytes(&test.seller.to_bytes()).unwrap();
+    test.claim_refund(&seller_keypair, seller).await.unwrap();
+    assert_eq!(test.context.banks_client.get_account(test.money_holder).await.unwrap(), None);
+    assert_eq!(test.lamports(seller).await, seller_lamports + 30);
+}
+
+#[tokio::test]
+async fn test_native_sol_vault_closed_after_last_batch_order() {
+    let mut test = AuctionTest::start(2).await;
+    test.use_native_currency();
+    let seller = test.seller.pubkey();
+    let (seller_item_account, item) = (test.seller_item_account, test.item);
+    test.set_token_balance(seller_item_account, item, seller, 2);
+    let now = test.now().await;
+    test.create(AuctionInstruction::CreateAuction {
+        start_price: 10,
+        start_ts: now,
+        end_ts: now + 100,
+        extension_secs: 0,
+        min_increment: 0,
+        increment_kind: IncrementKind::Absolute,
+        reserve_hash: Hash::default(),
+        kind: AuctionKind::Batch,
+        quantity: 2,
+        fees: FeeSplit::default(),
+    })
+    .await
+    .unwrap();
+    let seller_lamports = test.lamports(seller).await;
+    let vault_rent = Rent::default().minimum_balance(0);
+    test.batch_bid(0, 15, 1).await.unwrap();
+    test.batch_bid(1, 12, 2).await.unwrap();
+
+    // 成交价格12：出价者0退还3，出价者1成交1个退还12
+    test.warp_to_timestamp(now + 100).await;
+    test.close_auction().await.unwrap();
+    assert_eq!(test.lamports(seller).await, seller_lamports + 24);
+    test.claim_batch_order(0).await.unwrap();
+    assert_eq!(test.lamports(test.money_holder).await, vault_rent + 12);
+
+    // 最后一个出价领取以后金库关闭，租金退还给卖家
+    test.claim_batch_order(1).await.unwrap();
+    assert_eq!(test.context.banks_client.get_account(test.money_holder).await.unwrap(), None);
+    assert_eq!(test.lamports(seller).await, seller_lamports + 24 + vault_rent);
+}
+
+#[tokio::test]
+async fn test_native_sol_vault_prefunded_and_closed_on_cancel() {
+    let mut test = AuctionTest::start(0).await;
+    test.use_native_currency();
+    let seller = test.seller.pubkey();
+    let seller_lamports = test.lamports(seller).await;
+    let auction_lamports = test.lamports(test.auction).await;
+
+    // 金库的地址是可以预测的，提前转入 1 lamport 不能阻止卖家创建拍卖
+    let vault = test.money_holder;
+    test.prefund(vault, 1);
+    let now = test.now().await;
+    test.create_auction(10, now, now + 100, 0).await.unwrap();
+    let account = test.context.banks_client.get_account(vault).await.unwrap().unwrap();
+    assert_eq!(account.owner, test.program_id);
+    assert_eq!(account.lamports, Rent::default().minimum_balance(0) + 10);
+
+    // 取消拍卖时金库账户也被关闭，起拍价和金库的租金都退还给卖家
+    test.cancel_auction(true).await.unwrap();
+    assert_eq!(test.context.banks_client.get_account(vault).await.unwrap(), None);
+    assert_eq!(test.lamports(seller).await, seller_lamports + auction_lamports + 1);
+    assert_eq!(test.token_balance(test.seller_item_account).await, 1);
+}
+
+#[tokio::test]
+async fn test_forged_auction_can_not_drain_vault() {
+    let mut test = AuctionTest::start(1).await;
+    test.use_native_currency();
+    let now = test.now().await;
+    test.create_auction(10, now, now + 100, 0).await.unwrap();
+    let vault = test.money_holder;
+    let vault_lamports = test.lamports(vault).await;
+
+    // 别的合约伪造的拍卖账户：数据里的金库指向上面拍卖的金库，托管的钱退还到伪造者的钱包
+    let mut forged = test.get_auction().await;
+    test.auction = Pubkey::new_unique();
+    forged.status = AuctionStatus::Active;
+    forged.refund_address = test.bidders[0].keypair.pubkey();
+    let bid_record = test.bid_record(0);
+    test.set_auction_owned_by(forged, Pubkey::new_unique());
+    let incorrect_program = TransactionError::InstructionError(0, InstructionError::IncorrectProgramId);
+    assert_eq!(test.cancel_auction(true).await.unwrap_err(), incorrect_program);
+    assert_eq!(test.bid_with_accounts(0, 20, native_mint::id(), bid_record).await.unwrap_err(), incorrect_program);
+    test.warp_to_timestamp(now + 100).await;
+    assert_eq!(test.close_auction().await.unwrap_err(), incorrect_program);
+
+    // 属于本合约的拍卖也只能从自己的金库里转出
+    test.set_auction(forged);
+    assert_eq!(
+        test.cancel_auction(true).await.unwrap_err(),
+        TransactionError::InstructionError(0, InstructionError::InvalidSeeds)
+    );
+    assert_eq!(test.lamports(vault).await, vault_lamports);
+}
+
+#[tokio::test]
+async fn test_sealed_and_refund_handlers_reject_forged_auction() {
+    let mut test = AuctionTest::start(1).await;
+    let now = test.now().await;
+    let salt = [1u8; 32];
+    let foreign_program = Pubkey::new_unique();
+    let incorrect_program = TransactionError::InstructionError(0, InstructionError::IncorrectProgramId);
+    let mut auction = Auction {
+        kind: AuctionKind::Sealed { reveal_end_ts: now + 200 },
+        ..test.active_auction(10, now, now + 100)
+    };
+    test.set_auction_owned_by(auction, foreign_program);
+    assert_eq!(test.commit_bid(0, 50, &salt, 60).await.unwrap_err(), incorrect_program);
+
+    // 获胜价格高于保证金
+    auction.status = AuctionStatus::Settled;
+    auction.price = 100;
+    auction.refund_address = test.bidders[0].bid_account;
+    test.set_auction_owned_by(auction, foreign_program);
+    test.set_sealed_bid(0, SealedBid { revealed: true, price: 50, ..test.sealed_bid(0, 50, &salt, 60) });
+    assert_eq!(test.withdraw_deposit(0).await.unwrap_err(), incorrect_program);
+    // 即使拍卖属于本合约，计算获胜者多出的保证金也不能下溢
+    test.set_auction(auction);
+    test.warp_to_timestamp(now + 1).await;
+    assert_eq!(
+        test.withdraw_deposit(0).await.unwrap_err(),
+        TransactionError::InstructionError(0, InstructionError::InvalidAccountData)
+    );
+
+    let bidder = Keypair::from_bytes(&test.bidders[0].keypair.to_bytes()).unwrap();
+    let refund_account = test.bidders[0].refund_account;
+    test.set_refund_claim(bidder.pubkey(), 20);
+    test.set_auction_owned_by(auction, foreign_program);
+    assert_eq!(test.claim_refund(&bidder, refund_account).await.unwrap_err(), incorrect_program);
+    assert_eq!(test.refund_claim_amount(&bidder.pubkey()).await, 20);
+}
+
+#[tokio::test]
+async fn test_claim_refund_checks() {
+    let mut test = AuctionTest::start(1).await;
+    let now = test.now().await;
+    test.set_auction(test.active_auction(10, now, now + 100));
+    let bidder = Keypair::from_bytes(&test.bidders[0].keypair.to_bytes()).unwrap();
+    let refund_account = test.bidders[0].refund_account;
+
+    // 没有退款记录
+    assert_eq!(
+        test.claim_refund(&bidder, refund_account).await.unwrap_err(),
+        auction_error(AuctionError::NothingToClaim)
+    );
+
+    // 退款已经领取过（推进到下一个slot，否则和上面完全相同的交易会被当成重复交易）
+    test.set_refund_claim(bidder.pubkey(), 0);
+    test.warp_to_timestamp(now + 1).await;
+    assert_eq!(
+        test.claim_refund(&bidder, refund_account).await.unwrap_err(),
+        auction_error(AuctionError::NothingToClaim)
+    );
+
+    // 接收退款的账户必须是出价代币的账户
+    test.set_refund_claim(bidder.pubkey(), 20);
+    let item_account = test.bidders[0].item_account;
+    assert_eq!(
+        test.claim_refund(&bidder, item_account).await.unwrap_err(),
+        TransactionError::InstructionError(0, InstructionError::InvalidAccountData)
+    );
+}
+
+#[tokio::test]
+async fn test_broken_refund_account_does_not_block_bids() {
+    let mut test = AuctionTest::start(2).await;
+    let now = test.now().await;
+    test.create_auction(10, now, now + 100, 0).await.unwrap();
+    test.warp_to_timestamp(now + 10).await;
+    test.bid(0, 20).await.unwrap();
+
+    // 第一个出价者的退款账户被冻结，更高的出价仍然可以成功
+    let (bidder0, refund_account) = (test.bidders[0].keypair.pubkey(), test.bidders[0].refund_account);
+    let mut data = vec![0u8; TokenAccount::LEN];
+    TokenAccount::pack(
+        TokenAccount { mint: test.currency, owner: bidder0, state: AccountState::Frozen, ..TokenAccount::default() },
+        &mut data,
+    )
+    .unwrap();
+    test.context.set_account(&refund_account, &AccountSharedData::from(Account {
+        lamports: Rent::default().minimum_balance(TokenAccount::LEN),
+        data,
+        owner: spl_token::id(),
+        ..Account::default()
+    }));
+    test.warp_to_timestamp(now + 20).await;
+    test.bid(1, 30).await.unwrap();
+    assert_eq!(test.refund_claim_amount(&bidder0).await, 20);
+
+    // 出价者把退款领取到另一个代币账户
+    let bidder = Keypair::from_bytes(&test.bidders[0].keypair.to_bytes()).unwrap();
+    let currency_account = test.bidders[0].currency_account;
+    test.claim_refund(&bidder, currency_account).await.unwrap();
+    assert_eq!(test.token_balance(currency_account).await, INITIAL_BALANCE);
+    assert_eq!(test.refund_claim_amount(&bidder0).await, 0);
+    test.warp_to_timestamp(now + 30).await;
+    assert_eq!(
+        test.claim_refund(&bidder, currency_account).await.unwrap_err(),
+        auction_error(AuctionError::NothingToClaim)
+    );
+}
@@ -0,0 +1,297 @@
+// 原生模式运行合约（cargo test 不需要编译BPF程序）
+//
+// solana-program-test（1.10）的 processor! 不支持在CPI中修改账户数据长度（创建账户、allocate），
+// 也不支持合约自己 realloc，并且在CPI以后会访问已经失效的指令上下文。
+// 这里按照BPF加载器的格式序列化账户（每个账户的数据后面预留 MAX_PERMITTED_DATA_INCREASE 字节），
+// 再用 entrypoint::deserialize 还原成 AccountInfo，CPI 由下面的 NativeStubs 执行并同步账户数据长度。
+
+use solana_program_test::InvokeContext;
+use solana_sdk::{
+    account::{ReadableAccount, WritableAccount},
+    account_info::AccountInfo,
+    entrypoint::{self, ProcessInstruction, ProgramResult, BPF_ALIGN_OF_U128, MAX_PERMITTED_DATA_INCREASE, SUCCESS},
+    instruction::{Instruction, InstructionError},
+    program_error::{ProgramError, UNSUPPORTED_SYSVAR},
+    program_stubs::{self, SyscallStubs},
+    pubkey::Pubkey,
+};
+use std::{
+    cell::Cell,
+    mem::{size_of, size_of_val},
+    ptr,
+    slice,
+    sync::{Arc, Once},
+};
+
+thread_local! {
+    // 当前正在执行的原生合约的调用上下文（没有原生合约在执行时是空指针）
+    static INVOKE_CONTEXT: Cell<*mut InvokeContext<'static>> = const { Cell::new(ptr::null_mut()) };
+}
+
+fn get_invoke_context<'a, 'b>() -> Option<&'a mut InvokeContext<'b>> {
+    let invoke_context = INVOKE_CONTEXT.with(|invoke_context| invoke_context.get());
+    unsafe { invoke_context.cast::<InvokeContext<'b>>().as_mut() }
+}
+
+/**
+ * 执行合约入口函数（用在 ProgramTest::add_builtin_program 注册的函数里面）
+ * @param process_instruction 合约入口函数
+ * @param input               调用合约的参数
+ * @param invoke_context      测试环境的调用上下文
+ */
+pub fn process_instruction(
+    process_instruction: ProcessInstruction,
+    input: &[u8],
+    invoke_context: &mut InvokeContext,
+) -> Result<(), InstructionError> {
+    static STUBS: Once = Once::new();
+    STUBS.call_once(|| {
+        // 测试环境启动时已经设置了 solana-program-test 的桩函数，这里只替换CPI以及需要调用上下文的函数
+        let previous = program_stubs::set_syscall_stubs(Box::new(DefaultStubs));
+        program_stubs::set_syscall_stubs(Box::new(NativeStubs { previous }));
+    });
+
+    let (mut buffer, accounts) = serialize(invoke_context, input)?;
+    let program_id = {
+        let transaction_context = &invoke_context.transaction_context;
+        *transaction_context.get_current_instruction_context()?.get_program_key(transaction_context)?
+    };
+    log(invoke_context, format!("Program {} invoke [{}]", program_id, invoke_context.get_stack_height()));
+
+    let previous = INVOKE_CONTEXT.with(|ptr| ptr.replace((invoke_context as *mut InvokeContext).cast()));
+    let result = {
+        let (program_id, account_infos, data) = unsafe { entrypoint::deserialize(buffer.as_mut_ptr() as *mut u8) };
+        process_instruction(program_id, &account_infos, data)
+    };
+    INVOKE_CONTEXT.with(|ptr| ptr.set(previous));
+
+    if let Err(err) = result {
+        let err = InstructionError::from(u64::from(err));
+        log(invoke_context, format!("Program {} failed: {}", program_id, err));
+        return Err(err);
+    }
+    commit(invoke_context, &buffer, &accounts)?;
+    log(invoke_context, format!("Program {} success", program_id));
+    Ok(())
+}
+
+fn log(invoke_context: &InvokeContext, message: String) {
+    if let Some(log_collector) = invoke_context.get_log_collector() {
+        log_collector.borrow_mut().log(&message);
+    }
+}
+
+// 按BPF加载器的格式序列化指令账户，返回序列化的数据以及每个不重复的账户（指令账户序号，账户地址在数据中的位置）
+#[allow(clippy::type_complexity)]
+fn serialize(invoke_context: &InvokeContext, input: &[u8]) -> Result<(Vec<u64>, Vec<(usize, usize)>), InstructionError> {
+    let transaction_context = &invoke_context.transaction_context;
+    let instruction_context = transaction_context.get_current_instruction_context()?;
+    let indices = instruction_context.get_number_of_program_accounts()..instruction_context.get_number_of_accounts();
+
+    let mut bytes = Vec::new();
+    let mut keys = Vec::new();
+    let mut accounts = Vec::new();
+    bytes.extend_from_slice(&(indices.len() as u64).to_le_bytes());
+    for index in indices {
+        let account = instruction_context.try_borrow_account(transaction_context, index)?;
+        // 同一个账户出现多次时只记录第一次出现的位置
+        if let Some(position) = keys.iter().position(|key| key == account.get_key()) {
+            bytes.push(position as u8);
+            bytes.extend_from_slice(&[0u8; 7]);
+        } else {
+            bytes.extend_from_slice(&[u8::MAX, account.is_signer() as u8, account.is_writable() as u8, account.is_executable() as u8]);
+            // 原始数据长度（由 entrypoint::deserialize 写入）
+            bytes.extend_from_slice(&[0u8; 4]);
+            accounts.push((index, bytes.len()));
+            bytes.extend_from_slice(account.get_key().as_ref());
+            bytes.extend_from_slice(account.get_owner().as_ref());
+            bytes.extend_from_slice(&account.get_lamports().to_le_bytes());
+            bytes.extend_from_slice(&(account.get_data().len() as u64).to_le_bytes());
+            bytes.extend_from_slice(account.get_data());
+            bytes.resize(bytes.len() + MAX_PERMITTED_DATA_INCREASE, 0);
+            bytes.resize(bytes.len().div_ceil(BPF_ALIGN_OF_U128) * BPF_ALIGN_OF_U128, 0);
+            bytes.extend_from_slice(&account.get_rent_epoch().to_le_bytes());
+        }
+        keys.push(*account.get_key());
+    }
+    bytes.extend_from_slice(&(input.len() as u64).to_le_bytes());
+    bytes.extend_from_slice(input);
+    bytes.extend_from_slice(instruction_context.get_program_key(transaction_context)?.as_ref());
+
+    // 使用 u64 数组保证数据按8个字节对齐
+    let mut buffer = vec![0u64; bytes.len().div_ceil(size_of::<u64>())];
+    as_bytes_mut(&mut buffer)[..bytes.len()].copy_from_slice(&bytes);
+    Ok((buffer, accounts))
+}
+
+// 把合约修改以后的账户写回调用上下文（只写回可写的账户）
+fn commit(invoke_context: &InvokeContext, buffer: &[u64], accounts: &[(usize, usize)]) -> Result<(), InstructionError> {
+    let bytes = unsafe { slice::from_raw_parts(buffer.as_ptr() as *const u8, size_of_val(buffer)) };
+    let transaction_context = &invoke_context.transaction_context;
+    let instruction_context = transaction_context.get_current_instruction_context()?;
+    for &(index, offset) in accounts {
+        let mut account = instruction_context.try_borrow_account(transaction_context, index)?;
+        if !account.is_writable() {
+            continue;
+        }
+        let owner = &bytes[offset + 32..offset + 64];
+        let lamports = u64::from_le_bytes(bytes[offset + 64..offset + 72].try_into().unwrap());
+        let data_len = u64::from_le_bytes(bytes[offset + 72..offset + 80].try_into().unwrap()) as usize;
+        if account.get_owner().as_ref() != owner {
+            account.set_owner(owner);
+        }
+        account.set_lamports(lamports);
+        account.set_data(&bytes[offset + 80..offset + 80 + data_len]);
+    }
+    Ok(())
+}
+
+fn as_bytes_mut(buffer: &mut [u64]) -> &mut [u8] {
+    unsafe { slice::from_raw_parts_mut(buffer.as_mut_ptr() as *mut u8, size_of_val(buffer)) }
+}
+
+fn get_sysvar<T: Clone>(sysvar: Result<Arc<T>, InstructionError>, var_addr: *mut u8) -> u64 {
+    match sysvar {
+        Ok(sysvar) => {
+            unsafe { *(var_addr as *mut T) = T::clone(&sysvar) };
+            SUCCESS
+        }
+        Err(_) => UNSUPPORTED_SYSVAR,
+    }
+}
+
+// 替换桩函数期间临时使用的默认桩函数
+struct DefaultStubs;
+
+impl SyscallStubs for DefaultStubs {}
+
+// 原生合约执行期间使用的桩函数（不是原生合约调用时交给 solana-program-test 原来的桩函数）
+struct NativeStubs {
+    previous: Box<dyn SyscallStubs>,
+}
+
+impl SyscallStubs for NativeStubs {
+    fn sol_log(&self, message: &str) {
+        match get_invoke_context() {
+            Some(invoke_context) => log(invoke_context, format!("Program log: {}", message)),
+            None => self.previous.sol_log(message),
+        }
+    }
+
+    fn sol_invoke_signed(&self, instruction: &Instruction, account_infos: &[AccountInfo], signers_seeds: &[&[&[u8]]]) -> ProgramResult {
+        let invoke_context = match get_invoke_context() {
+            Some(invoke_context) => invoke_context,
+            None => return self.previous.sol_invoke_signed(instruction, account_infos, signers_seeds),
+        };
+        let to_program_error = |err: InstructionError| ProgramError::try_from(err).unwrap_or_else(|err| panic!("{}", err));
+        let caller = {
+            let transaction_context = &invoke_context.transaction_context;
+            *transaction_context.get_current_instruction_context().unwrap().get_program_key(transaction_context).unwrap()
+        };
+        let signers = signers_seeds
+            .iter()
+            .map(|seeds| Pubkey::create_program_address(seeds, &caller))
+            .collect::<Result<Vec<_>, _>>()?;
+        let (instruction_accounts, program_indices) =
+            invoke_context.prepare_instruction(instruction, &signers).map_err(to_program_error)?;
+
+        // 调用之前把调用者对账户的修改同步到调用上下文
+        let mut account_indices = Vec::with_capacity(instruction_accounts.len());
+        for instruction_account in instruction_accounts.iter() {
+            let transaction_context = &invoke_context.transaction_context;
+            let key = transaction_context.get_key_of_account_at_index(instruction_account.index_in_transaction).unwrap();
+            let account_info_index = account_infos
+                .iter()
+                .position(|account_info| account_info.unsigned_key() == key)
+                .ok_or(ProgramError::NotEnoughAccountKeys)?;
+            let account_info = &account_infos[account_info_index];
+            let mut account = transaction_context.get_account_at_index(instruction_account.index_in_transaction).unwrap().borrow_mut();
+            account.copy_into_owner_from_slice(account_info.owner.as_ref());
+            account.set_data_from_slice(&account_info.try_borrow_data()?);
+            account.set_lamports(account_info.lamports());
+            account.set_executable(account_info.executable);
+            account.set_rent_epoch(account_info.rent_epoch);
+            if instruction_account.is_writable {
+                account_indices.push((instruction_account.index_in_transaction, account_info_index));
+            }
+        }
+
+        let mut compute_units_consumed = 0;
+        invoke_context
+            .process_instruction(
+                &instruction.data,
+                &instruction_accounts,
+                &program_indices,
+                &mut compute_units_consumed,
+                &mut Default::default(),
+            )
+            .map_err(to_program_error)?;
+
+        // 调用以后把账户的修改同步回调用者（账户数据长度可以改变）
+        for (index_in_transaction, account_info_index) in account_indices {
+            let account = invoke_context.transaction_context.get_account_at_index(index_in_transaction).unwrap().borrow();
+            let account_info = &account_infos[account_info_index];
+            **account_info.try_borrow_mut_lamports()? = account.lamports();
+            if account_info.owner != account.owner() {
+                account_info.assign(account.owner());
+            }
+            account_info.realloc(account.data().len(), false)?;
+            account_info.try_borrow_mut_data()?.copy_from_slice(account.data());
+        }
+        Ok(())
+    }
+
+    fn sol_get_clock_sysvar(&self, var_addr: *mut u8) -> u64 {
+        match get_invoke_context() {
+            Some(invoke_context) => get_sysvar(invoke_context.get_sysvar_cache().get_clock(), var_addr),
+            None => self.previous.sol_get_clock_sysvar(var_addr),
+        }
+    }
+
+    fn sol_get_epoch_schedule_sysvar(&self, var_addr: *mut u8) -> u64 {
+        match get_invoke_context() {
+            Some(invoke_context) => get_sysvar(invoke_context.get_sysvar_cache().get_epoch_schedule(), var_addr),
+            None => self.previous.sol_get_epoch_schedule_sysvar(var_addr),
+        }
+    }
+
+    #[allow(deprecated)]
+    fn sol_get_fees_sysvar(&self, var_addr: *mut u8) -> u64 {
+        match get_invoke_context() {
+            Some(invoke_context) => get_sysvar(invoke_context.get_sysvar_cache().get_fees(), var_addr),
+            None => self.previous.sol_get_fees_sysvar(var_addr),
+        }
+    }
+
+    fn sol_get_rent_sysvar(&self, var_addr: *mut u8) -> u64 {
+        match get_invoke_context() {
+            Some(invoke_context) => get_sysvar(invoke_context.get_sysvar_cache().get_rent(), var_addr),
+            None => self.previous.sol_get_rent_sysvar(var_addr),
+        }
+    }
+
+    fn sol_get_return_data(&self) -> Option<(Pubkey, Vec<u8>)> {
+        match get_invoke_context() {
+            Some(invoke_context) => {
+                let (program_id, data) = invoke_context.transaction_context.get_return_data();
+                Some((*program_id, data.to_vec()))
+            }
+            None => self.previous.sol_get_return_data(),
+        }
+    }
+
+    fn sol_set_return_data(&self, data: &[u8]) {
+        match get_invoke_context() {
+            Some(invoke_context) => {
+                let transaction_context = &mut invoke_context.transaction_context;
+                let caller = *transaction_context
+                    .get_current_instruction_context()
+                    .unwrap()
+                    .get_program_key(transaction_context)
+                    .unwrap();
+                transaction_context.set_return_data(caller, data.to_vec()).unwrap();
+            }
+            None => self.previous.sol_set_return_data(data),
+        }
+    }
+}